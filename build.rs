@@ -1,67 +1,306 @@
 //! Build script for compiling kernels to SPIR-V and CUDA PTX
+//!
+//! Every entry point that gets built is recorded in `$OUT_DIR/kernel_manifest.rs`,
+//! which `src/kernel.rs` includes. The manifest maps each entry point to its
+//! kind, workgroup size and artifact, along with the bindings,
+//! push-constant size and params source reflected from the SPIR-V.
+//!
+//! The [`shared::WORKGROUP_SIZE`] entry point of each SPIR-V module has its
+//! literal workgroup size rewritten into a `LocalSizeId` spec constant, so
+//! runners can specialize it to sizes no entry point was built for.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+#[path = "src/reflect.rs"]
+#[allow(dead_code)]
+mod reflect;
+
+/// Block sizes the CUDA kernels may be launched with; SPIR-V sizes come from the entry points
+#[cfg(all(feature = "cuda", not(target_os = "macos")))]
+const CUDA_BLOCK_SIZES: [u32; 4] = [64, 128, 256, 512];
+
+/// A kernel variant to list in the manifest
+#[cfg_attr(
+    not(any(feature = "vulkan", feature = "wgpu", feature = "cuda")),
+    allow(dead_code)
+)]
+struct Variant {
+    /// `KernelKind` variant name
+    kind: &'static str,
+    workgroup_size: u32,
+    entry_point: String,
+    /// Index into the artifacts of the variant's kind
+    artifact: usize,
+    /// `SortOrder` variant name compiled into the entry point
+    sort_order: Option<&'static str>,
+    /// `ParamsSource` variant name
+    params: &'static str,
+    bindings: Vec<reflect::BindingLayout>,
+    push_constant_size: u32,
+    spec_constants: Vec<reflect::SpecConstant>,
+    /// Whether the workgroup width is the [`shared::WORKGROUP_SIZE_SPEC_ID`] constant
+    workgroup_size_constant: bool,
+}
+
+/// Artifacts and variants of one kernel kind (SPIR-V or PTX)
+#[derive(Default)]
+struct Kernels {
+    artifacts: Vec<PathBuf>,
+    variants: Vec<Variant>,
+}
+
+impl Kernels {
+    #[cfg_attr(
+        not(any(feature = "vulkan", feature = "wgpu", feature = "cuda")),
+        allow(dead_code)
+    )]
+    fn add_artifact(&mut self, path: &Path) -> usize {
+        self.artifacts.push(path.to_path_buf());
+        self.artifacts.len() - 1
+    }
+
+    /// Generated statics for the artifacts and the variant table named `table`
+    fn write(&self, out: &mut String, table: &str, kind: &str) {
+        for (index, path) in self.artifacts.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "static {table}_ARTIFACT_{index}: &[u8] = include_bytes!({:?});",
+                path.display().to_string()
+            );
+        }
+        let _ = writeln!(out, "\n/// {kind} entry points built into this binary");
+        let _ = writeln!(out, "pub static {table}: &[KernelVariant] = &[");
+        for variant in &self.variants {
+            let bindings: Vec<String> = variant.bindings.iter().map(binding_literal).collect();
+            let spec_constants: Vec<String> = variant
+                .spec_constants
+                .iter()
+                .map(|constant| {
+                    format!(
+                        "crate::reflect::SpecConstant {{ id: {}, default: {} }}",
+                        constant.id, constant.default
+                    )
+                })
+                .collect();
+            let sort_order = match variant.sort_order {
+                Some(order) => format!("Some(SortOrder::{order})"),
+                None => "None".to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "    KernelVariant {{ kind: KernelKind::{}, workgroup_size: {}, sort_order: {sort_order}, params: ParamsSource::{}, entry_point: {:?}, artifact: {table}_ARTIFACT_{}, bindings: &[{}], push_constant_size: {}, spec_constants: &[{}], workgroup_size_constant: {} }},",
+                variant.kind,
+                variant.workgroup_size,
+                variant.params,
+                variant.entry_point,
+                variant.artifact,
+                bindings.join(", "),
+                variant.push_constant_size,
+                spec_constants.join(", "),
+                variant.workgroup_size_constant
+            );
+        }
+        let _ = writeln!(out, "];\n");
+    }
+}
+
+/// Rust expression for a binding in the generated manifest
+fn binding_literal(binding: &reflect::BindingLayout) -> String {
+    let ty = match binding.ty {
+        reflect::BindingType::StorageBuffer { read_only } => {
+            format!("crate::reflect::BindingType::StorageBuffer {{ read_only: {read_only} }}")
+        }
+        reflect::BindingType::UniformBuffer => "crate::reflect::BindingType::UniformBuffer".into(),
+    };
+    format!(
+        "crate::reflect::BindingLayout {{ set: {}, binding: {}, ty: {ty} }}",
+        binding.set, binding.binding
+    )
+}
+
+/// Parse the kind and compiled-in sort order from an entry point named
+/// `<kind>_kernel[_uniform][_wg<size>][_ascending|_descending]`
+#[cfg_attr(
+    not(any(feature = "vulkan", feature = "wgpu", feature = "cuda")),
+    allow(dead_code)
+)]
+fn parse_entry_point(name: &str) -> (&'static str, Option<&'static str>) {
+    let (kind, rest) = name
+        .split_once("_kernel")
+        .unwrap_or_else(|| panic!("entry point {name} is not named <kind>_kernel"));
+    let kind = match kind {
+        "bitonic" => "Bitonic",
+        _ => panic!("entry point {name} has no KernelKind; add `{kind}` to src/kernel.rs"),
+    };
+    let sort_order = if rest.ends_with("_ascending") {
+        Some("Ascending")
+    } else if rest.ends_with("_descending") {
+        Some("Descending")
+    } else {
+        None
+    };
+    (kind, sort_order)
+}
+
+/// `ParamsSource` of a reflected entry point: push constants if it reads
+/// any, otherwise the uniform buffer holding its params
+#[cfg_attr(not(any(feature = "vulkan", feature = "wgpu")), allow(dead_code))]
+fn params_source(entry_point: &reflect::EntryPointInfo) -> &'static str {
+    if entry_point.push_constants.is_some() {
+        "PushConstants"
+    } else if !entry_point.uniform_blocks.is_empty() {
+        "UniformBuffer"
+    } else {
+        panic!(
+            "{} reads neither push constants nor a uniform buffer",
+            entry_point.name
+        )
+    }
+}
 
 fn main() {
     // Only build kernels when the appropriate features are enabled
     #[cfg(any(feature = "vulkan", feature = "wgpu"))]
-    build_spirv_kernel();
+    let spirv = build_spirv_kernels();
+    #[cfg(not(any(feature = "vulkan", feature = "wgpu")))]
+    let spirv = Kernels::default();
 
-    #[cfg(feature = "cuda")]
-    {
-        #[cfg(target_os = "macos")]
-        panic!("CUDA is not supported on macOS. CUDA requires NVIDIA GPUs and is only available on Linux and Windows");
+    #[cfg(all(feature = "cuda", target_os = "macos"))]
+    panic!("CUDA is not supported on macOS. CUDA requires NVIDIA GPUs and is only available on Linux and Windows");
 
-        #[cfg(not(target_os = "macos"))]
-        build_cuda_kernel();
-    }
+    #[cfg(all(feature = "cuda", not(target_os = "macos")))]
+    let ptx = build_cuda_kernel();
+    #[cfg(not(all(feature = "cuda", not(target_os = "macos"))))]
+    let ptx = Kernels::default();
+
+    let mut manifest =
+        String::from("// Generated by build.rs: kernel variants and their artifacts\n\n");
+    spirv.write(&mut manifest, "SPIRV_KERNELS", "SPIR-V");
+    ptx.write(&mut manifest, "PTX_KERNELS", "PTX");
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("kernel_manifest.rs"), manifest).unwrap();
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/reflect.rs");
 }
 
 #[cfg(any(feature = "vulkan", feature = "wgpu"))]
-fn build_spirv_kernel() {
+fn build_spirv_kernels() -> Kernels {
+    use shared::{WORKGROUP_SIZE, WORKGROUP_SIZE_SPEC_ID};
     use spirv_builder::SpirvBuilder;
-    use std::path::PathBuf;
 
+    let mut kernels = Kernels::default();
     let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
     let crate_path = PathBuf::from(manifest_dir).join("kernel");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
 
-    let result = SpirvBuilder::new(crate_path, "spirv-unknown-vulkan1.2")
-        .print_metadata(spirv_builder::MetadataPrintout::Full)
-        .build()
-        .unwrap();
-
-    // Export the kernel path for the runtime to use
-    println!(
-        "cargo:rustc-env=BITONIC_KERNEL_SPV_PATH={}",
-        result.module.unwrap_single().display()
-    );
-
-    // Use the first entry point
-    println!(
-        "cargo:rustc-env=BITONIC_KERNEL_SPV_ENTRY={}",
-        result.entry_points.first().unwrap()
-    );
+    // Devices without push constants reject any module declaring them, so
+    // the uniform-buffer entry points get a module of their own. Only 32-bit
+    // keys are built, as `SortableKey` maps every key type to a `u32`.
+    for feature in [None, Some("uniform-params")] {
+        let mut builder = SpirvBuilder::new(&crate_path, "spirv-unknown-vulkan1.2")
+            .print_metadata(spirv_builder::MetadataPrintout::Full);
+        if let Some(feature) = feature {
+            builder = builder.shader_crate_features([feature.to_string()]);
+        }
+        let result = builder.build().unwrap();
+
+        // Every build writes the same file, so each module is copied out
+        // before the next build replaces it
+        let path = out_dir.join(format!("kernel-{}.spv", feature.unwrap_or("default")));
+        let mut words: Vec<u32> = std::fs::read(result.module.unwrap_single())
+            .unwrap()
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+
+        // A module can only have one constant per SpecId, so only its default
+        // size becomes tunable; the other sizes keep their literal
+        let suffix = format!("_wg{WORKGROUP_SIZE}");
+        let tunable: Vec<String> = reflect::reflect(&words)
+            .unwrap_or_else(|err| panic!("failed to reflect {}: {err}", path.display()))
+            .into_iter()
+            .map(|entry_point| entry_point.name)
+            .filter(|name| name.ends_with(&suffix))
+            .collect();
+        if let [name] = tunable.as_slice() {
+            words = reflect::tunable_workgroup_size(&words, name, WORKGROUP_SIZE_SPEC_ID)
+                .unwrap_or_else(|err| panic!("failed to make {name} tunable: {err}"));
+        }
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        std::fs::write(&path, bytes).unwrap();
+        let artifact = kernels.add_artifact(&path);
+
+        // Every entry point of the module is listed, laid out as reflected
+        let entry_points = reflect::reflect(&words)
+            .unwrap_or_else(|err| panic!("failed to reflect {}: {err}", path.display()));
+        for entry_point in entry_points {
+            let (kind, sort_order) = parse_entry_point(&entry_point.name);
+            let workgroup_size = match entry_point.workgroup_size {
+                Some([size, 1, 1]) => size,
+                _ => panic!("{} has no one-dimensional workgroup size", entry_point.name),
+            };
+            let params = params_source(&entry_point);
+            kernels.variants.push(Variant {
+                kind,
+                workgroup_size,
+                sort_order,
+                params,
+                entry_point: entry_point.name,
+                artifact,
+                bindings: entry_point.bindings,
+                push_constant_size: entry_point.push_constants.map_or(0, |block| block.size),
+                spec_constants: entry_point.spec_constants,
+                workgroup_size_constant: entry_point.workgroup_size_spec_id
+                    == Some(WORKGROUP_SIZE_SPEC_ID),
+            });
+        }
+    }
+    kernels
 }
 
 #[cfg(all(feature = "cuda", not(target_os = "macos")))]
-fn build_cuda_kernel() {
+fn build_cuda_kernel() -> Kernels {
     use cuda_builder::CudaBuilder;
-    use std::path::PathBuf;
 
+    let mut kernels = Kernels::default();
     let out_dir = std::env::var("OUT_DIR").unwrap();
     let out_path = PathBuf::from(&out_dir);
 
     let ptx_path = out_path.join("kernel.ptx");
 
     println!("cargo:rerun-if-changed=shared/src/lib.rs");
-    println!("cargo:rerun-if-changed=build.rs");
 
     CudaBuilder::new("kernel")
         .copy_to(&ptx_path)
         .build()
         .expect("Failed to build CUDA kernel");
 
-    // Export the PTX path as an environment variable for embedding
-    println!(
-        "cargo:rustc-env=BITONIC_KERNEL_PTX_PATH={}",
-        ptx_path.display()
-    );
+    // One PTX module holds every entry point, and any block size can be launched
+    let artifact = kernels.add_artifact(&ptx_path);
+    // A kernel reading the order from its params and one per compiled-in
+    // order, the CUDA counterpart of the SPIR-V specialization constant
+    for entry_point in [
+        "bitonic_kernel",
+        "bitonic_kernel_ascending",
+        "bitonic_kernel_descending",
+    ] {
+        let (kind, sort_order) = parse_entry_point(entry_point);
+        for workgroup_size in CUDA_BLOCK_SIZES {
+            kernels.variants.push(Variant {
+                kind,
+                workgroup_size,
+                sort_order,
+                params: "KernelArgument",
+                entry_point: entry_point.to_string(),
+                artifact,
+                bindings: Vec::new(),
+                push_constant_size: 0,
+                spec_constants: Vec::new(),
+                workgroup_size_constant: false,
+            });
+        }
+    }
+    kernels
 }
@@ -7,7 +7,7 @@
 #![cfg_attr(target_os = "cuda", no_std)]
 
 #[cfg(any(target_arch = "spirv", target_os = "cuda"))]
-use shared::BitonicParams;
+use shared::{BitonicParams, SORT_ORDER_DYNAMIC};
 use shared::{Pass, SortOrder, Stage, ThreadId};
 
 #[cfg(target_arch = "spirv")]
@@ -137,37 +137,106 @@ pub fn bitonic_sort_step(
     }
 }
 
-/// GPU entry point for Vulkan/SPIR-V
-#[cfg(target_arch = "spirv")]
-#[spirv(compute(threads(256)))]
-pub fn bitonic_kernel(
-    #[spirv(global_invocation_id)] gid: UVec3,
-    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] data: &mut [u32],
-    #[spirv(push_constant)] params: &BitonicParams,
-) {
-    let thread_id = ThreadId::new(gid.x);
-
-    // Convert u32 to SortOrder
-    let sort_order = if params.sort_order == 0 {
+/// Order to sort in: `specialized` if it names one, otherwise the order in the params
+#[cfg(any(target_arch = "spirv", target_os = "cuda"))]
+#[inline]
+fn resolve_sort_order(specialized: u32, params_order: u32) -> SortOrder {
+    let order = if specialized == SORT_ORDER_DYNAMIC {
+        params_order
+    } else {
+        specialized
+    };
+    if order == 0 {
         SortOrder::Ascending
     } else {
         SortOrder::Descending
-    };
+    }
+}
 
+/// Sort step of the thread at `gid`, shared by every SPIR-V entry point
+#[cfg(target_arch = "spirv")]
+#[inline]
+fn spirv_sort_step(gid: UVec3, data: &mut [u32], params: &BitonicParams, sort_order: u32) {
     bitonic_sort_step(
-        thread_id,
+        ThreadId::new(gid.x),
         data,
         params.stage,
         params.pass_of_stage,
         params.num_elements,
-        sort_order,
+        resolve_sort_order(sort_order, params.sort_order),
     );
 }
 
-/// GPU entry point for CUDA
+/// Declare one SPIR-V entry point per workgroup size
+///
+/// rust-gpu only accepts literal workgroup sizes, so each size is its own
+/// entry point (`bitonic_kernel[_uniform]_wg<size>`). `build.rs` reads the
+/// size back from the module, then turns the literal of the
+/// [`shared::WORKGROUP_SIZE`] entry point into the
+/// [`shared::WORKGROUP_SIZE_SPEC_ID`] constant so other sizes can be
+/// specialized from it.
+///
+/// The sort order is a specialization constant: `id` is
+/// [`shared::SORT_ORDER_SPEC_ID`] and the default [`SORT_ORDER_DYNAMIC`],
+/// written out because attributes take literals. The `uniform` form reads the
+/// params from a uniform buffer at binding 1 instead of push constants, for
+/// devices that have none.
+#[cfg(target_arch = "spirv")]
+macro_rules! spirv_entry_points {
+    (uniform $($name:ident: $threads:literal),* $(,)?) => {
+        $(
+            #[spirv(compute(threads($threads)))]
+            pub fn $name(
+                #[spirv(global_invocation_id)] gid: UVec3,
+                #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] data: &mut [u32],
+                #[spirv(uniform, descriptor_set = 0, binding = 1)] params: &BitonicParams,
+                #[spirv(spec_constant(id = 0, default = 2))] sort_order: u32,
+            ) {
+                spirv_sort_step(gid, data, params, sort_order);
+            }
+        )*
+    };
+    ($($name:ident: $threads:literal),* $(,)?) => {
+        $(
+            #[spirv(compute(threads($threads)))]
+            pub fn $name(
+                #[spirv(global_invocation_id)] gid: UVec3,
+                #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] data: &mut [u32],
+                #[spirv(push_constant)] params: &BitonicParams,
+                #[spirv(spec_constant(id = 0, default = 2))] sort_order: u32,
+            ) {
+                spirv_sort_step(gid, data, params, sort_order);
+            }
+        )*
+    };
+}
+
+// GPU entry points for Vulkan/SPIR-V
+#[cfg(all(target_arch = "spirv", not(feature = "uniform-params")))]
+spirv_entry_points!(
+    bitonic_kernel_wg64: 64,
+    bitonic_kernel_wg128: 128,
+    bitonic_kernel_wg256: 256,
+    bitonic_kernel_wg512: 512,
+);
+
+// For WebGPU and other devices without push constants. wgpu rejects a whole
+// module that declares push constants on such devices, so these get their own
+// module with the `uniform-params` feature.
+#[cfg(all(target_arch = "spirv", feature = "uniform-params"))]
+spirv_entry_points!(uniform
+    bitonic_kernel_uniform_wg64: 64,
+    bitonic_kernel_uniform_wg128: 128,
+    bitonic_kernel_uniform_wg256: 256,
+    bitonic_kernel_uniform_wg512: 512,
+);
+
+/// Sort step of the current CUDA thread; the block size is chosen at launch
+///
+/// `ORDER` plays the part of the SPIR-V sort-order specialization constant.
 #[cfg(target_os = "cuda")]
-#[kernel]
-pub unsafe fn bitonic_kernel(data: *mut u32, params: BitonicParams) {
+#[inline]
+unsafe fn cuda_sort_step<const ORDER: u32>(data: *mut u32, params: BitonicParams) {
     let thread_id =
         ThreadId::new(thread::thread_idx_x() + thread::block_idx_x() * thread::block_dim_x());
 
@@ -175,19 +244,36 @@ pub unsafe fn bitonic_kernel(data: *mut u32, params: BitonicParams) {
     // Safety: The caller must ensure the pointer is valid for num_elements
     let data_slice = core::slice::from_raw_parts_mut(data, params.num_elements as usize);
 
-    // Convert u32 to SortOrder
-    let sort_order = if params.sort_order == 0 {
-        SortOrder::Ascending
-    } else {
-        SortOrder::Descending
-    };
-
     bitonic_sort_step(
         thread_id,
         data_slice,
         params.stage,
         params.pass_of_stage,
         params.num_elements,
-        sort_order,
+        resolve_sort_order(ORDER, params.sort_order),
     );
 }
+
+/// Declare CUDA entry points, each with its compiled-in sort order
+///
+/// `build.rs` reads the order from the name
+/// (`bitonic_kernel[_ascending|_descending]`).
+#[cfg(target_os = "cuda")]
+macro_rules! cuda_entry_points {
+    ($($name:ident: $order:expr;)*) => {
+        $(
+            #[kernel]
+            pub unsafe fn $name(data: *mut u32, params: BitonicParams) {
+                cuda_sort_step::<{ $order }>(data, params);
+            }
+        )*
+    };
+}
+
+// GPU entry points for CUDA
+#[cfg(target_os = "cuda")]
+cuda_entry_points! {
+    bitonic_kernel: SORT_ORDER_DYNAMIC;
+    bitonic_kernel_ascending: SortOrder::Ascending as u32;
+    bitonic_kernel_descending: SortOrder::Descending as u32;
+}
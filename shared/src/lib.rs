@@ -5,14 +5,32 @@
 use bytemuck::{Pod, Zeroable};
 use core::fmt::{self, Display};
 
-/// Workgroup size for compute shaders
-/// IMPORTANT: This must be kept in sync with the literal value in kernel/src/lib.rs
+/// Preferred workgroup size for compute shaders
+///
+/// The kernel is built for several workgroup sizes; runners use this one when
+/// the device allows it.
 pub const WORKGROUP_SIZE: u32 = 256;
 
 /// CUDA-specific alias for WORKGROUP_SIZE (CUDA uses "block" terminology)
 #[cfg(feature = "cuda")]
 pub const BLOCK_SIZE: u32 = WORKGROUP_SIZE;
 
+/// `SpecId` of the SPIR-V specialization constant holding the sort order
+///
+/// Runners specialize one pipeline per [`SortOrder`] through it, so the order
+/// is compiled into the kernel instead of read from every dispatch's params.
+pub const SORT_ORDER_SPEC_ID: u32 = 0;
+
+/// `SpecId` of the SPIR-V specialization constant holding the workgroup width
+///
+/// `build.rs` declares the [`WORKGROUP_SIZE`] entry point of each module with
+/// a `LocalSizeId` read from it, so runners can pick sizes no entry point was
+/// built for.
+pub const WORKGROUP_SIZE_SPEC_ID: u32 = 1;
+
+/// Sort-order constant value that reads the order from [`BitonicParams::sort_order`]
+pub const SORT_ORDER_DYNAMIC: u32 = 2;
+
 /// The constant used in the computation (index * 2 + COMPUTE_CONSTANT)
 pub const COMPUTE_CONSTANT: u32 = 42;
 
@@ -89,7 +107,7 @@ impl Pass {
 }
 
 /// Sort order
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[repr(u32)]
 pub enum SortOrder {
     Ascending = 0,
@@ -278,4 +296,3 @@ impl CompareDirection {
         matches!(self, CompareDirection::Up)
     }
 }
-
@@ -0,0 +1,111 @@
+//! Capability reporting for sorting backends
+
+use crate::error::{ChimeraError, Result};
+
+/// Largest padded element count a single bitonic sort can address.
+///
+/// `BitonicParams::num_elements` is a `u32` and the padded size is a power of
+/// two, so `2^31` is the hard ceiling regardless of the device.
+pub const MAX_BITONIC_ELEMENTS: u64 = 1 << 31;
+
+/// What a runner (and the device behind it) supports
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Maximum number of elements after padding to a power of two
+    pub max_elements: u64,
+    /// Whether the runner can sort 64-bit keys
+    ///
+    /// `false` for every runner, as only `u32` kernels are built.
+    pub supports_64bit_keys: bool,
+    /// Maximum push constant block size in bytes (0 if push constants are unavailable)
+    pub max_push_constant_size: u32,
+    /// Whether subgroup (warp/wave) operations are available in compute shaders
+    pub subgroup_operations: bool,
+    /// Whether GPU timestamp queries can be recorded
+    pub timestamp_queries: bool,
+    /// Whether the adapter is a software (CPU) implementation
+    pub is_software: bool,
+}
+
+impl Capabilities {
+    /// Round a raw device element limit down to a power of two and clamp it to
+    /// [`MAX_BITONIC_ELEMENTS`]
+    pub fn clamp_max_elements(limit: u64) -> u64 {
+        let limit = limit.min(MAX_BITONIC_ELEMENTS);
+        if limit == 0 {
+            0
+        } else {
+            1 << (63 - limit.leading_zeros())
+        }
+    }
+
+    /// Check that a sort of `len` keys of `key_size` bytes can run on this runner
+    pub fn check_sort(&self, len: usize, key_size: usize) -> Result<()> {
+        let padded = (len as u64).next_power_of_two();
+        if padded > self.max_elements {
+            return Err(ChimeraError::TooManyElements {
+                requested: len,
+                max: self.max_elements,
+            });
+        }
+
+        if key_size > 4 && !self.supports_64bit_keys {
+            return Err(ChimeraError::Unsupported(format!(
+                "{}-bit keys are not supported by this device",
+                key_size * 8
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, MAX_BITONIC_ELEMENTS};
+    use crate::error::ChimeraError;
+
+    fn caps(max_elements: u64, supports_64bit_keys: bool) -> Capabilities {
+        Capabilities {
+            max_elements,
+            supports_64bit_keys,
+            max_push_constant_size: 128,
+            subgroup_operations: false,
+            timestamp_queries: false,
+            is_software: false,
+        }
+    }
+
+    #[test]
+    fn test_clamp_max_elements() {
+        assert_eq!(Capabilities::clamp_max_elements(0), 0);
+        assert_eq!(Capabilities::clamp_max_elements(1000), 512);
+        assert_eq!(Capabilities::clamp_max_elements(1024), 1024);
+        assert_eq!(
+            Capabilities::clamp_max_elements(u64::MAX),
+            MAX_BITONIC_ELEMENTS
+        );
+    }
+
+    #[test]
+    fn test_check_sort_rejects_oversized_input() {
+        let caps = caps(1024, false);
+        assert!(caps.check_sort(1024, 4).is_ok());
+        assert!(matches!(
+            caps.check_sort(1025, 4),
+            Err(ChimeraError::TooManyElements {
+                requested: 1025,
+                max: 1024
+            })
+        ));
+    }
+
+    #[test]
+    fn test_check_sort_rejects_64bit_keys() {
+        assert!(matches!(
+            caps(1024, false).check_sort(16, 8),
+            Err(ChimeraError::Unsupported(_))
+        ));
+        assert!(caps(1024, true).check_sort(16, 8).is_ok());
+    }
+}
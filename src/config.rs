@@ -0,0 +1,595 @@
+//! Runner configuration and device selection
+
+use crate::kernel::KernelSource;
+use std::fmt::{self, Display};
+use std::ops::{BitOr, BitOrAssign};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Set of compute backends a runner is allowed to use
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Backends(u32);
+
+impl Backends {
+    pub const NONE: Self = Self(0);
+    pub const VULKAN: Self = Self(1 << 0);
+    pub const METAL: Self = Self(1 << 1);
+    pub const DX12: Self = Self(1 << 2);
+    pub const GL: Self = Self(1 << 3);
+    pub const BROWSER_WEBGPU: Self = Self(1 << 4);
+    pub const CUDA: Self = Self(1 << 5);
+    pub const ALL: Self = Self(0b11_1111);
+
+    /// Whether every backend in `other` is also in `self`
+    #[inline]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl Default for Backends {
+    fn default() -> Self {
+        #[cfg(all(target_os = "macos", feature = "vulkan"))]
+        return Backends::VULKAN;
+
+        #[cfg(all(target_os = "macos", not(feature = "vulkan")))]
+        return Backends::METAL;
+
+        #[cfg(not(target_os = "macos"))]
+        Backends::ALL
+    }
+}
+
+impl BitOr for Backends {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Backends {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+#[cfg(feature = "wgpu")]
+impl From<Backends> for wgpu::Backends {
+    fn from(backends: Backends) -> Self {
+        let mut out = wgpu::Backends::empty();
+        if backends.contains(Backends::VULKAN) {
+            out |= wgpu::Backends::VULKAN;
+        }
+        if backends.contains(Backends::METAL) {
+            out |= wgpu::Backends::METAL;
+        }
+        if backends.contains(Backends::DX12) {
+            out |= wgpu::Backends::DX12;
+        }
+        if backends.contains(Backends::GL) {
+            out |= wgpu::Backends::GL;
+        }
+        if backends.contains(Backends::BROWSER_WEBGPU) {
+            out |= wgpu::Backends::BROWSER_WEBGPU;
+        }
+        out
+    }
+}
+
+/// Preference used when no explicit device is requested
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PowerPreference {
+    /// Take the first suitable device
+    None,
+    /// Prefer integrated GPUs
+    LowPower,
+    /// Prefer discrete GPUs
+    #[default]
+    HighPerformance,
+}
+
+#[cfg(feature = "wgpu")]
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(preference: PowerPreference) -> Self {
+        match preference {
+            PowerPreference::None => wgpu::PowerPreference::None,
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+/// Where the ash runner keeps sort data while the kernel runs
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MemoryStrategy {
+    /// Device-local memory with staging on discrete GPUs, host-visible memory on
+    /// integrated and other unified-memory devices
+    #[default]
+    Auto,
+    /// Storage buffer in host-visible memory that the host writes and reads directly
+    HostVisible,
+    /// Storage buffer in device-local memory, filled and read back through a
+    /// staging buffer; falls back to host-visible memory on unified-memory devices
+    DeviceLocal,
+}
+
+impl Display for MemoryStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryStrategy::Auto => write!(f, "auto"),
+            MemoryStrategy::HostVisible => write!(f, "host-visible"),
+            MemoryStrategy::DeviceLocal => write!(f, "device-local (staged)"),
+        }
+    }
+}
+
+/// Which device a runner should be created on
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// Let the runner pick based on [`PowerPreference`]
+    #[default]
+    Auto,
+    /// Index into the list returned by the runner's `enumerate_devices`
+    Index(usize),
+    /// First device whose name contains this string (case-insensitive)
+    Name(String),
+}
+
+impl DeviceSelector {
+    /// Whether a device at `index` named `name` satisfies an explicit selector
+    pub fn matches(&self, index: usize, name: &str) -> bool {
+        match self {
+            DeviceSelector::Auto => true,
+            DeviceSelector::Index(i) => *i == index,
+            DeviceSelector::Name(filter) => name.to_lowercase().contains(&filter.to_lowercase()),
+        }
+    }
+}
+
+/// Kind of physical device
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceType {
+    DiscreteGpu,
+    IntegratedGpu,
+    VirtualGpu,
+    Cpu,
+    Other,
+}
+
+impl DeviceType {
+    /// Ranking used for automatic selection (lower is better)
+    pub fn rank(self, preference: PowerPreference) -> u32 {
+        match (preference, self) {
+            (PowerPreference::None, _) => 0,
+            (PowerPreference::HighPerformance, DeviceType::DiscreteGpu) => 0,
+            (PowerPreference::HighPerformance, DeviceType::IntegratedGpu) => 1,
+            (PowerPreference::LowPower, DeviceType::IntegratedGpu) => 0,
+            (PowerPreference::LowPower, DeviceType::DiscreteGpu) => 1,
+            (_, DeviceType::VirtualGpu) => 2,
+            (_, DeviceType::Other) => 3,
+            (_, DeviceType::Cpu) => 4,
+        }
+    }
+}
+
+impl Display for DeviceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceType::DiscreteGpu => write!(f, "discrete GPU"),
+            DeviceType::IntegratedGpu => write!(f, "integrated GPU"),
+            DeviceType::VirtualGpu => write!(f, "virtual GPU"),
+            DeviceType::Cpu => write!(f, "CPU"),
+            DeviceType::Other => write!(f, "other"),
+        }
+    }
+}
+
+#[cfg(feature = "wgpu")]
+impl From<wgpu::DeviceType> for DeviceType {
+    fn from(device_type: wgpu::DeviceType) -> Self {
+        match device_type {
+            wgpu::DeviceType::DiscreteGpu => DeviceType::DiscreteGpu,
+            wgpu::DeviceType::IntegratedGpu => DeviceType::IntegratedGpu,
+            wgpu::DeviceType::VirtualGpu => DeviceType::VirtualGpu,
+            wgpu::DeviceType::Cpu => DeviceType::Cpu,
+            wgpu::DeviceType::Other => DeviceType::Other,
+        }
+    }
+}
+
+#[cfg(feature = "ash")]
+impl From<ash::vk::PhysicalDeviceType> for DeviceType {
+    fn from(device_type: ash::vk::PhysicalDeviceType) -> Self {
+        use ash::vk::PhysicalDeviceType;
+        match device_type {
+            PhysicalDeviceType::DISCRETE_GPU => DeviceType::DiscreteGpu,
+            PhysicalDeviceType::INTEGRATED_GPU => DeviceType::IntegratedGpu,
+            PhysicalDeviceType::VIRTUAL_GPU => DeviceType::VirtualGpu,
+            PhysicalDeviceType::CPU => DeviceType::Cpu,
+            _ => DeviceType::Other,
+        }
+    }
+}
+
+/// A device a runner can be created on
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    /// Host API that enumerated the device ("wgpu", "ash", "cust")
+    pub host: &'static str,
+    /// Backend the device is driven through ("Vulkan", "Metal", "CUDA", ...)
+    pub backend: &'static str,
+    /// Index to pass to [`RunnerConfig::device_index`]
+    pub index: usize,
+    pub name: String,
+    pub device_type: DeviceType,
+    pub driver: Option<String>,
+}
+
+impl Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}:{}] {} ({}, {})",
+            self.host, self.index, self.name, self.backend, self.device_type
+        )?;
+        if let Some(driver) = self.driver.as_deref().filter(|d| !d.is_empty()) {
+            write!(f, " - {driver}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Severity of a message from a validation layer or driver
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for DebugSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DebugSeverity::Verbose => "verbose",
+            DebugSeverity::Info => "info",
+            DebugSeverity::Warning => "warning",
+            DebugSeverity::Error => "error",
+        })
+    }
+}
+
+/// A message reported while [`RunnerConfig::debug`] is enabled
+#[derive(Clone, Debug)]
+pub struct DebugMessage {
+    pub severity: DebugSeverity,
+    /// `general`, `validation` or `performance`
+    pub kind: &'static str,
+    /// Validation message identifier, e.g. `VUID-vkCmdDispatch-None-02697`
+    pub id: Option<String>,
+    pub message: String,
+}
+
+impl Display for DebugMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{} {}]", self.kind, self.severity)?;
+        if let Some(id) = &self.id {
+            write!(f, " {id}:")?;
+        }
+        write!(f, " {}", self.message)
+    }
+}
+
+/// Destination for debug messages
+#[derive(Clone)]
+pub struct DebugSink(Arc<dyn Fn(&DebugMessage) + Send + Sync>);
+
+impl DebugSink {
+    pub fn new(sink: impl Fn(&DebugMessage) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+
+    /// Print messages to stderr
+    pub fn stderr() -> Self {
+        Self::new(|message| eprintln!("{message}"))
+    }
+
+    pub fn emit(&self, message: &DebugMessage) {
+        (self.0)(message)
+    }
+}
+
+impl Default for DebugSink {
+    fn default() -> Self {
+        Self::stderr()
+    }
+}
+
+impl fmt::Debug for DebugSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DebugSink(..)")
+    }
+}
+
+/// Configuration honoured by all GPU runners
+#[derive(Clone, Debug)]
+pub struct RunnerConfig {
+    /// Backends the runner may use
+    pub backends: Backends,
+    /// Explicit device selection
+    pub device: DeviceSelector,
+    /// Preference used when `device` is [`DeviceSelector::Auto`]
+    pub power_preference: PowerPreference,
+    /// Whether software (CPU) adapters may be selected
+    pub allow_software_adapter: bool,
+    /// Only select software (CPU) adapters such as lavapipe or SwiftShader
+    ///
+    /// Implies `allow_software_adapter`.
+    pub force_software_adapter: bool,
+    /// Memory placement of sort data (ash only)
+    pub memory_strategy: MemoryStrategy,
+    /// Enable validation layers and debug messages where available (ash only)
+    pub debug: bool,
+    /// Receives debug messages when `debug` is enabled
+    pub debug_sink: DebugSink,
+    /// Directory for persistent pipeline caches (wgpu on Vulkan, ash)
+    pub pipeline_cache_dir: Option<PathBuf>,
+    /// Run staging copies on a transfer-only queue family when one exists (ash only)
+    pub dedicated_transfer_queue: bool,
+    /// Request timestamp queries for `SortRunner::sort_profiled` (wgpu only; ash and CUDA always can)
+    pub profiling: bool,
+    /// SPIR-V kernel of the wgpu and ash runners
+    pub spirv_kernel: KernelSource,
+    /// PTX kernel of the CUDA runner
+    pub ptx_kernel: KernelSource,
+    /// Workgroup size of the kernel variant to use, instead of choosing one from device limits
+    pub workgroup_size: Option<u32>,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        Self {
+            backends: Backends::default(),
+            device: DeviceSelector::Auto,
+            power_preference: PowerPreference::HighPerformance,
+            allow_software_adapter: true,
+            force_software_adapter: false,
+            memory_strategy: MemoryStrategy::Auto,
+            debug: false,
+            debug_sink: DebugSink::stderr(),
+            pipeline_cache_dir: None,
+            dedicated_transfer_queue: false,
+            profiling: false,
+            spirv_kernel: KernelSource::Embedded,
+            ptx_kernel: KernelSource::Embedded,
+            workgroup_size: None,
+        }
+    }
+}
+
+impl RunnerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn backends(mut self, backends: Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    pub fn device_index(mut self, index: usize) -> Self {
+        self.device = DeviceSelector::Index(index);
+        self
+    }
+
+    pub fn device_name(mut self, name: impl Into<String>) -> Self {
+        self.device = DeviceSelector::Name(name.into());
+        self
+    }
+
+    pub fn power_preference(mut self, preference: PowerPreference) -> Self {
+        self.power_preference = preference;
+        self
+    }
+
+    /// Let software adapters be selected; disallowing them also stops forcing them
+    pub fn allow_software_adapter(mut self, allow: bool) -> Self {
+        self.allow_software_adapter = allow;
+        if !allow {
+            self.force_software_adapter = false;
+        }
+        self
+    }
+
+    /// Restrict selection to software adapters, e.g. to run on GPU-less CI
+    pub fn force_software_adapter(mut self, force: bool) -> Self {
+        self.force_software_adapter = force;
+        if force {
+            self.allow_software_adapter = true;
+        }
+        self
+    }
+
+    pub fn memory_strategy(mut self, strategy: MemoryStrategy) -> Self {
+        self.memory_strategy = strategy;
+        self
+    }
+
+    /// Enable `VK_LAYER_KHRONOS_validation` and `VK_EXT_debug_utils` when installed
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Route debug messages to `sink` instead of stderr
+    pub fn debug_sink(mut self, sink: DebugSink) -> Self {
+        self.debug_sink = sink;
+        self
+    }
+
+    /// Load compiled pipelines from `dir` and write them back when the runner is dropped
+    pub fn pipeline_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.pipeline_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Keep uploads and downloads off the compute queue where the device allows it
+    pub fn dedicated_transfer_queue(mut self, dedicated: bool) -> Self {
+        self.dedicated_transfer_queue = dedicated;
+        self
+    }
+
+    /// Enable the device features GPU timestamps need, where they must be requested up front
+    pub fn profiling(mut self, profiling: bool) -> Self {
+        self.profiling = profiling;
+        self
+    }
+
+    /// Build the wgpu and ash pipelines from `source` instead of the embedded SPIR-V
+    pub fn spirv_kernel(mut self, source: KernelSource) -> Self {
+        self.spirv_kernel = source;
+        self
+    }
+
+    /// Load the CUDA kernel from `source` instead of the embedded PTX
+    pub fn ptx_kernel(mut self, source: KernelSource) -> Self {
+        self.ptx_kernel = source;
+        self
+    }
+
+    /// Run the kernel with `size` invocations per workgroup
+    ///
+    /// Sizes no entry point was built for specialize the one whose workgroup
+    /// size is a constant.
+    pub fn workgroup_size(mut self, size: u32) -> Self {
+        self.workgroup_size = Some(size);
+        self
+    }
+
+    /// Pick the best device from `devices` according to this configuration
+    ///
+    /// Returns the position in `devices` of the selected entry.
+    pub fn select_device(&self, devices: &[DeviceInfo]) -> Option<usize> {
+        let allowed = |d: &&DeviceInfo| match d.device_type {
+            DeviceType::Cpu => self.allow_software_adapter || self.force_software_adapter,
+            _ => !self.force_software_adapter,
+        };
+
+        match &self.device {
+            DeviceSelector::Auto => devices
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| allowed(d))
+                .min_by_key(|(i, d)| (d.device_type.rank(self.power_preference), *i))
+                .map(|(i, _)| i),
+            selector => devices
+                .iter()
+                .position(|d| selector.matches(d.index, &d.name) && allowed(&d)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backends, DeviceInfo, DeviceType, PowerPreference, RunnerConfig};
+
+    fn devices() -> Vec<DeviceInfo> {
+        [
+            ("llvmpipe (LLVM 17.0.6, 256 bits)", DeviceType::Cpu),
+            ("Intel(R) UHD Graphics 770", DeviceType::IntegratedGpu),
+            ("NVIDIA GeForce RTX 4090", DeviceType::DiscreteGpu),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, device_type))| DeviceInfo {
+            host: "test",
+            backend: "Vulkan",
+            index,
+            name: name.to_string(),
+            device_type,
+            driver: None,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_select_by_power_preference() {
+        let devices = devices();
+        let config = RunnerConfig::new();
+        assert_eq!(config.select_device(&devices), Some(2));
+
+        let config = config.power_preference(PowerPreference::LowPower);
+        assert_eq!(config.select_device(&devices), Some(1));
+
+        let config = config.power_preference(PowerPreference::None);
+        assert_eq!(config.select_device(&devices), Some(0));
+    }
+
+    #[test]
+    fn test_select_by_index_and_name() {
+        let devices = devices();
+        assert_eq!(
+            RunnerConfig::new().device_index(1).select_device(&devices),
+            Some(1)
+        );
+        assert_eq!(
+            RunnerConfig::new()
+                .device_name("rtx")
+                .select_device(&devices),
+            Some(2)
+        );
+        assert_eq!(
+            RunnerConfig::new().device_index(7).select_device(&devices),
+            None
+        );
+    }
+
+    #[test]
+    fn test_software_adapter_filter() {
+        let devices = devices();
+        let config = RunnerConfig::new()
+            .allow_software_adapter(false)
+            .power_preference(PowerPreference::None);
+        assert_eq!(config.select_device(&devices), Some(1));
+        assert_eq!(config.device_index(0).select_device(&devices), None);
+    }
+
+    #[test]
+    fn test_force_software_adapter() {
+        let devices = devices();
+        let config = RunnerConfig::new()
+            .allow_software_adapter(false)
+            .force_software_adapter(true);
+        assert!(config.allow_software_adapter);
+        assert_eq!(config.select_device(&devices), Some(0));
+        assert_eq!(config.clone().device_index(2).select_device(&devices), None);
+
+        // Disallowing software adapters afterwards lifts the restriction
+        let config = config.allow_software_adapter(false);
+        assert!(!config.force_software_adapter);
+        assert_eq!(config.select_device(&devices), Some(2));
+
+        // Forcing wins over a field set by hand
+        let config = RunnerConfig {
+            allow_software_adapter: false,
+            force_software_adapter: true,
+            ..RunnerConfig::new()
+        };
+        assert_eq!(config.select_device(&devices), Some(0));
+    }
+
+    #[test]
+    fn test_backends_mask() {
+        let mask = Backends::VULKAN | Backends::CUDA;
+        assert!(mask.contains(Backends::VULKAN));
+        assert!(!mask.contains(Backends::METAL));
+        assert!(Backends::ALL.contains(mask));
+        assert!(Backends::NONE.is_empty());
+    }
+}
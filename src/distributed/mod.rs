@@ -0,0 +1,394 @@
+//! Sample sort across worker processes
+//!
+//! Every worker holds part of the dataset and a [`SortRunner`]. Worker
+//! [`COORDINATOR_RANK`] gathers a random sample of keys from all workers, picks
+//! splitters and broadcasts them. Workers then exchange buckets so that each one
+//! owns a contiguous key range, and sort that range locally. Concatenating the
+//! workers' results in rank order yields the globally sorted dataset.
+
+mod transport;
+
+pub use transport::{
+    ChannelTransport, TcpTransport, Transport, DEFAULT_MAX_FRAME_SIZE, DEFAULT_READ_TIMEOUT,
+};
+
+use crate::{
+    error::{ChimeraError, Result},
+    SortRunner,
+};
+use rand::Rng;
+use shared::{SortOrder, SortableKey};
+
+/// Rank of the worker that picks splitters and gathers results
+pub const COORDINATOR_RANK: usize = 0;
+
+/// Number of samples each worker contributes per worker in the world
+const DEFAULT_OVERSAMPLING: usize = 32;
+
+fn encode_keys(keys: &[u32]) -> Vec<u8> {
+    keys.iter().flat_map(|key| key.to_le_bytes()).collect()
+}
+
+fn decode_keys(bytes: &[u8]) -> Result<Vec<u32>> {
+    if bytes.len() % 4 != 0 {
+        return Err(ChimeraError::Transport(format!(
+            "key message of {} bytes is not a whole number of keys",
+            bytes.len()
+        )));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect())
+}
+
+/// One worker of a distributed sample sort
+pub struct DistributedSort<T: Transport, R: SortRunner> {
+    transport: T,
+    runner: R,
+    oversampling: usize,
+}
+
+impl<T: Transport, R: SortRunner> DistributedSort<T, R> {
+    pub fn new(transport: T, runner: R) -> Self {
+        Self {
+            transport,
+            runner,
+            oversampling: DEFAULT_OVERSAMPLING,
+        }
+    }
+
+    /// Set how many samples each worker contributes per worker in the world
+    pub fn with_oversampling(mut self, oversampling: usize) -> Self {
+        self.oversampling = oversampling.max(1);
+        self
+    }
+
+    pub fn rank(&self) -> usize {
+        self.transport.rank()
+    }
+
+    pub fn world_size(&self) -> usize {
+        self.transport.world_size()
+    }
+
+    /// Sort the distributed dataset, of which this worker holds `local`
+    ///
+    /// Must be called on every worker. Returns this worker's slice of the
+    /// result: worker `r` receives the `r`-th key range in `order`.
+    pub fn sort<K>(&mut self, local: &[K], order: SortOrder) -> Result<Vec<K>>
+    where
+        K: SortableKey,
+    {
+        let world_size = self.world_size();
+        let keys: Vec<u32> = local.iter().map(|key| key.to_sortable_u32()).collect();
+        let splitters = self.exchange_splitters(&keys)?;
+
+        // Bucket `b` holds the `b`-th key range; descending order hands the
+        // largest range to rank 0
+        let mut buckets = vec![Vec::new(); world_size];
+        for key in keys {
+            let bucket = splitters.partition_point(|&s| s <= key);
+            let destination = match order {
+                SortOrder::Ascending => bucket,
+                SortOrder::Descending => world_size - 1 - bucket,
+            };
+            buckets[destination].push(key);
+        }
+
+        for (to, bucket) in buckets.iter().enumerate() {
+            self.transport.send(to, encode_keys(bucket))?;
+        }
+
+        let mut received = Vec::new();
+        for from in 0..world_size {
+            received.extend(decode_keys(&self.transport.recv(from)?)?);
+        }
+
+        // The sortable representation preserves order, so sort it directly
+        self.runner.sort(&mut received, order)?;
+        Ok(received.into_iter().map(K::from_sortable_u32).collect())
+    }
+
+    /// Collect every worker's sorted slice on the coordinator
+    ///
+    /// Must be called on every worker. Returns the concatenated result on
+    /// [`COORDINATOR_RANK`] and `None` elsewhere.
+    pub fn gather<K>(&mut self, sorted: &[K]) -> Result<Option<Vec<K>>>
+    where
+        K: SortableKey,
+    {
+        let keys: Vec<u32> = sorted.iter().map(|key| key.to_sortable_u32()).collect();
+        self.transport.send(COORDINATOR_RANK, encode_keys(&keys))?;
+
+        if self.rank() != COORDINATOR_RANK {
+            return Ok(None);
+        }
+
+        let mut all = Vec::new();
+        for from in 0..self.world_size() {
+            let keys = decode_keys(&self.transport.recv(from)?)?;
+            all.extend(keys.into_iter().map(K::from_sortable_u32));
+        }
+        Ok(Some(all))
+    }
+
+    /// Send a sample to the coordinator and receive the splitters it picks
+    fn exchange_splitters(&mut self, keys: &[u32]) -> Result<Vec<u32>> {
+        let world_size = self.world_size();
+
+        let sample: Vec<u32> = if keys.is_empty() {
+            Vec::new()
+        } else {
+            let mut rng = rand::thread_rng();
+            (0..self.oversampling * world_size)
+                .map(|_| keys[rng.gen_range(0..keys.len())])
+                .collect()
+        };
+        self.transport
+            .send(COORDINATOR_RANK, encode_keys(&sample))?;
+
+        if self.rank() == COORDINATOR_RANK {
+            let mut samples = Vec::new();
+            for from in 0..world_size {
+                samples.extend(decode_keys(&self.transport.recv(from)?)?);
+            }
+            samples.sort_unstable();
+
+            let splitters: Vec<u32> = if samples.is_empty() {
+                Vec::new()
+            } else {
+                (1..world_size)
+                    .map(|i| samples[i * samples.len() / world_size])
+                    .collect()
+            };
+
+            let message = encode_keys(&splitters);
+            for to in 0..world_size {
+                self.transport.send(to, message.clone())?;
+            }
+        }
+
+        decode_keys(&self.transport.recv(COORDINATOR_RANK)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChannelTransport, DistributedSort, TcpTransport, Transport};
+    use crate::{error::ChimeraError, verify_sorted, CpuRunner};
+    use shared::SortOrder;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::process::Stdio;
+    use std::time::Duration;
+
+    const WORKER_RANK_ENV: &str = "CHIMERA_DISTRIBUTED_TEST_RANK";
+    /// Marker before the listening address a worker process reports
+    const WORKER_ADDR_PREFIX: &str = "chimera-worker-addr=";
+
+    /// Deterministic per-worker input so every process can reproduce it
+    fn worker_data(rank: usize, len: usize) -> Vec<i32> {
+        let mut state = 0x9E37_79B9u32.wrapping_mul(rank as u32 + 1);
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as i32 - (1 << 23)
+            })
+            .collect()
+    }
+
+    fn expected(world_size: usize, len: usize, order: SortOrder) -> Vec<i32> {
+        let mut all: Vec<i32> = (0..world_size)
+            .flat_map(|rank| worker_data(rank, len))
+            .collect();
+        all.sort_unstable();
+        if order == SortOrder::Descending {
+            all.reverse();
+        }
+        all
+    }
+
+    /// Run one worker to completion and return the gathered result on rank 0
+    fn run_worker<T: Transport>(transport: T, len: usize, order: SortOrder) -> Option<Vec<i32>> {
+        let mut worker = DistributedSort::new(transport, CpuRunner);
+        let local = worker_data(worker.rank(), len);
+        let sorted = worker.sort(&local, order).unwrap();
+        assert!(verify_sorted(&sorted, order));
+        worker.gather(&sorted).unwrap()
+    }
+
+    fn run_threads<T: Transport + Send + 'static>(
+        transports: Vec<T>,
+        len: usize,
+        order: SortOrder,
+    ) -> Vec<i32> {
+        let handles: Vec<_> = transports
+            .into_iter()
+            .map(|transport| std::thread::spawn(move || run_worker(transport, len, order)))
+            .collect();
+
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().unwrap())
+            .next()
+            .unwrap()
+    }
+
+    fn loopback_listeners(count: usize) -> (Vec<TcpListener>, Vec<SocketAddr>) {
+        let listeners: Vec<TcpListener> = (0..count)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let addrs = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+        (listeners, addrs)
+    }
+
+    #[test]
+    fn test_channel_transport_sort() {
+        let result = run_threads(ChannelTransport::mesh(4), 500, SortOrder::Ascending);
+        assert_eq!(result, expected(4, 500, SortOrder::Ascending));
+    }
+
+    #[test]
+    fn test_channel_transport_sort_descending() {
+        let result = run_threads(ChannelTransport::mesh(3), 300, SortOrder::Descending);
+        assert_eq!(result, expected(3, 300, SortOrder::Descending));
+    }
+
+    #[test]
+    fn test_single_worker() {
+        let result = run_threads(ChannelTransport::mesh(1), 100, SortOrder::Ascending);
+        assert_eq!(result, expected(1, 100, SortOrder::Ascending));
+    }
+
+    fn tcp_mesh(count: usize) -> Vec<TcpTransport> {
+        let (listeners, addrs) = loopback_listeners(count);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = listeners
+                .into_iter()
+                .enumerate()
+                .map(|(rank, listener)| {
+                    let addrs = &addrs;
+                    scope.spawn(move || TcpTransport::new(rank, listener, addrs).unwrap())
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })
+    }
+
+    #[test]
+    fn test_tcp_loopback_sort() {
+        let result = run_threads(tcp_mesh(3), 2000, SortOrder::Ascending);
+        assert_eq!(result, expected(3, 2000, SortOrder::Ascending));
+    }
+
+    #[test]
+    fn test_tcp_frame_limit() {
+        let mut transports = tcp_mesh(2);
+        let mut receiver = transports.pop().unwrap().with_max_frame_size(16);
+        let mut sender = transports.pop().unwrap();
+
+        sender.send(1, vec![0; 16]).unwrap();
+        sender.send(1, vec![0; 17]).unwrap();
+        assert_eq!(receiver.recv(0).unwrap().len(), 16);
+        assert!(matches!(receiver.recv(0), Err(ChimeraError::Transport(_))));
+    }
+
+    #[test]
+    fn test_tcp_read_timeout() {
+        let mut transports = tcp_mesh(2);
+        let mut receiver = transports
+            .pop()
+            .unwrap()
+            .with_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+
+        assert!(matches!(receiver.recv(0), Err(ChimeraError::Transport(_))));
+    }
+
+    /// Spawns this test binary as worker processes that talk over TCP loopback
+    ///
+    /// Each child binds an ephemeral port and reports it on stdout; the parent
+    /// then sends every child the full peer list on stdin.
+    #[test]
+    fn test_tcp_multi_process_sort() {
+        const WORKERS: usize = 3;
+        const LEN: usize = 1000;
+
+        if let Ok(rank) = std::env::var(WORKER_RANK_ENV) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let mut stdout = std::io::stdout();
+            writeln!(
+                stdout,
+                "{WORKER_ADDR_PREFIX}{}",
+                listener.local_addr().unwrap()
+            )
+            .unwrap();
+            stdout.flush().unwrap();
+
+            let mut peers = String::new();
+            std::io::stdin().read_line(&mut peers).unwrap();
+            let peers: Vec<SocketAddr> = peers
+                .trim()
+                .split(',')
+                .map(|addr| addr.parse().unwrap())
+                .collect();
+            let transport = TcpTransport::new(rank.parse().unwrap(), listener, &peers).unwrap();
+            run_worker(transport, LEN, SortOrder::Ascending);
+            return;
+        }
+
+        let coordinator_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut addrs = vec![coordinator_listener.local_addr().unwrap()];
+        let mut children: Vec<_> = (1..WORKERS)
+            .map(|rank| {
+                let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+                    .args([
+                        "--exact",
+                        "distributed::tests::test_tcp_multi_process_sort",
+                        "--nocapture",
+                    ])
+                    .env(WORKER_RANK_ENV, rank.to_string())
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .unwrap();
+                let mut stdout = BufReader::new(child.stdout.take().unwrap());
+                let addr = loop {
+                    let mut line = String::new();
+                    assert_ne!(
+                        stdout.read_line(&mut line).unwrap(),
+                        0,
+                        "worker {rank} exited"
+                    );
+                    // libtest may have printed the test name on the same line
+                    if let Some((_, addr)) = line.trim().split_once(WORKER_ADDR_PREFIX) {
+                        break addr.parse().unwrap();
+                    }
+                };
+                addrs.push(addr);
+                (child, stdout)
+            })
+            .collect();
+
+        let peers = addrs
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        for (child, _) in &mut children {
+            let mut stdin = child.stdin.take().unwrap();
+            writeln!(stdin, "{peers}").unwrap();
+        }
+
+        let transport = TcpTransport::new(0, coordinator_listener, &addrs).unwrap();
+        let result = run_worker(transport, LEN, SortOrder::Ascending).unwrap();
+
+        for (mut child, mut stdout) in children {
+            // Drain the rest of the child's output so it never blocks on the pipe
+            std::io::copy(&mut stdout, &mut std::io::sink()).unwrap();
+            assert!(child.wait().unwrap().success());
+        }
+        assert_eq!(result, expected(WORKERS, LEN, SortOrder::Ascending));
+    }
+}
@@ -0,0 +1,313 @@
+//! Message transports between sort workers
+
+use crate::error::{ChimeraError, Result};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long a worker keeps retrying to reach, or waiting to be reached by, a
+/// peer that has not started yet
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delay between connection attempts
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Default for how long [`TcpTransport::recv`] waits for a message
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Default for the largest frame [`TcpTransport::recv`] accepts, 1 GiB
+pub const DEFAULT_MAX_FRAME_SIZE: u64 = 1 << 30;
+
+/// Point-to-point messaging between the workers of a distributed sort
+///
+/// Messages between a pair of workers arrive in the order they were sent.
+pub trait Transport {
+    /// Index of this worker, `0..world_size()`
+    fn rank(&self) -> usize;
+
+    /// Number of workers taking part
+    fn world_size(&self) -> usize;
+
+    /// Queue a message for worker `to`
+    ///
+    /// Must not wait for the receiver, so that all workers can send before any
+    /// of them receives.
+    fn send(&mut self, to: usize, message: Vec<u8>) -> Result<()>;
+
+    /// Block until the next message from worker `from` arrives
+    fn recv(&mut self, from: usize) -> Result<Vec<u8>>;
+}
+
+fn check_peer(peer: usize, world_size: usize) -> Result<()> {
+    if peer >= world_size {
+        return Err(ChimeraError::Transport(format!(
+            "worker {peer} does not exist in a world of {world_size}"
+        )));
+    }
+    Ok(())
+}
+
+/// In-process transport backed by channels, for workers running as threads
+pub struct ChannelTransport {
+    rank: usize,
+    senders: Vec<Sender<Vec<u8>>>,
+    receivers: Vec<Receiver<Vec<u8>>>,
+}
+
+impl ChannelTransport {
+    /// Create a fully connected set of `world_size` transports, one per worker
+    pub fn mesh(world_size: usize) -> Vec<Self> {
+        let mut senders: Vec<Vec<Sender<Vec<u8>>>> = (0..world_size).map(|_| Vec::new()).collect();
+        let mut receivers: Vec<Vec<Receiver<Vec<u8>>>> =
+            (0..world_size).map(|_| Vec::new()).collect();
+
+        // senders[from][to] pairs with receivers[to][from]
+        for from_senders in senders.iter_mut() {
+            for to_receivers in receivers.iter_mut() {
+                let (tx, rx) = mpsc::channel();
+                from_senders.push(tx);
+                to_receivers.push(rx);
+            }
+        }
+
+        senders
+            .into_iter()
+            .zip(receivers)
+            .enumerate()
+            .map(|(rank, (senders, receivers))| Self {
+                rank,
+                senders,
+                receivers,
+            })
+            .collect()
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn rank(&self) -> usize {
+        self.rank
+    }
+
+    fn world_size(&self) -> usize {
+        self.senders.len()
+    }
+
+    fn send(&mut self, to: usize, message: Vec<u8>) -> Result<()> {
+        check_peer(to, self.world_size())?;
+        self.senders[to]
+            .send(message)
+            .map_err(|_| ChimeraError::Transport(format!("worker {to} hung up")))
+    }
+
+    fn recv(&mut self, from: usize) -> Result<Vec<u8>> {
+        check_peer(from, self.world_size())?;
+        self.receivers[from]
+            .recv()
+            .map_err(|_| ChimeraError::Transport(format!("worker {from} hung up")))
+    }
+}
+
+/// TCP transport for workers running as separate processes
+///
+/// Worker `i` listens on `peers[i]`, connects to every lower rank and accepts
+/// connections from every higher rank. Messages are framed with a little-endian
+/// `u64` length. Each peer connection has a writer thread so `send` never blocks
+/// on the receiver.
+///
+/// A peer that does not connect within 30 seconds, or does not send an awaited
+/// message within the read timeout, fails the transport instead of hanging it.
+pub struct TcpTransport {
+    rank: usize,
+    world_size: usize,
+    readers: Vec<Option<TcpStream>>,
+    writers: Vec<Option<Sender<Vec<u8>>>>,
+    writer_threads: Vec<JoinHandle<std::io::Result<()>>>,
+    loopback: VecDeque<Vec<u8>>,
+    max_frame_size: u64,
+}
+
+impl TcpTransport {
+    /// Bind `peers[rank]` and connect to all other workers
+    pub fn bind(rank: usize, peers: &[SocketAddr]) -> Result<Self> {
+        check_peer(rank, peers.len())?;
+        let listener = TcpListener::bind(peers[rank])?;
+        Self::new(rank, listener, peers)
+    }
+
+    /// Connect to all other workers, accepting connections on `listener`
+    pub fn new(rank: usize, listener: TcpListener, peers: &[SocketAddr]) -> Result<Self> {
+        let world_size = peers.len();
+        check_peer(rank, world_size)?;
+
+        let mut streams: Vec<Option<TcpStream>> = (0..world_size).map(|_| None).collect();
+
+        // Connecting first cannot deadlock: lower ranks' listeners queue the
+        // connection until they get around to accepting it
+        for (peer, addr) in peers.iter().enumerate().take(rank) {
+            let mut stream = Self::connect_with_retry(*addr)?;
+            stream.write_all(&(rank as u32).to_le_bytes())?;
+            streams[peer] = Some(stream);
+        }
+
+        for _ in rank + 1..world_size {
+            let mut stream = Self::accept_with_deadline(&listener)?;
+            let mut peer = [0u8; 4];
+            stream.read_exact(&mut peer)?;
+            let peer = u32::from_le_bytes(peer) as usize;
+            if peer <= rank || peer >= world_size || streams[peer].is_some() {
+                return Err(ChimeraError::Transport(format!(
+                    "unexpected handshake from worker {peer}"
+                )));
+            }
+            streams[peer] = Some(stream);
+        }
+
+        let mut writers = Vec::with_capacity(world_size);
+        let mut writer_threads = Vec::with_capacity(world_size - 1);
+        for stream in &streams {
+            let Some(stream) = stream else {
+                writers.push(None);
+                continue;
+            };
+
+            stream.set_nodelay(true)?;
+            stream.set_read_timeout(Some(DEFAULT_READ_TIMEOUT))?;
+            let mut stream = stream.try_clone()?;
+            let (tx, rx) = mpsc::channel::<Vec<u8>>();
+            writer_threads.push(std::thread::spawn(move || {
+                for message in rx {
+                    stream.write_all(&(message.len() as u64).to_le_bytes())?;
+                    stream.write_all(&message)?;
+                }
+                stream.flush()
+            }));
+            writers.push(Some(tx));
+        }
+
+        Ok(Self {
+            rank,
+            world_size,
+            readers: streams,
+            writers,
+            writer_threads,
+            loopback: VecDeque::new(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        })
+    }
+
+    /// Set how long `recv` waits for a message; `None` waits forever
+    pub fn with_read_timeout(self, timeout: Option<Duration>) -> Result<Self> {
+        for stream in self.readers.iter().flatten() {
+            stream.set_read_timeout(timeout)?;
+        }
+        Ok(self)
+    }
+
+    /// Set the largest message `recv` accepts, in bytes
+    pub fn with_max_frame_size(mut self, max_frame_size: u64) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    fn accept_with_deadline(listener: &TcpListener) -> Result<TcpStream> {
+        listener.set_nonblocking(true)?;
+        let start = Instant::now();
+        let stream = loop {
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(e) if e.kind() != std::io::ErrorKind::WouldBlock => return Err(e.into()),
+                Err(_) if start.elapsed() >= CONNECT_TIMEOUT => {
+                    return Err(ChimeraError::Transport(format!(
+                        "no worker connected to {} within {CONNECT_TIMEOUT:?}",
+                        listener.local_addr()?
+                    )));
+                }
+                Err(_) => std::thread::sleep(CONNECT_RETRY_INTERVAL),
+            }
+        };
+        listener.set_nonblocking(false)?;
+        // Accepted sockets may inherit the listener's non-blocking mode
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+        Ok(stream)
+    }
+
+    fn connect_with_retry(addr: SocketAddr) -> Result<TcpStream> {
+        let start = Instant::now();
+        loop {
+            match TcpStream::connect(addr) {
+                Ok(stream) => return Ok(stream),
+                Err(e) if start.elapsed() >= CONNECT_TIMEOUT => {
+                    return Err(ChimeraError::Transport(format!(
+                        "could not connect to {addr}: {e}"
+                    )));
+                }
+                Err(_) => std::thread::sleep(CONNECT_RETRY_INTERVAL),
+            }
+        }
+    }
+}
+
+impl Transport for TcpTransport {
+    fn rank(&self) -> usize {
+        self.rank
+    }
+
+    fn world_size(&self) -> usize {
+        self.world_size
+    }
+
+    fn send(&mut self, to: usize, message: Vec<u8>) -> Result<()> {
+        check_peer(to, self.world_size)?;
+        match &self.writers[to] {
+            None => {
+                self.loopback.push_back(message);
+                Ok(())
+            }
+            Some(writer) => writer
+                .send(message)
+                .map_err(|_| ChimeraError::Transport(format!("connection to worker {to} closed"))),
+        }
+    }
+
+    fn recv(&mut self, from: usize) -> Result<Vec<u8>> {
+        check_peer(from, self.world_size)?;
+        let Some(stream) = self.readers[from].as_mut() else {
+            return self.loopback.pop_front().ok_or_else(|| {
+                ChimeraError::Transport("no message queued for this worker".to_string())
+            });
+        };
+
+        let timed_out = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                ChimeraError::Transport(format!("timed out waiting for worker {from}"))
+            }
+            _ => e.into(),
+        };
+        let mut len = [0u8; 8];
+        stream.read_exact(&mut len).map_err(timed_out)?;
+        let len = u64::from_le_bytes(len);
+        if len > self.max_frame_size {
+            return Err(ChimeraError::Transport(format!(
+                "worker {from} sent a frame of {len} bytes, more than the {} byte limit",
+                self.max_frame_size
+            )));
+        }
+        let mut message = vec![0u8; len as usize];
+        stream.read_exact(&mut message).map_err(timed_out)?;
+        Ok(message)
+    }
+}
+
+impl Drop for TcpTransport {
+    fn drop(&mut self) {
+        // Closing the channels lets the writer threads flush and exit
+        self.writers.clear();
+        for handle in self.writer_threads.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
@@ -20,9 +20,21 @@ pub enum ChimeraError {
     #[error("Failed to find kernel module: {0}")]
     KernelNotFound(String),
 
+    #[error("Invalid kernel: {0}")]
+    InvalidKernel(String),
+
     #[error("Failed to find compute queue family")]
     NoComputeQueue,
 
+    #[error("Too many elements: {requested} requested, at most {max} supported")]
+    TooManyElements { requested: usize, max: u64 },
+
+    #[error("Unsupported operation: {0}")]
+    Unsupported(String),
+
+    #[error("Transport error: {0}")]
+    Transport(String),
+
     #[cfg(feature = "wgpu")]
     #[error("wgpu error: {0}")]
     Wgpu(#[from] wgpu::Error),
@@ -35,6 +47,11 @@ pub enum ChimeraError {
     #[error("Vulkan error: {0}")]
     Vulkan(String),
 
+    /// The Vulkan loader is missing or found no driver
+    #[cfg(feature = "ash")]
+    #[error("Failed to load Vulkan: {0}")]
+    VulkanLoader(String),
+
     #[cfg(feature = "cuda")]
     #[error("CUDA error: {0}")]
     Cuda(#[from] cust::error::CudaError),
@@ -52,6 +69,12 @@ impl From<Box<dyn std::error::Error>> for ChimeraError {
     }
 }
 
+impl From<crate::reflect::ReflectError> for ChimeraError {
+    fn from(err: crate::reflect::ReflectError) -> Self {
+        ChimeraError::InvalidKernel(err.0)
+    }
+}
+
 #[cfg(feature = "ash")]
 impl From<ash::vk::Result> for ChimeraError {
     fn from(err: ash::vk::Result) -> Self {
@@ -0,0 +1,723 @@
+//! Kernel binaries chosen at runtime
+//!
+//! `build.rs` compiles the kernel for several workgroup sizes and lists every entry point in [`SPIRV_KERNELS`] and [`PTX_KERNELS`],
+//! together with the bindings and push-constant size reflected from the
+//! SPIR-V. Runners pick a [`KernelVariant`] from their device's limits and
+//! features, which decide how it receives its params ([`ParamsSource`]), and
+//! build the pipeline from a [`KernelSource`]: the variant's embedded
+//! artifact, a file, or bytes supplied by the application. SPIR-V and PTX are
+//! checked before they reach the driver, and the loaded SPIR-V is reflected
+//! into a [`BitonicLayout`] that must match [`BitonicParams`], so a wrong or
+//! truncated file fails with [`ChimeraError::InvalidKernel`] instead of a
+//! driver crash.
+
+use crate::error::{ChimeraError, Result};
+use crate::reflect::{self, BindingLayout, BindingType, BlockLayout, SpecConstant};
+use shared::{
+    BitonicParams, Pass, SortOrder, Stage, SORT_ORDER_SPEC_ID, WORKGROUP_SIZE,
+    WORKGROUP_SIZE_SPEC_ID,
+};
+use std::borrow::Cow;
+use std::mem::{offset_of, size_of};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+include!(concat!(env!("OUT_DIR"), "/kernel_manifest.rs"));
+
+/// First word of every SPIR-V module
+pub const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Newest SPIR-V version the runners accept, that of the Vulkan 1.2 build target
+pub const MAX_SPIRV_VERSION: (u32, u32) = (1, 5);
+
+/// Algorithm an entry point implements, from the `<kind>_kernel` prefix of its name
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KernelKind {
+    /// One compare-and-swap pass of a bitonic sort
+    Bitonic,
+}
+
+/// How an entry point receives [`BitonicParams`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ParamsSource {
+    /// A push-constant block, set before each dispatch
+    PushConstants,
+    /// A uniform buffer in set 0, bound at a dynamic offset per dispatch
+    ///
+    /// For devices without push constants, such as WebGPU.
+    UniformBuffer,
+    /// A by-value kernel argument (PTX)
+    KernelArgument,
+}
+
+/// One entry point built by `build.rs`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct KernelVariant {
+    pub kind: KernelKind,
+    /// Invocations per workgroup (SPIR-V) or threads per block (CUDA)
+    pub workgroup_size: u32,
+    /// Order compiled into the entry point; `None` reads it from [`BitonicParams`]
+    /// unless a SPIR-V pipeline specializes it
+    pub sort_order: Option<SortOrder>,
+    pub params: ParamsSource,
+    pub entry_point: &'static str,
+    /// SPIR-V module or PTX containing `entry_point`
+    pub artifact: &'static [u8],
+    /// Buffers the entry point accesses; empty for PTX, whose kernels take pointers
+    pub bindings: &'static [BindingLayout],
+    /// Bytes of push constants the entry point reads; zero for PTX
+    pub push_constant_size: u32,
+    /// Specialization constants of the SPIR-V module; empty for PTX
+    pub spec_constants: &'static [SpecConstant],
+    /// Whether the workgroup width is the [`WORKGROUP_SIZE_SPEC_ID`] constant,
+    /// so `workgroup_size` can be any size the device allows
+    pub workgroup_size_constant: bool,
+}
+
+impl KernelVariant {
+    /// Pick the bitonic variant of `variants` to run on a device allowing `max_workgroup_size` invocations
+    ///
+    /// Only entry points reading their params from `params` are considered.
+    /// `requested` asks for an exact workgroup size, served by an entry point
+    /// built for it or else by specializing one whose workgroup size is a
+    /// constant. Otherwise [`WORKGROUP_SIZE`] is preferred, falling back to
+    /// the largest size the device allows.
+    pub fn select(
+        variants: &'static [KernelVariant],
+        params: ParamsSource,
+        max_workgroup_size: u32,
+        requested: Option<u32>,
+    ) -> Result<KernelVariant> {
+        let candidates = || {
+            variants.iter().filter(|variant| {
+                variant.kind == KernelKind::Bitonic
+                    && variant.sort_order.is_none()
+                    && variant.params == params
+            })
+        };
+        let fits = |size: u32| size > 0 && size <= max_workgroup_size;
+        let selected = match requested {
+            Some(size) if fits(size) => candidates()
+                .find(|variant| variant.workgroup_size == size)
+                .or_else(|| candidates().find(|variant| variant.workgroup_size_constant))
+                .map(|variant| KernelVariant {
+                    workgroup_size: size,
+                    ..*variant
+                }),
+            Some(_) => None,
+            None => candidates()
+                .filter(|variant| fits(variant.workgroup_size))
+                .max_by_key(|variant| {
+                    (
+                        variant.workgroup_size == WORKGROUP_SIZE,
+                        variant.workgroup_size,
+                    )
+                })
+                .copied(),
+        };
+        selected.ok_or_else(|| {
+            ChimeraError::Unsupported(format!(
+                "no kernel variant reading {params:?} with workgroup size {} fits a device limit of {max_workgroup_size}",
+                requested.map_or("of any size".to_string(), |size| size.to_string())
+            ))
+        })
+    }
+
+    /// The entry point of `variants` like this one with `order` compiled in, if one was built
+    pub fn specialized(
+        &self,
+        variants: &'static [KernelVariant],
+        order: SortOrder,
+    ) -> Option<&'static KernelVariant> {
+        variants.iter().find(|variant| {
+            variant.kind == self.kind
+                && variant.workgroup_size == self.workgroup_size
+                && variant.params == self.params
+                && variant.sort_order == Some(order)
+        })
+    }
+}
+
+/// Pipeline layout of a bitonic entry point, reflected from the module a runner loads
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitonicLayout {
+    /// Buffers the entry point accesses, all in descriptor set 0
+    pub bindings: Vec<BindingLayout>,
+    /// Binding of the storage buffer holding the keys
+    pub key_binding: u32,
+    /// Binding of the uniform buffer holding [`BitonicParams`], if not push constants
+    pub params_binding: Option<u32>,
+    /// Bytes of push constants: the size of [`BitonicParams`], or zero when
+    /// they are read from `params_binding`
+    pub push_constant_size: u32,
+    /// Whether the module declares the sort-order specialization constant
+    /// ([`SORT_ORDER_SPEC_ID`]), so a pipeline can be built per order
+    pub sort_order_constant: bool,
+    /// Whether the entry point's width is the [`WORKGROUP_SIZE_SPEC_ID`]
+    /// constant, which pipelines set to `variant.workgroup_size`
+    pub workgroup_size_constant: bool,
+}
+
+impl BitonicLayout {
+    /// Reflect `variant.entry_point` from `spirv` and check it against the Rust side
+    ///
+    /// The entry point must bind one writable storage buffer in set 0, read
+    /// a block laid out like [`BitonicParams`] from where `variant.params`
+    /// says, and, unless its width is the [`WORKGROUP_SIZE_SPEC_ID`] constant,
+    /// have a workgroup size of `variant.workgroup_size`.
+    pub fn reflect(spirv: &[u32], variant: &KernelVariant) -> Result<Self> {
+        let name = variant.entry_point;
+        let entry_point = reflect::reflect(spirv)?
+            .into_iter()
+            .find(|entry_point| entry_point.name == name)
+            .ok_or_else(|| {
+                ChimeraError::InvalidKernel(format!("the module has no entry point `{name}`"))
+            })?;
+
+        let workgroup_size_constant =
+            entry_point.workgroup_size_spec_id == Some(WORKGROUP_SIZE_SPEC_ID);
+        if let (Some(size), false) = (entry_point.workgroup_size, workgroup_size_constant) {
+            if size != [variant.workgroup_size, 1, 1] {
+                return Err(ChimeraError::InvalidKernel(format!(
+                    "`{name}` has a workgroup size of {size:?}, the variant expects {}",
+                    variant.workgroup_size
+                )));
+            }
+        }
+
+        let (uniforms, expected) = match variant.params {
+            ParamsSource::PushConstants => (0, "one writable storage buffer in set 0"),
+            ParamsSource::UniformBuffer => (
+                1,
+                "one writable storage buffer and one uniform buffer in set 0",
+            ),
+            ParamsSource::KernelArgument => {
+                return Err(ChimeraError::InvalidKernel(format!(
+                    "`{name}` takes BitonicParams as a kernel argument, which SPIR-V cannot"
+                )))
+            }
+        };
+        let bindings_of = |ty: BindingType| -> Vec<u32> {
+            entry_point
+                .bindings
+                .iter()
+                .filter(|binding| binding.set == 0 && binding.ty == ty)
+                .map(|binding| binding.binding)
+                .collect()
+        };
+        let keys = bindings_of(BindingType::StorageBuffer { read_only: false });
+        let params_bindings = bindings_of(BindingType::UniformBuffer);
+        let (key_binding, params_binding) = match (keys.as_slice(), params_bindings.as_slice()) {
+            (&[keys], params)
+                if params.len() == uniforms && entry_point.bindings.len() == 1 + uniforms =>
+            {
+                (keys, params.first().copied())
+            }
+            _ => {
+                return Err(ChimeraError::InvalidKernel(format!(
+                    "`{name}` binds {:?}, expected {expected}",
+                    entry_point.bindings
+                )))
+            }
+        };
+
+        let params = bitonic_params_layout();
+        let (block, source) = match params_binding {
+            Some(binding) => (
+                entry_point
+                    .uniform_blocks
+                    .iter()
+                    .find(|(set_binding, _)| *set_binding == (0, binding))
+                    .map(|(_, block)| block.clone()),
+                "uniform params",
+            ),
+            None => (entry_point.push_constants, "push constants"),
+        };
+        match block {
+            Some(block) if block == params => {}
+            Some(block) => {
+                return Err(ChimeraError::InvalidKernel(format!(
+                    "{source} of `{name}` do not match BitonicParams: the kernel reads {} bytes \
+                     with (offset, size) members {:?}, BitonicParams has {} bytes with {:?}",
+                    block.size, block.members, params.size, params.members
+                )))
+            }
+            None => {
+                return Err(ChimeraError::InvalidKernel(format!(
+                    "`{name}` reads no {source}, but BitonicParams is passed as {source}"
+                )))
+            }
+        }
+
+        Ok(Self {
+            bindings: entry_point.bindings,
+            key_binding,
+            params_binding,
+            push_constant_size: if params_binding.is_some() {
+                0
+            } else {
+                params.size
+            },
+            sort_order_constant: entry_point
+                .spec_constants
+                .iter()
+                .any(|constant| constant.id == SORT_ORDER_SPEC_ID),
+            workgroup_size_constant,
+        })
+    }
+}
+
+/// Layout the host writes [`BitonicParams`] with
+fn bitonic_params_layout() -> BlockLayout {
+    let member = |offset: usize, size: usize| (offset as u32, size as u32);
+    BlockLayout {
+        size: size_of::<BitonicParams>() as u32,
+        members: vec![
+            member(offset_of!(BitonicParams, num_elements), size_of::<u32>()),
+            member(offset_of!(BitonicParams, stage), size_of::<Stage>()),
+            member(offset_of!(BitonicParams, pass_of_stage), size_of::<Pass>()),
+            member(offset_of!(BitonicParams, sort_order), size_of::<u32>()),
+        ],
+    }
+}
+
+/// Where a runner gets its kernel from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum KernelSource {
+    /// The artifact of the selected [`KernelVariant`]
+    #[default]
+    Embedded,
+    /// A SPIR-V or PTX file read when the runner is created
+    File(PathBuf),
+    /// SPIR-V or PTX bytes held by the application
+    Bytes(Arc<[u8]>),
+}
+
+impl KernelSource {
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::File(path.into())
+    }
+
+    pub fn bytes(bytes: impl Into<Arc<[u8]>>) -> Self {
+        Self::Bytes(bytes.into())
+    }
+
+    fn read(&self, variant: &KernelVariant) -> Result<Cow<'static, [u8]>> {
+        match self {
+            KernelSource::Embedded => Ok(Cow::Borrowed(variant.artifact)),
+            KernelSource::File(path) => std::fs::read(path)
+                .map(Cow::Owned)
+                .map_err(|err| ChimeraError::KernelNotFound(format!("{}: {err}", path.display()))),
+            KernelSource::Bytes(bytes) => Ok(Cow::Owned(bytes.to_vec())),
+        }
+    }
+
+    /// Load and validate the SPIR-V module for `variant`, returning its words
+    ///
+    /// Files and bytes must contain `variant.entry_point`, as modules built
+    /// from the `kernel` crate do.
+    pub fn spirv(&self, variant: &KernelVariant) -> Result<Vec<u32>> {
+        parse_spirv(&self.read(variant)?)
+    }
+
+    /// Load and validate the PTX for `variant`, returning its text and `sm_XX` target
+    pub fn ptx(&self, variant: &KernelVariant) -> Result<(Cow<'static, str>, u32)> {
+        let ptx = match self.read(variant)? {
+            Cow::Borrowed(bytes) => Cow::Borrowed(
+                std::str::from_utf8(bytes)
+                    .map_err(|_| ChimeraError::InvalidKernel("PTX is not UTF-8".to_string()))?,
+            ),
+            Cow::Owned(bytes) => Cow::Owned(
+                String::from_utf8(bytes)
+                    .map_err(|_| ChimeraError::InvalidKernel("PTX is not UTF-8".to_string()))?,
+            ),
+        };
+        let target = ptx_target(&ptx)?;
+        Ok((ptx, target))
+    }
+}
+
+/// Check the header of a SPIR-V module and convert it to host-endian words
+pub fn parse_spirv(bytes: &[u8]) -> Result<Vec<u32>> {
+    // Magic, version, generator, bound and schema
+    if bytes.len() < 20 || bytes.len() % 4 != 0 {
+        return Err(ChimeraError::InvalidKernel(format!(
+            "{} bytes is not a SPIR-V module",
+            bytes.len()
+        )));
+    }
+
+    let mut words: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    if words[0] == SPIRV_MAGIC.swap_bytes() {
+        words.iter_mut().for_each(|word| *word = word.swap_bytes());
+    }
+    if words[0] != SPIRV_MAGIC {
+        return Err(ChimeraError::InvalidKernel(format!(
+            "wrong SPIR-V magic number {:#010x}",
+            words[0]
+        )));
+    }
+
+    let version = ((words[1] >> 16) & 0xff, (words[1] >> 8) & 0xff);
+    if version.0 != 1 || version > MAX_SPIRV_VERSION {
+        return Err(ChimeraError::InvalidKernel(format!(
+            "SPIR-V {}.{} is not supported, at most {}.{} is",
+            version.0, version.1, MAX_SPIRV_VERSION.0, MAX_SPIRV_VERSION.1
+        )));
+    }
+    Ok(words)
+}
+
+/// The `sm_XX` compute capability a PTX module targets, as `10 * major + minor`
+pub fn ptx_target(ptx: &str) -> Result<u32> {
+    let directive = |name: &str| {
+        ptx.lines()
+            .map(str::trim)
+            .find_map(|line| line.strip_prefix(name))
+            .filter(|rest| rest.starts_with(char::is_whitespace))
+    };
+
+    if directive(".version").is_none() {
+        return Err(ChimeraError::InvalidKernel(
+            "PTX has no .version directive".to_string(),
+        ));
+    }
+    let target = directive(".target")
+        .ok_or_else(|| ChimeraError::InvalidKernel("PTX has no .target directive".to_string()))?;
+
+    // `.target sm_75` optionally followed by `, debug` or an arch-specific `sm_90a`
+    target
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .find_map(|target| target.strip_prefix("sm_"))
+        .and_then(|arch| arch.trim_end_matches(char::is_alphabetic).parse().ok())
+        .ok_or_else(|| {
+            ChimeraError::InvalidKernel(format!(
+                "PTX target `{}` is not an sm_XX architecture",
+                target.trim()
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_spirv, ptx_target, BitonicLayout, KernelKind, KernelSource, KernelVariant,
+        ParamsSource, SPIRV_MAGIC,
+    };
+    use crate::error::ChimeraError;
+    use crate::reflect::tests::{
+        bitonic_module, remove_instruction, uniform_bitonic_module, BITONIC_OFFSETS,
+        SORT_ORDER_SPEC_ID,
+    };
+    use crate::reflect::{tunable_workgroup_size, BindingLayout, BindingType};
+    use shared::{SortOrder, WORKGROUP_SIZE_SPEC_ID};
+
+    const KEYS: BindingLayout = BindingLayout {
+        set: 0,
+        binding: 0,
+        ty: BindingType::StorageBuffer { read_only: false },
+    };
+
+    const fn variant(workgroup_size: u32) -> KernelVariant {
+        KernelVariant {
+            kind: KernelKind::Bitonic,
+            workgroup_size,
+            sort_order: None,
+            params: ParamsSource::PushConstants,
+            entry_point: "bitonic_kernel",
+            artifact: b".version 7.0\n.target sm_52\n",
+            bindings: &[KEYS],
+            push_constant_size: 16,
+            spec_constants: &[],
+            workgroup_size_constant: false,
+        }
+    }
+
+    const fn ordered(workgroup_size: u32, order: SortOrder) -> KernelVariant {
+        KernelVariant {
+            sort_order: Some(order),
+            entry_point: "bitonic_kernel_ordered",
+            ..variant(workgroup_size)
+        }
+    }
+
+    const fn uniform(workgroup_size: u32) -> KernelVariant {
+        KernelVariant {
+            params: ParamsSource::UniformBuffer,
+            entry_point: "bitonic_kernel_uniform",
+            push_constant_size: 0,
+            ..variant(workgroup_size)
+        }
+    }
+
+    static VARIANTS: [KernelVariant; 8] = [
+        variant(64),
+        variant(128),
+        variant(256),
+        variant(512),
+        ordered(256, SortOrder::Ascending),
+        ordered(256, SortOrder::Descending),
+        ordered(1024, SortOrder::Ascending),
+        uniform(64),
+    ];
+
+    #[test]
+    fn test_select_variant() {
+        let select = |max, requested| {
+            KernelVariant::select(&VARIANTS, ParamsSource::PushConstants, max, requested)
+                .map(|variant| variant.workgroup_size)
+                .ok()
+        };
+        // The default size wins over larger ones, smaller devices get the largest that fits
+        assert_eq!(select(1024, None), Some(256));
+        assert_eq!(select(192, None), Some(128));
+        assert_eq!(select(32, None), None);
+        assert_eq!(select(1024, Some(512)), Some(512));
+        assert_eq!(select(256, Some(512)), None);
+        // Entry points with a compiled-in order are only reached through `specialized`
+        assert_eq!(select(2048, Some(1024)), None);
+
+        // Devices without push constants only get uniform-buffer entry points
+        let uniform =
+            KernelVariant::select(&VARIANTS, ParamsSource::UniformBuffer, 1024, None).unwrap();
+        assert_eq!(uniform, VARIANTS[7]);
+    }
+
+    #[test]
+    fn test_select_workgroup_size_constant() {
+        static TUNABLE: [KernelVariant; 2] = [
+            variant(128),
+            KernelVariant {
+                workgroup_size_constant: true,
+                ..variant(256)
+            },
+        ];
+        let select = |max, requested| {
+            KernelVariant::select(&TUNABLE, ParamsSource::PushConstants, max, requested)
+                .map(|variant| (variant.workgroup_size, variant.workgroup_size_constant))
+                .ok()
+        };
+        // Built sizes are used as they are, any other size the device allows is specialized
+        assert_eq!(select(1024, None), Some((256, true)));
+        assert_eq!(select(1024, Some(128)), Some((128, false)));
+        assert_eq!(select(1024, Some(1024)), Some((1024, true)));
+        assert_eq!(select(1024, Some(96)), Some((96, true)));
+        assert_eq!(select(512, Some(1024)), None);
+        assert_eq!(select(1024, Some(0)), None);
+        // Without a constant only the built sizes are available
+        assert_eq!(
+            KernelVariant::select(&VARIANTS, ParamsSource::PushConstants, 1024, Some(96)).ok(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_specialized_variant() {
+        let specialized = |variant: &KernelVariant, order| {
+            variant
+                .specialized(&VARIANTS, order)
+                .map(|variant| (variant.workgroup_size, variant.sort_order))
+        };
+        assert_eq!(
+            specialized(&VARIANTS[2], SortOrder::Descending),
+            Some((256, Some(SortOrder::Descending)))
+        );
+        assert_eq!(specialized(&VARIANTS[1], SortOrder::Ascending), None);
+        assert_eq!(specialized(&VARIANTS[7], SortOrder::Ascending), None);
+    }
+
+    #[test]
+    fn test_bitonic_layout() {
+        let variant = KernelVariant {
+            entry_point: "bitonic_kernel_wg64",
+            ..VARIANTS[0]
+        };
+        let variant = &variant;
+        let layout =
+            BitonicLayout::reflect(&bitonic_module(64, &BITONIC_OFFSETS, false), variant).unwrap();
+        assert_eq!(
+            layout,
+            BitonicLayout {
+                bindings: vec![KEYS],
+                key_binding: 0,
+                params_binding: None,
+                push_constant_size: 16,
+                sort_order_constant: true,
+                workgroup_size_constant: false,
+            }
+        );
+
+        let uniform = KernelVariant {
+            entry_point: "bitonic_kernel_uniform_wg64",
+            ..VARIANTS[7]
+        };
+        assert_eq!(
+            BitonicLayout::reflect(&uniform_bitonic_module(64, &BITONIC_OFFSETS), &uniform)
+                .unwrap(),
+            BitonicLayout {
+                bindings: vec![
+                    KEYS,
+                    BindingLayout {
+                        set: 0,
+                        binding: 1,
+                        ty: BindingType::UniformBuffer,
+                    },
+                ],
+                key_binding: 0,
+                params_binding: Some(1),
+                push_constant_size: 0,
+                sort_order_constant: true,
+                workgroup_size_constant: false,
+            }
+        );
+        // Kernels built before the order became a specialization constant still load
+        let dynamic = remove_instruction(
+            bitonic_module(64, &BITONIC_OFFSETS, false),
+            &SORT_ORDER_SPEC_ID,
+        );
+        assert!(
+            !BitonicLayout::reflect(&dynamic, variant)
+                .unwrap()
+                .sort_order_constant
+        );
+
+        let error = |spirv: Vec<u32>, variant: &KernelVariant| match BitonicLayout::reflect(
+            &spirv, variant,
+        ) {
+            Err(ChimeraError::InvalidKernel(message)) => message,
+            other => panic!("expected an invalid kernel, got {other:?}"),
+        };
+        // A params struct missing `sort_order` names both layouts
+        let message = error(bitonic_module(64, &[0, 4, 8], false), variant);
+        assert!(message.contains("do not match BitonicParams"), "{message}");
+        assert!(message.contains("12 bytes"), "{message}");
+        assert!(message.contains("16 bytes"), "{message}");
+        // Same size, different offsets
+        let message = error(bitonic_module(64, &[0, 8, 4, 12], false), variant);
+        assert!(message.contains("do not match BitonicParams"), "{message}");
+        let message = error(uniform_bitonic_module(64, &[0, 4, 8]), &uniform);
+        assert!(message.starts_with("uniform params"), "{message}");
+        // A push-constant kernel cannot stand in for a uniform-buffer variant
+        let message = error(
+            bitonic_module(64, &BITONIC_OFFSETS, false),
+            &KernelVariant {
+                entry_point: "bitonic_kernel_wg64",
+                ..uniform
+            },
+        );
+        assert!(message.contains("one uniform buffer"), "{message}");
+
+        let message = error(bitonic_module(64, &BITONIC_OFFSETS, true), variant);
+        assert!(message.contains("one writable storage buffer"), "{message}");
+        let message = error(bitonic_module(128, &BITONIC_OFFSETS, false), variant);
+        assert!(message.contains("no entry point"), "{message}");
+        let wg128 = KernelVariant {
+            entry_point: "bitonic_kernel_wg128",
+            ..*variant
+        };
+        let message = error(bitonic_module(128, &BITONIC_OFFSETS, false), &wg128);
+        assert!(message.contains("workgroup size"), "{message}");
+
+        // A width read from the constant can be specialized to any size
+        let tunable = tunable_workgroup_size(
+            &bitonic_module(64, &BITONIC_OFFSETS, false),
+            "bitonic_kernel_wg64",
+            WORKGROUP_SIZE_SPEC_ID,
+        )
+        .unwrap();
+        let wg1024 = KernelVariant {
+            workgroup_size: 1024,
+            workgroup_size_constant: true,
+            ..*variant
+        };
+        assert!(
+            BitonicLayout::reflect(&tunable, &wg1024)
+                .unwrap()
+                .workgroup_size_constant
+        );
+    }
+
+    fn spirv_header(version: (u32, u32)) -> Vec<u8> {
+        [SPIRV_MAGIC, (version.0 << 16) | (version.1 << 8), 0, 1, 0]
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_spirv() {
+        let words = parse_spirv(&spirv_header((1, 5))).unwrap();
+        assert_eq!(words, vec![SPIRV_MAGIC, 0x0001_0500, 0, 1, 0]);
+
+        // Big-endian modules are byte-swapped to host order
+        let big_endian: Vec<u8> = spirv_header((1, 3))
+            .chunks(4)
+            .flat_map(|word| word.iter().rev().copied().collect::<Vec<_>>())
+            .collect();
+        assert_eq!(parse_spirv(&big_endian).unwrap()[1], 0x0001_0300);
+
+        for invalid in [
+            spirv_header((1, 6)),
+            spirv_header((2, 0)),
+            spirv_header((1, 0))[..18].to_vec(),
+            b"not a spir-v module!".to_vec(),
+        ] {
+            assert!(matches!(
+                parse_spirv(&invalid),
+                Err(ChimeraError::InvalidKernel(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_ptx_target() {
+        let ptx = "//\n// Generated by NVVM\n//\n\n.version 7.8\n.target sm_75\n.address_size 64\n";
+        assert_eq!(ptx_target(ptx).unwrap(), 75);
+        assert_eq!(
+            ptx_target(".version 8.0\n.target sm_90a, debug\n").unwrap(),
+            90
+        );
+
+        for invalid in [
+            ".target sm_75\n",
+            ".version 7.8\n",
+            ".version 7.8\n.target compute_75\n",
+        ] {
+            assert!(matches!(
+                ptx_target(invalid),
+                Err(ChimeraError::InvalidKernel(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_kernel_sources() {
+        let dir = std::env::temp_dir().join(format!("chimera-kernel-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bitonic.spv");
+        std::fs::write(&path, spirv_header((1, 2))).unwrap();
+
+        let variant = &VARIANTS[0];
+        let from_file = KernelSource::file(&path).spirv(variant).unwrap();
+        let from_bytes = KernelSource::bytes(spirv_header((1, 2)))
+            .spirv(variant)
+            .unwrap();
+        assert_eq!(from_file, from_bytes);
+
+        assert_eq!(KernelSource::Embedded.ptx(variant).unwrap().1, 52);
+        let ptx = KernelSource::bytes(b".version 7.0\n.target sm_61\n".to_vec());
+        assert_eq!(ptx.ptx(variant).unwrap().1, 61);
+        assert!(matches!(
+            KernelSource::bytes(vec![0xff, 0xfe]).ptx(variant),
+            Err(ChimeraError::InvalidKernel(_))
+        ));
+        assert!(matches!(
+            KernelSource::file(dir.join("missing.ptx")).ptx(variant),
+            Err(ChimeraError::KernelNotFound(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -5,8 +5,6 @@
 //! - CUDA (via rust-cuda)
 //! - Vulkan (via rust-gpu/SPIR-V)
 
-#![feature(once_cell_try)]
-
 // Feature validation
 
 #[cfg(all(feature = "wgpu", feature = "ash"))]
@@ -15,13 +13,49 @@ compile_error!("Cannot enable both 'wgpu' and 'ash' features at the same time");
 #[cfg(all(target_os = "macos", feature = "cuda"))]
 compile_error!("The 'cuda' feature is not supported on macOS. CUDA requires NVIDIA GPUs and is only available on Linux and Windows");
 
+pub mod capabilities;
+pub mod config;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod distributed;
 pub mod error;
+pub mod kernel;
+pub mod pipeline_cache;
+pub mod profile;
+pub mod reflect;
 pub mod runners;
+pub mod trace;
+#[cfg(all(target_arch = "wasm32", feature = "web"))]
+pub mod web;
 
+pub use capabilities::Capabilities;
+pub use config::RunnerConfig;
 use error::Result;
+pub use kernel::KernelSource;
+pub use profile::SortProfile;
 use shared::{BitonicParams, Pass, SortOrder, SortableKey, Stage};
 
+/// Parameters for every pass of a bitonic sort over `n` elements, in dispatch order
+///
+/// `n` must be a power of two.
+pub fn bitonic_passes(n: u32, order: SortOrder) -> Vec<BitonicParams> {
+    let num_stages = n.max(1).ilog2();
+
+    (0..num_stages)
+        .flat_map(|stage| {
+            (0..=stage).map(move |pass| BitonicParams {
+                num_elements: n,
+                stage: Stage::new(stage),
+                pass_of_stage: Pass::new(pass),
+                sort_order: order.into(),
+            })
+        })
+        .collect()
+}
+
 /// Common trait for all sorting backends
+///
+/// The generic helpers require `Self: Sized`, so runners can also be used as
+/// `dyn SortRunner` for the `u32` key pipeline (`pad_data`, `run_bitonic_stages`).
 pub trait SortRunner {
     /// Get backend information for logging
     ///
@@ -35,6 +69,9 @@ pub trait SortRunner {
         Option<String>,
     );
 
+    /// Report what this runner and its device support
+    fn capabilities(&self) -> Capabilities;
+
     /// Execute a single kernel pass - platform-specific implementation required
     ///
     /// # Arguments
@@ -43,7 +80,10 @@ pub trait SortRunner {
     fn execute_kernel_pass(&self, data: &mut [u32], params: BitonicParams) -> Result<()>;
 
     /// Prepare data by converting to `u32` representation
-    fn prepare_data<T: SortableKey>(&self, data: &[T]) -> (Vec<u32>, usize) {
+    fn prepare_data<T: SortableKey>(&self, data: &[T]) -> (Vec<u32>, usize)
+    where
+        Self: Sized,
+    {
         let gpu_data: Vec<u32> = data.iter().map(|x| x.to_sortable_u32()).collect();
         (gpu_data, data.len())
     }
@@ -62,25 +102,17 @@ pub trait SortRunner {
 
     /// Run all bitonic sort stages and passes
     fn run_bitonic_stages(&self, data: &mut [u32], order: SortOrder) -> Result<()> {
-        let n = data.len() as u32;
-        let num_stages = (n as f32).log2() as u32;
-
-        for stage in 0..num_stages {
-            for pass in 0..=stage {
-                let params = BitonicParams {
-                    num_elements: n,
-                    stage: Stage::new(stage),
-                    pass_of_stage: Pass::new(pass),
-                    sort_order: order.into(),
-                };
-                self.execute_kernel_pass(data, params)?;
-            }
+        for params in bitonic_passes(data.len() as u32, order) {
+            self.execute_kernel_pass(data, params)?;
         }
         Ok(())
     }
 
     /// Convert sorted `u32` data back to original type
-    fn finalize_data<T: SortableKey>(&self, gpu_data: &[u32], output: &mut [T]) {
+    fn finalize_data<T: SortableKey>(&self, gpu_data: &[u32], output: &mut [T])
+    where
+        Self: Sized,
+    {
         for (i, &val) in gpu_data.iter().take(output.len()).enumerate() {
             output[i] = T::from_sortable_u32(val);
         }
@@ -94,11 +126,17 @@ pub trait SortRunner {
         &self,
         data: &mut [T],
         order: SortOrder,
-    ) -> Result<()> {
+    ) -> Result<()>
+    where
+        Self: Sized,
+    {
         if data.len() <= 1 {
             return Ok(());
         }
 
+        self.capabilities()
+            .check_sort(data.len(), std::mem::size_of::<T>())?;
+
         let (mut gpu_data, original_size) = self.prepare_data(data);
         self.pad_data(&mut gpu_data, original_size, order);
         self.run_bitonic_stages(&mut gpu_data, order)?;
@@ -107,10 +145,27 @@ pub trait SortRunner {
 
         Ok(())
     }
+
+    /// Sort like [`SortRunner::sort`] and report how long each pass took
+    ///
+    /// The default times every pass on the host; GPU runners override it with
+    /// timestamp queries or CUDA events where the device supports them.
+    fn sort_profiled<T: SortableKey + bytemuck::Pod + Send + Sync>(
+        &self,
+        data: &mut [T],
+        order: SortOrder,
+    ) -> Result<SortProfile>
+    where
+        Self: Sized,
+    {
+        profile::wall_clock_profile(self, data, order)
+    }
 }
 
 // Re-export runners for convenience
 pub use runners::CpuRunner;
+#[cfg(not(target_arch = "wasm32"))]
+pub use runners::MultiDeviceRunner;
 
 #[cfg(feature = "cuda")]
 pub use runners::CudaRunner;
@@ -121,14 +176,6 @@ pub use runners::WgpuRunner;
 #[cfg(feature = "ash")]
 pub use runners::AshRunner;
 
-/// Compiled SPIR-V bytecode for the bitonic sort kernel
-#[cfg(any(feature = "wgpu", feature = "ash"))]
-pub const BITONIC_SPIRV: &[u8] = include_bytes!(env!("BITONIC_KERNEL_SPV_PATH"));
-
-/// Compiled PTX code for the bitonic sort kernel
-#[cfg(feature = "cuda")]
-pub const BITONIC_PTX: &str = include_str!(env!("BITONIC_KERNEL_PTX_PATH"));
-
 /// Verify that a slice is sorted in the specified order
 #[cfg(test)]
 pub fn verify_sorted<T: SortableKey + PartialOrd>(data: &[T], order: SortOrder) -> bool {
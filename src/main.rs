@@ -41,7 +41,13 @@ fn log_backend_info(
     }
 }
 
-fn run_sort_test<T, R>(runner: &R, data: &mut [T], test_type: &str, order: SortOrder) -> Result<()>
+fn run_sort_test<T, R>(
+    runner: &R,
+    data: &mut [T],
+    test_type: &str,
+    order: SortOrder,
+    profile: bool,
+) -> Result<()>
 where
     T: SortableKey + bytemuck::Pod + Send + Sync + std::fmt::Debug + PartialOrd + Clone,
     R: SortRunner,
@@ -58,7 +64,12 @@ where
         vec![]
     };
 
-    runner.sort(data, order)?;
+    if profile {
+        let profile = runner.sort_profiled(data, order)?;
+        println!("  Profile: {profile}");
+    } else {
+        runner.sort(data, order)?;
+    }
 
     // Verify sort
     let is_sorted = match order {
@@ -88,14 +99,19 @@ where
     }
 }
 
-fn run_test_on_backend<T>(data: &mut [T], test_type: &str, order: SortOrder) -> Result<()>
+fn run_test_on_backend<T>(
+    config: &RunnerConfig,
+    data: &mut [T],
+    test_type: &str,
+    order: SortOrder,
+) -> Result<()>
 where
     T: SortableKey + bytemuck::Pod + Send + Sync + std::fmt::Debug + PartialOrd + Clone,
 {
     #[cfg(not(any(feature = "cuda", feature = "wgpu", feature = "ash")))]
     {
         let runner = CpuRunner;
-        run_sort_test(&runner, data, test_type, order)?;
+        run_sort_test(&runner, data, test_type, order, config.profiling)?;
     }
 
     #[cfg(any(feature = "cuda", feature = "wgpu", feature = "ash"))]
@@ -104,30 +120,30 @@ where
 
         #[cfg(feature = "cuda")]
         if !gpu_executed {
-            if let Ok(runner) = CudaRunner::new() {
-                run_sort_test(&runner, data, test_type, order)?;
+            if let Ok(runner) = CudaRunner::with_config(config) {
+                run_sort_test(&runner, data, test_type, order, config.profiling)?;
                 gpu_executed = true;
-            } else if let Err(e) = CudaRunner::new() {
+            } else if let Err(e) = CudaRunner::with_config(config) {
                 eprintln!("  CUDA initialization failed: {e}");
             }
         }
 
         #[cfg(feature = "wgpu")]
         if !gpu_executed {
-            if let Ok(runner) = futures::executor::block_on(WgpuRunner::new()) {
-                run_sort_test(&runner, data, test_type, order)?;
+            if let Ok(runner) = futures::executor::block_on(WgpuRunner::with_config(config)) {
+                run_sort_test(&runner, data, test_type, order, config.profiling)?;
                 gpu_executed = true;
-            } else if let Err(e) = futures::executor::block_on(WgpuRunner::new()) {
+            } else if let Err(e) = futures::executor::block_on(WgpuRunner::with_config(config)) {
                 eprintln!("  wgpu initialization failed: {e}");
             }
         }
 
         #[cfg(feature = "ash")]
         if !gpu_executed {
-            if let Ok(runner) = AshRunner::new() {
-                run_sort_test(&runner, data, test_type, order)?;
+            if let Ok(runner) = AshRunner::with_config(config) {
+                run_sort_test(&runner, data, test_type, order, config.profiling)?;
                 gpu_executed = true;
-            } else if let Err(e) = AshRunner::new() {
+            } else if let Err(e) = AshRunner::with_config(config) {
                 eprintln!("  Vulkan initialization failed: {e}");
             }
         }
@@ -141,7 +157,42 @@ where
     Ok(())
 }
 
+fn print_devices() {
+    let devices = runners::list_devices(&RunnerConfig::default());
+    if devices.is_empty() {
+        println!("No GPU devices found");
+    }
+    for device in devices {
+        println!("{device}");
+    }
+}
+
 fn main() -> Result<()> {
+    if std::env::args().any(|arg| arg == "--list-devices") {
+        print_devices();
+        return Ok(());
+    }
+
+    // `--software` runs the GPU backends on a software adapter (lavapipe, SwiftShader)
+    // `--debug` enables Vulkan validation layers for the ash backend
+    // `--profile` prints GPU timings of every sort
+    // `--spirv <path>` and `--ptx <path>` load the kernel from a file instead of the binary
+    let kernel_path = |flag: &str| {
+        let mut args = std::env::args().skip_while(|arg| arg != flag);
+        args.nth(1).map(KernelSource::file)
+    };
+    let mut config = RunnerConfig::new()
+        .force_software_adapter(std::env::args().any(|arg| arg == "--software"))
+        .debug(std::env::args().any(|arg| arg == "--debug"))
+        .profiling(std::env::args().any(|arg| arg == "--profile"))
+        .pipeline_cache_dir(std::env::temp_dir().join("rust-gpu-chimera-pipeline-cache"));
+    if let Some(source) = kernel_path("--spirv") {
+        config = config.spirv_kernel(source);
+    }
+    if let Some(source) = kernel_path("--ptx") {
+        config = config.ptx_kernel(source);
+    }
+
     print_header();
 
     print_test_header("Demo 1: Sorting 1000 u32 elements");
@@ -149,7 +200,7 @@ fn main() -> Result<()> {
     for (i, v) in u32_data.iter_mut().enumerate() {
         *v = ((i * 31337 + 42) % 1000) as u32;
     }
-    run_test_on_backend(&mut u32_data, "u32", SortOrder::Ascending)?;
+    run_test_on_backend(&config, &mut u32_data, "u32", SortOrder::Ascending)?;
 
     print_test_header("Demo 2: Sorting u32 with special values");
     let mut u32_special = vec![
@@ -170,14 +221,19 @@ fn main() -> Result<()> {
         100,
         50,
     ];
-    run_test_on_backend(&mut u32_special, "u32 special", SortOrder::Ascending)?;
+    run_test_on_backend(
+        &config,
+        &mut u32_special,
+        "u32 special",
+        SortOrder::Ascending,
+    )?;
 
     print_test_header("Demo 3: Sorting 1000 i32 elements");
     let mut i32_data = vec![0i32; 1000];
     for (i, v) in i32_data.iter_mut().enumerate() {
         *v = ((i as i32 * 31337 - 500000) % 2000) - 1000;
     }
-    run_test_on_backend(&mut i32_data, "i32", SortOrder::Ascending)?;
+    run_test_on_backend(&config, &mut i32_data, "i32", SortOrder::Ascending)?;
 
     print_test_header("Demo 4: Sorting i32 with special values");
     let mut i32_special = vec![
@@ -198,14 +254,19 @@ fn main() -> Result<()> {
         -100,
         50,
     ];
-    run_test_on_backend(&mut i32_special, "i32 special", SortOrder::Ascending)?;
+    run_test_on_backend(
+        &config,
+        &mut i32_special,
+        "i32 special",
+        SortOrder::Ascending,
+    )?;
 
     print_test_header("Demo 5: Sorting 1000 f32 elements");
     let mut f32_data = vec![0.0f32; 1000];
     for (i, v) in f32_data.iter_mut().enumerate() {
         *v = ((i as f32 * std::f32::consts::PI) - 500.0) * 0.123;
     }
-    run_test_on_backend(&mut f32_data, "f32", SortOrder::Ascending)?;
+    run_test_on_backend(&config, &mut f32_data, "f32", SortOrder::Ascending)?;
 
     print_test_header("Demo 6: Sorting f32 with special values");
     let mut f32_special = vec![
@@ -226,15 +287,20 @@ fn main() -> Result<()> {
         -1e10,
         0.1,
     ];
-    run_test_on_backend(&mut f32_special, "f32 special", SortOrder::Ascending)?;
+    run_test_on_backend(
+        &config,
+        &mut f32_special,
+        "f32 special",
+        SortOrder::Ascending,
+    )?;
 
     print_test_header("Demo 7: Sorting u32 descending");
     let u32_desc = vec![42u32, 7, 999, 0, 13, 256, 128, 511, 1, 64];
-    run_test_on_backend(&mut u32_desc.clone(), "u32", SortOrder::Descending)?;
+    run_test_on_backend(&config, &mut u32_desc.clone(), "u32", SortOrder::Descending)?;
 
     print_test_header("Demo 8: Sorting i32 descending with negatives");
     let i32_desc = vec![-42i32, 7, -999, 0, 13, -256, 128, -1, 100, -100];
-    run_test_on_backend(&mut i32_desc.clone(), "i32", SortOrder::Descending)?;
+    run_test_on_backend(&config, &mut i32_desc.clone(), "i32", SortOrder::Descending)?;
 
     print_test_header("Demo 9: Sorting f32 descending with special values");
     let f32_desc = vec![
@@ -250,7 +316,7 @@ fn main() -> Result<()> {
         f32::MAX,
         f32::MIN,
     ];
-    run_test_on_backend(&mut f32_desc.clone(), "f32", SortOrder::Descending)?;
+    run_test_on_backend(&config, &mut f32_desc.clone(), "f32", SortOrder::Descending)?;
 
     println!("\n═══════════════════════════════════════════════════");
     println!("All demos completed successfully! 🎉");
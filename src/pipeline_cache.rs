@@ -0,0 +1,164 @@
+//! Pipeline cache files shared by the Vulkan-based runners
+//!
+//! Driver cache blobs are stored behind a small header that records which
+//! device and driver produced them. Files written by another device or driver
+//! version, or that fail their checksum, are deleted instead of being handed
+//! to the driver.
+
+use crate::error::Result;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 8] = b"CHIMPSOC";
+const FORMAT_VERSION: u32 = 1;
+
+/// Fixed part of the header: magic, format version, identity length, data length, checksum
+const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 8;
+
+/// 64-bit FNV-1a, enough to catch truncated or bit-flipped files
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A pipeline cache file for one device and driver
+#[derive(Clone, Debug)]
+pub struct PipelineCacheFile {
+    path: PathBuf,
+    identity: Vec<u8>,
+}
+
+impl PipelineCacheFile {
+    /// Cache file `name` in `dir`, valid only for the device and driver described by `identity`
+    ///
+    /// `identity` should contain everything that invalidates the cache when it
+    /// changes, such as the device UUID and driver version.
+    pub fn new(dir: &Path, name: &str, identity: Vec<u8>) -> Self {
+        Self {
+            path: dir.join(format!("{name}.bin")),
+            identity,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read the cached data, deleting the file if it is corrupt or belongs to another device
+    pub fn load(&self) -> Option<Vec<u8>> {
+        let bytes = std::fs::read(&self.path).ok()?;
+        let data = self.parse(&bytes);
+        if data.is_none() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        data
+    }
+
+    /// Write `data` to the cache file, replacing it atomically
+    pub fn store(&self, data: &[u8]) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.identity.len() + data.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.identity.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&checksum(data).to_le_bytes());
+        bytes.extend_from_slice(&self.identity);
+        bytes.extend_from_slice(data);
+
+        // Write beside the target and rename so readers never see a partial file
+        let temp = self
+            .path
+            .with_extension(format!("tmp{}", std::process::id()));
+        std::fs::write(&temp, &bytes)?;
+        std::fs::rename(&temp, &self.path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp);
+        })?;
+        Ok(())
+    }
+
+    fn parse(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        let (header, rest) = bytes.split_at_checked(HEADER_LEN)?;
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+
+        if &header[..8] != MAGIC || u32_at(8) != FORMAT_VERSION {
+            return None;
+        }
+        let identity_len = u32_at(12) as usize;
+        let data_len = u64_at(16);
+        let (identity, data) = rest.split_at_checked(identity_len)?;
+
+        (identity == self.identity.as_slice()
+            && data.len() as u64 == data_len
+            && checksum(data) == u64_at(24))
+        .then(|| data.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PipelineCacheFile;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "chimera-pipeline-cache-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = temp_dir("round-trip");
+        let cache = PipelineCacheFile::new(&dir, "device", b"uuid-1 driver-1".to_vec());
+        assert_eq!(cache.load(), None);
+
+        cache.store(b"pipeline data").unwrap();
+        assert_eq!(cache.load().as_deref(), Some(&b"pipeline data"[..]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_mismatched_identity_is_discarded() {
+        let dir = temp_dir("mismatch");
+        PipelineCacheFile::new(&dir, "device", b"driver-1".to_vec())
+            .store(b"old driver data")
+            .unwrap();
+
+        let cache = PipelineCacheFile::new(&dir, "device", b"driver-2".to_vec());
+        assert_eq!(cache.load(), None);
+        assert!(!cache.path().exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_file_is_discarded() {
+        let dir = temp_dir("corrupt");
+        let cache = PipelineCacheFile::new(&dir, "device", b"uuid".to_vec());
+
+        cache.store(b"pipeline data").unwrap();
+        let mut bytes = std::fs::read(cache.path()).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        std::fs::write(cache.path(), &bytes).unwrap();
+        assert_eq!(cache.load(), None);
+        assert!(!cache.path().exists());
+
+        // Truncated files, including ones shorter than the header
+        cache.store(b"pipeline data").unwrap();
+        let bytes = std::fs::read(cache.path()).unwrap();
+        std::fs::write(cache.path(), &bytes[..bytes.len() - 3]).unwrap();
+        assert_eq!(cache.load(), None);
+        std::fs::write(cache.path(), &bytes[..5]).unwrap();
+        assert_eq!(cache.load(), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+//! Per-pass timing of a sort
+//!
+//! [`SortRunner::sort_profiled`](crate::SortRunner::sort_profiled) returns a
+//! [`SortProfile`]. GPU runners fill it from timestamp queries or CUDA events
+//! where the device supports them; otherwise it is measured with wall-clock
+//! time on the host. Host-side spans of the sort are recorded as well, so a
+//! profile can be exported with [`ChromeTrace`](crate::trace::ChromeTrace).
+
+use crate::{bitonic_passes, error::Result, SortRunner};
+use shared::{BitonicParams, SortOrder, SortableKey};
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+// `std::time::Instant::now` panics on wasm32-unknown-unknown
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+/// How the durations of a [`SortProfile`] were measured
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimingSource {
+    /// Vulkan or WebGPU timestamp queries
+    GpuTimestamps,
+    /// CUDA events on the runner's stream
+    CudaEvents,
+    /// `Instant` on the host, including dispatch overhead
+    WallClock,
+}
+
+impl Display for TimingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TimingSource::GpuTimestamps => "GPU timestamps",
+            TimingSource::CudaEvents => "CUDA events",
+            TimingSource::WallClock => "wall clock",
+        })
+    }
+}
+
+/// Duration of one bitonic pass
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PassTiming {
+    pub stage: u32,
+    pub pass_of_stage: u32,
+    pub duration: Duration,
+}
+
+impl PassTiming {
+    pub fn new(params: &BitonicParams, duration: Duration) -> Self {
+        Self {
+            stage: params.stage.as_u32(),
+            pass_of_stage: params.pass_of_stage.as_u32(),
+            duration,
+        }
+    }
+}
+
+/// A host-side step of a sort, relative to the start of the sort
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HostSpan {
+    pub name: &'static str,
+    pub start: Duration,
+    pub duration: Duration,
+    /// `(stage, pass_of_stage)` of `execute_kernel_pass` spans
+    pub pass: Option<(u32, u32)>,
+}
+
+/// Records [`HostSpan`]s relative to the moment it was created
+///
+/// Runners overriding [`SortRunner::sort_profiled`] use it to fill
+/// [`SortProfile::host_spans`].
+pub struct SpanRecorder {
+    origin: Instant,
+    spans: Vec<HostSpan>,
+}
+
+impl SpanRecorder {
+    pub fn start() -> Self {
+        Self {
+            origin: Instant::now(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// Time since the recorder was created
+    pub fn elapsed(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    /// Run `f` as a span called `name`
+    pub fn time<R>(&mut self, name: &'static str, f: impl FnOnce() -> R) -> R {
+        self.time_span(name, None, f)
+    }
+
+    /// Run `f` as the `execute_kernel_pass` span of `params`
+    pub fn time_pass<R>(&mut self, params: &BitonicParams, f: impl FnOnce() -> R) -> R {
+        let pass = (params.stage.as_u32(), params.pass_of_stage.as_u32());
+        self.time_span("execute_kernel_pass", Some(pass), f)
+    }
+
+    fn time_span<R>(
+        &mut self,
+        name: &'static str,
+        pass: Option<(u32, u32)>,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        let start = self.elapsed();
+        let result = f();
+        self.push(name, start, pass);
+        result
+    }
+
+    /// Record a span called `name` from `start` until now, for steps that cannot run in a closure
+    pub fn record(&mut self, name: &'static str, start: Duration) {
+        self.push(name, start, None);
+    }
+
+    fn push(&mut self, name: &'static str, start: Duration, pass: Option<(u32, u32)>) {
+        self.spans.push(HostSpan {
+            name,
+            start,
+            duration: self.elapsed() - start,
+            pass,
+        });
+    }
+
+    /// Duration of the most recent span
+    pub fn last(&self) -> Duration {
+        self.spans
+            .last()
+            .map_or(Duration::ZERO, |span| span.duration)
+    }
+
+    /// Store the spans and the total time in `profile`
+    pub fn finish(self, profile: &mut SortProfile) {
+        profile.wall_time = self.elapsed();
+        profile.host_spans = self.spans;
+    }
+}
+
+/// Where the time of one sort went
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SortProfile {
+    pub source: TimingSource,
+    /// Moving the keys to the device; key conversion for wall-clock profiles
+    pub upload: Duration,
+    /// Every (stage, pass) in dispatch order
+    pub passes: Vec<PassTiming>,
+    /// Moving the sorted keys back; key conversion for wall-clock profiles
+    pub download: Duration,
+    /// Host time of the whole call, including overhead the other fields miss
+    pub wall_time: Duration,
+    /// Host-side steps in the order they ran
+    pub host_spans: Vec<HostSpan>,
+    /// When, relative to the start of the sort, the host handed work to the device
+    ///
+    /// Device clocks are not correlated with the host clock, so device timings
+    /// are placed back to back from this point.
+    pub device_start: Duration,
+}
+
+impl SortProfile {
+    pub fn new(source: TimingSource) -> Self {
+        Self {
+            source,
+            upload: Duration::ZERO,
+            passes: Vec::new(),
+            download: Duration::ZERO,
+            wall_time: Duration::ZERO,
+            host_spans: Vec::new(),
+            device_start: Duration::ZERO,
+        }
+    }
+
+    /// Total time spent in the sort kernel
+    pub fn kernel_time(&self) -> Duration {
+        self.passes.iter().map(|pass| pass.duration).sum()
+    }
+
+    /// Kernel time of one bitonic stage
+    pub fn stage_time(&self, stage: u32) -> Duration {
+        self.passes
+            .iter()
+            .filter(|pass| pass.stage == stage)
+            .map(|pass| pass.duration)
+            .sum()
+    }
+
+    /// Upload, kernel and download time together
+    pub fn device_time(&self) -> Duration {
+        self.upload + self.kernel_time() + self.download
+    }
+}
+
+impl Display for SortProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "upload {:?}, {} passes {:?}, download {:?} ({}; {:?} wall)",
+            self.upload,
+            self.passes.len(),
+            self.kernel_time(),
+            self.download,
+            self.source,
+            self.wall_time
+        )
+    }
+}
+
+/// Duration of `ticks` timestamp ticks of `period_ns` nanoseconds each
+pub fn ticks_to_duration(ticks: u64, period_ns: f64) -> Duration {
+    Duration::from_nanos((ticks as f64 * period_ns).round() as u64)
+}
+
+/// Sort `data` pass by pass through [`SortRunner::execute_kernel_pass`], timing each on the host
+pub fn wall_clock_profile<R, T>(runner: &R, data: &mut [T], order: SortOrder) -> Result<SortProfile>
+where
+    R: SortRunner,
+    T: SortableKey,
+{
+    let mut spans = SpanRecorder::start();
+    let mut profile = SortProfile::new(TimingSource::WallClock);
+    if data.len() > 1 {
+        runner
+            .capabilities()
+            .check_sort(data.len(), std::mem::size_of::<T>())?;
+
+        let (mut keys, len) = spans.time("prepare_data", || runner.prepare_data(data));
+        profile.upload = spans.last();
+        spans.time("pad_data", || runner.pad_data(&mut keys, len, order));
+        profile.upload += spans.last();
+
+        profile.device_start = spans.elapsed();
+        for params in bitonic_passes(keys.len() as u32, order) {
+            spans.time_pass(&params, || runner.execute_kernel_pass(&mut keys, params))?;
+            profile.passes.push(PassTiming::new(&params, spans.last()));
+        }
+
+        keys.truncate(len);
+        spans.time("finalize_data", || runner.finalize_data(&keys, data));
+        profile.download = spans.last();
+    }
+    spans.finish(&mut profile);
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ticks_to_duration, wall_clock_profile, TimingSource};
+    use crate::{bitonic_passes, CpuRunner};
+    use shared::SortOrder;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ticks_to_duration() {
+        assert_eq!(ticks_to_duration(1000, 1.0), Duration::from_micros(1));
+        assert_eq!(ticks_to_duration(3, 83.333), Duration::from_nanos(250));
+        assert_eq!(ticks_to_duration(0, 52.08), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_wall_clock_profile() {
+        let mut data = vec![5i32, -3, 9, 1, 0, -7];
+        let profile = wall_clock_profile(&CpuRunner, &mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, vec![-7, -3, 0, 1, 5, 9]);
+
+        assert_eq!(profile.source, TimingSource::WallClock);
+        let expected: Vec<(u32, u32)> = bitonic_passes(8, SortOrder::Ascending)
+            .iter()
+            .map(|params| (params.stage.as_u32(), params.pass_of_stage.as_u32()))
+            .collect();
+        let recorded: Vec<(u32, u32)> = profile
+            .passes
+            .iter()
+            .map(|pass| (pass.stage, pass.pass_of_stage))
+            .collect();
+        assert_eq!(recorded, expected);
+        assert_eq!(
+            profile.kernel_time(),
+            (0..3).map(|stage| profile.stage_time(stage)).sum()
+        );
+        assert!(profile.wall_time >= profile.device_time());
+
+        let names: Vec<&str> = profile.host_spans.iter().map(|span| span.name).collect();
+        assert_eq!(names[..2], ["prepare_data", "pad_data"]);
+        assert_eq!(names.last(), Some(&"finalize_data"));
+        assert_eq!(
+            names
+                .iter()
+                .filter(|&&name| name == "execute_kernel_pass")
+                .count(),
+            expected.len()
+        );
+        assert!(profile
+            .host_spans
+            .windows(2)
+            .all(|pair| pair[0].start + pair[0].duration <= pair[1].start));
+    }
+}
@@ -0,0 +1,967 @@
+//! Minimal SPIR-V reflection
+//!
+//! Reads the compute entry points of a module together with their workgroup
+//! size, buffer bindings and push-constant block size; enough to build
+//! pipeline layouts without hand-writing them. `build.rs` includes this file
+//! as well to generate the kernel manifest, so it depends on `std` only.
+//!
+//! It also rewrites modules where runners need it: [`specialize`] freezes
+//! specialization constants, [`tunable_workgroup_size`] turns a literal
+//! workgroup size into one, and [`freeze_workgroup_size`] turns it back.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT_TRUE: u32 = 41;
+const OP_CONSTANT_FALSE: u32 = 42;
+const OP_CONSTANT: u32 = 43;
+const OP_CONSTANT_COMPOSITE: u32 = 44;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_SPEC_CONSTANT_COMPOSITE: u32 = 51;
+const OP_SPEC_CONSTANT_OP: u32 = 52;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_EXECUTION_MODE_ID: u32 = 331;
+
+/// `OpTypeVoid` to `OpTypeForwardPointer`, the opcodes declaring types
+const TYPE_OPCODES: std::ops::RangeInclusive<u32> = 19..=39;
+
+const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_NON_WRITABLE: u32 = 24;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+/// A module that could not be reflected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReflectError(pub String);
+
+impl Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+/// Kind of buffer bound to a descriptor
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BindingType {
+    StorageBuffer { read_only: bool },
+    UniformBuffer,
+}
+
+/// A buffer an entry point accesses through a descriptor
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BindingLayout {
+    pub set: u32,
+    pub binding: u32,
+    pub ty: BindingType,
+}
+
+/// A scalar specialization constant and its default value
+///
+/// Booleans default to 0 or 1; wider constants report their low 32 bits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpecConstant {
+    pub id: u32,
+    pub default: u32,
+}
+
+/// Layout of a push-constant or uniform block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockLayout {
+    /// Size in bytes of the whole block
+    pub size: u32,
+    /// Offset and size in bytes of each member in declaration order, with
+    /// nested structs flattened into their members
+    ///
+    /// Flattening makes a block compare equal to the Rust struct it wraps, as
+    /// rust-gpu puts push constants and uniforms inside a one-member block.
+    pub members: Vec<(u32, u32)>,
+}
+
+/// What a compute entry point needs from its pipeline layout
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryPointInfo {
+    pub name: String,
+    /// Workgroup size from the `LocalSize` literals, or the values of the
+    /// `LocalSizeId` constants with spec constants at their defaults
+    pub workgroup_size: Option<[u32; 3]>,
+    /// `SpecId` of the constant giving the `LocalSizeId` width, if it is one
+    pub workgroup_size_spec_id: Option<u32>,
+    /// Sorted by set, then binding
+    pub bindings: Vec<BindingLayout>,
+    /// Push-constant block, if the entry point reads one
+    pub push_constants: Option<BlockLayout>,
+    /// Block of each uniform buffer in `bindings`, by set and binding
+    pub uniform_blocks: Vec<((u32, u32), BlockLayout)>,
+    /// Specialization constants of the whole module, sorted by id, as SPIR-V
+    /// does not tie them to entry points
+    pub spec_constants: Vec<SpecConstant>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar { bytes: u32 },
+    Vector { component: u32, count: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+/// Decorations of an id or struct member: the decoration and its first literal
+type Decorations = Vec<(u32, Option<u32>)>;
+
+/// Everything read from the module, indexed by result id
+#[derive(Default)]
+struct Module {
+    entry_points: Vec<(String, u32, Vec<u32>)>,
+    local_sizes: HashMap<u32, [u32; 3]>,
+    /// Constant ids of each `LocalSizeId` execution mode, by function
+    local_size_ids: HashMap<u32, [u32; 3]>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    /// Result id and default value of each scalar specialization constant
+    spec_constants: Vec<(u32, u32)>,
+    /// Variable id to pointer type id and storage class
+    variables: HashMap<u32, (u32, u32)>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), Decorations>,
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self, ReflectError> {
+        let mut module = Module::default();
+        let mut offset = 5;
+        while offset < words.len() {
+            let word_count = (words[offset] >> 16) as usize;
+            let opcode = words[offset] & 0xffff;
+            if word_count == 0 || offset + word_count > words.len() {
+                return Err(ReflectError(format!(
+                    "truncated instruction at word {offset}"
+                )));
+            }
+            let operands = &words[offset + 1..offset + word_count];
+            module.instruction(opcode, operands)?;
+            offset += word_count;
+        }
+        Ok(module)
+    }
+
+    fn instruction(&mut self, opcode: u32, operands: &[u32]) -> Result<(), ReflectError> {
+        let operand = |index: usize| {
+            operands
+                .get(index)
+                .copied()
+                .ok_or_else(|| ReflectError(format!("opcode {opcode} is missing operand {index}")))
+        };
+        match opcode {
+            OP_ENTRY_POINT if operand(0)? == EXECUTION_MODEL_GL_COMPUTE => {
+                let function = operand(1)?;
+                let (name, name_words) = literal_string(&operands[2..])?;
+                let interface = operands[2 + name_words..].to_vec();
+                self.entry_points.push((name, function, interface));
+            }
+            OP_EXECUTION_MODE if operand(1)? == EXECUTION_MODE_LOCAL_SIZE => {
+                self.local_sizes
+                    .insert(operand(0)?, [operand(2)?, operand(3)?, operand(4)?]);
+            }
+            OP_EXECUTION_MODE_ID if operand(1)? == EXECUTION_MODE_LOCAL_SIZE_ID => {
+                self.local_size_ids
+                    .insert(operand(0)?, [operand(2)?, operand(3)?, operand(4)?]);
+            }
+            OP_TYPE_INT | OP_TYPE_FLOAT => {
+                let bytes = operand(1)? / 8;
+                self.types.insert(operand(0)?, Type::Scalar { bytes });
+            }
+            OP_TYPE_VECTOR | OP_TYPE_MATRIX => {
+                let (component, count) = (operand(1)?, operand(2)?);
+                self.types
+                    .insert(operand(0)?, Type::Vector { component, count });
+            }
+            OP_TYPE_ARRAY => {
+                let (element, length) = (operand(1)?, operand(2)?);
+                self.types
+                    .insert(operand(0)?, Type::Array { element, length });
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types.insert(operand(0)?, Type::RuntimeArray);
+            }
+            OP_TYPE_STRUCT => {
+                let members = operands[1..].to_vec();
+                self.types.insert(operand(0)?, Type::Struct { members });
+            }
+            OP_TYPE_POINTER => {
+                let pointee = operand(2)?;
+                self.types.insert(operand(0)?, Type::Pointer { pointee });
+            }
+            OP_CONSTANT => {
+                self.constants.insert(operand(1)?, operand(2)?);
+            }
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE => {
+                let default = u32::from(opcode == OP_SPEC_CONSTANT_TRUE);
+                self.spec_constants.push((operand(1)?, default));
+            }
+            OP_SPEC_CONSTANT => {
+                self.spec_constants.push((operand(1)?, operand(2)?));
+            }
+            OP_VARIABLE => {
+                self.variables
+                    .insert(operand(1)?, (operand(0)?, operand(2)?));
+            }
+            OP_DECORATE => {
+                let decoration = (operand(1)?, operands.get(2).copied());
+                self.decorations
+                    .entry(operand(0)?)
+                    .or_default()
+                    .push(decoration);
+            }
+            OP_MEMBER_DECORATE => {
+                let decoration = (operand(2)?, operands.get(3).copied());
+                self.member_decorations
+                    .entry((operand(0)?, operand(1)?))
+                    .or_default()
+                    .push(decoration);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn decoration(&self, id: u32, decoration: u32) -> Option<Option<u32>> {
+        self.decorations
+            .get(&id)?
+            .iter()
+            .find(|(kind, _)| *kind == decoration)
+            .map(|(_, value)| *value)
+    }
+
+    fn member_decoration(&self, id: u32, member: u32, decoration: u32) -> Option<Option<u32>> {
+        self.member_decorations
+            .get(&(id, member))?
+            .iter()
+            .find(|(kind, _)| *kind == decoration)
+            .map(|(_, value)| *value)
+    }
+
+    /// Value of the scalar constant `id`; spec constants whose `SpecId` is
+    /// in `values` take that value, the others their default
+    fn constant_value(&self, id: u32, values: &[(u32, u32)]) -> Option<u32> {
+        if let Some(&(_, default)) = self.spec_constants.iter().find(|(result, _)| *result == id) {
+            let spec_id = self.decoration(id, DECORATION_SPEC_ID).flatten();
+            return Some(
+                values
+                    .iter()
+                    .find(|(value_id, _)| Some(*value_id) == spec_id)
+                    .map_or(default, |(_, value)| *value),
+            );
+        }
+        self.constants.get(&id).copied()
+    }
+
+    /// Workgroup size of `function` with spec constants set from `values`
+    fn workgroup_size(
+        &self,
+        function: u32,
+        values: &[(u32, u32)],
+    ) -> Result<Option<[u32; 3]>, ReflectError> {
+        if let Some(size) = self.local_sizes.get(&function) {
+            return Ok(Some(*size));
+        }
+        let Some(ids) = self.local_size_ids.get(&function) else {
+            return Ok(None);
+        };
+        let mut size = [0; 3];
+        for (dimension, &id) in size.iter_mut().zip(ids) {
+            *dimension = self.constant_value(id, values).ok_or_else(|| {
+                ReflectError(format!(
+                    "LocalSizeId operand %{id} is not a scalar constant"
+                ))
+            })?;
+        }
+        Ok(Some(size))
+    }
+
+    fn ty(&self, id: u32) -> Result<&Type, ReflectError> {
+        self.types
+            .get(&id)
+            .ok_or_else(|| ReflectError(format!("type %{id} is not a supported type")))
+    }
+
+    /// Size in bytes of type `id` as laid out by its `Offset` and `ArrayStride` decorations
+    fn size_of(&self, id: u32) -> Result<u32, ReflectError> {
+        Ok(match self.ty(id)? {
+            Type::Scalar { bytes } => *bytes,
+            Type::Vector { component, count } => count * self.size_of(*component)?,
+            Type::Array { element, length } => {
+                let length = *self.constants.get(length).ok_or_else(|| {
+                    ReflectError(format!("array length %{length} is not a constant"))
+                })?;
+                let stride = match self.decoration(id, DECORATION_ARRAY_STRIDE).flatten() {
+                    Some(stride) => stride,
+                    None => self.size_of(*element)?,
+                };
+                length * stride
+            }
+            Type::RuntimeArray => 0,
+            Type::Struct { .. } => self.block_layout(id)?.size,
+            Type::Pointer { .. } => {
+                return Err(ReflectError(format!("type %{id} is a pointer")));
+            }
+        })
+    }
+
+    /// Specialization constants that have a `SpecId`, sorted by it
+    fn spec_constants(&self) -> Vec<SpecConstant> {
+        let mut constants: Vec<SpecConstant> = self
+            .spec_constants
+            .iter()
+            .filter_map(|&(result, default)| {
+                let id = self.decoration(result, DECORATION_SPEC_ID).flatten()?;
+                Some(SpecConstant { id, default })
+            })
+            .collect();
+        constants.sort_by_key(|constant| constant.id);
+        constants
+    }
+
+    /// Member offsets and sizes of the struct `id`
+    fn block_layout(&self, id: u32) -> Result<BlockLayout, ReflectError> {
+        let Type::Struct { members } = self.ty(id)? else {
+            return Err(ReflectError(format!("type %{id} is not a struct")));
+        };
+        let mut layout = BlockLayout {
+            size: 0,
+            members: Vec::with_capacity(members.len()),
+        };
+        for (index, &member) in members.iter().enumerate() {
+            let offset = self
+                .member_decoration(id, index as u32, DECORATION_OFFSET)
+                .flatten()
+                .unwrap_or(layout.size);
+            if let Type::Struct { .. } = self.ty(member)? {
+                let nested = self.block_layout(member)?;
+                layout.members.extend(
+                    nested
+                        .members
+                        .iter()
+                        .map(|&(nested_offset, size)| (offset + nested_offset, size)),
+                );
+                layout.size = layout.size.max(offset + nested.size);
+            } else {
+                let size = self.size_of(member)?;
+                layout.members.push((offset, size));
+                layout.size = layout.size.max(offset + size);
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Whether every member of the block struct `id` is read-only
+    fn read_only_block(&self, id: u32) -> Result<bool, ReflectError> {
+        match self.ty(id)? {
+            Type::Struct { members } => Ok((0..members.len() as u32).all(|member| {
+                self.member_decoration(id, member, DECORATION_NON_WRITABLE)
+                    .is_some()
+            })),
+            _ => Ok(false),
+        }
+    }
+
+    fn entry_point(
+        &self,
+        name: &str,
+        function: u32,
+        interface: &[u32],
+    ) -> Result<EntryPointInfo, ReflectError> {
+        let mut bindings = Vec::new();
+        let mut push_constants = None;
+        let mut uniform_blocks = Vec::new();
+
+        for id in interface {
+            let Some(&(pointer, storage_class)) = self.variables.get(id) else {
+                continue;
+            };
+            let Type::Pointer { pointee, .. } = *self.ty(pointer)? else {
+                return Err(ReflectError(format!("variable %{id} is not a pointer")));
+            };
+
+            match storage_class {
+                STORAGE_CLASS_PUSH_CONSTANT => {
+                    push_constants = Some(self.block_layout(pointee)?);
+                }
+                STORAGE_CLASS_STORAGE_BUFFER | STORAGE_CLASS_UNIFORM => {
+                    let set = self.decoration(*id, DECORATION_DESCRIPTOR_SET).flatten();
+                    let binding = self.decoration(*id, DECORATION_BINDING).flatten();
+                    let (Some(set), Some(binding)) = (set, binding) else {
+                        return Err(ReflectError(format!(
+                            "buffer %{id} of {name} has no descriptor set or binding"
+                        )));
+                    };
+
+                    // Uniform blocks decorated BufferBlock are pre-1.3 storage buffers
+                    let storage = storage_class == STORAGE_CLASS_STORAGE_BUFFER
+                        || self.decoration(pointee, DECORATION_BUFFER_BLOCK).is_some();
+                    let ty = if storage {
+                        let read_only = self.decoration(*id, DECORATION_NON_WRITABLE).is_some()
+                            || self.read_only_block(pointee)?;
+                        BindingType::StorageBuffer { read_only }
+                    } else if self.decoration(pointee, DECORATION_BLOCK).is_some() {
+                        uniform_blocks.push(((set, binding), self.block_layout(pointee)?));
+                        BindingType::UniformBuffer
+                    } else {
+                        return Err(ReflectError(format!(
+                            "uniform %{id} of {name} is not a block"
+                        )));
+                    };
+                    bindings.push(BindingLayout { set, binding, ty });
+                }
+                STORAGE_CLASS_UNIFORM_CONSTANT
+                    if self.decoration(*id, DECORATION_BINDING).is_some() =>
+                {
+                    return Err(ReflectError(format!(
+                        "{name} binds an image or sampler, which runners do not support"
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        Ok(EntryPointInfo {
+            name: name.to_string(),
+            workgroup_size: self.workgroup_size(function, &[])?,
+            workgroup_size_spec_id: self
+                .local_size_ids
+                .get(&function)
+                .and_then(|[width, ..]| self.decoration(*width, DECORATION_SPEC_ID).flatten()),
+            bindings,
+            push_constants,
+            uniform_blocks,
+            spec_constants: self.spec_constants(),
+        })
+    }
+}
+
+/// Decode a nul-terminated literal string, returning it and the number of words it used
+fn literal_string(words: &[u32]) -> Result<(String, usize), ReflectError> {
+    let mut bytes = Vec::new();
+    for (index, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                let name = String::from_utf8(bytes)
+                    .map_err(|_| ReflectError("entry point name is not UTF-8".to_string()))?;
+                return Ok((name, index + 1));
+            }
+            bytes.push(byte);
+        }
+    }
+    Err(ReflectError("unterminated entry point name".to_string()))
+}
+
+/// Reflect every compute entry point of the SPIR-V module `words`
+///
+/// Only the interface lists of SPIR-V 1.4 and later name every global an
+/// entry point uses; older modules only report the ones listed.
+pub fn reflect(words: &[u32]) -> Result<Vec<EntryPointInfo>, ReflectError> {
+    if words.len() < 5 {
+        return Err(ReflectError("module has no SPIR-V header".to_string()));
+    }
+    let module = Module::parse(words)?;
+    module
+        .entry_points
+        .iter()
+        .map(|(name, function, interface)| module.entry_point(name, *function, interface))
+        .collect()
+}
+
+/// Freeze every specialization constant of the module `words` into a constant
+///
+/// Scalars whose `SpecId` is listed in `values` take that value, all others
+/// keep their default, and the `SpecId` decorations are dropped. This is for
+/// consumers that cannot specialize a module, such as naga's WGSL backend.
+pub fn specialize(words: &[u32], values: &[(u32, u32)]) -> Result<Vec<u32>, ReflectError> {
+    let words = &freeze_workgroup_size(words, values)?;
+    let module = Module::parse(words)?;
+    let value_of = |result: u32| {
+        let id = module.decoration(result, DECORATION_SPEC_ID).flatten()?;
+        values
+            .iter()
+            .find(|(spec_id, _)| *spec_id == id)
+            .map(|(_, value)| *value)
+    };
+
+    let mut specialized = words[..5].to_vec();
+    let mut offset = 5;
+    while offset < words.len() {
+        let word_count = (words[offset] >> 16) as usize;
+        let opcode = words[offset] & 0xffff;
+        let mut instruction = words[offset..offset + word_count].to_vec();
+        offset += word_count;
+
+        let header = |opcode: u32| ((word_count as u32) << 16) | opcode;
+        match opcode {
+            OP_DECORATE if instruction.get(2) == Some(&DECORATION_SPEC_ID) => continue,
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE => {
+                let value = value_of(instruction[2])
+                    .map_or(opcode == OP_SPEC_CONSTANT_TRUE, |value| value != 0);
+                instruction[0] = header(if value {
+                    OP_CONSTANT_TRUE
+                } else {
+                    OP_CONSTANT_FALSE
+                });
+            }
+            OP_SPEC_CONSTANT => {
+                if let Some(value) = value_of(instruction[2]) {
+                    if word_count != 4 {
+                        return Err(ReflectError(format!(
+                            "spec constant %{} is wider than 32 bits",
+                            instruction[2]
+                        )));
+                    }
+                    instruction[3] = value;
+                }
+                instruction[0] = header(OP_CONSTANT);
+            }
+            OP_SPEC_CONSTANT_COMPOSITE => instruction[0] = header(OP_CONSTANT_COMPOSITE),
+            OP_SPEC_CONSTANT_OP => {
+                return Err(ReflectError(format!(
+                    "spec constant %{} is an OpSpecConstantOp, which cannot be frozen",
+                    instruction.get(2).copied().unwrap_or_default()
+                )));
+            }
+            _ => {}
+        }
+        specialized.extend(instruction);
+    }
+    Ok(specialized)
+}
+
+/// Make the workgroup width of `entry_point` the specialization constant `spec_id`
+///
+/// The literal `LocalSize` becomes a `LocalSizeId` whose width is a spec
+/// constant defaulting to the old width, so pipelines can pick any size
+/// without a module per size. `LocalSizeId` needs SPIR-V 1.2, and Vulkan
+/// devices need `maintenance4` to accept it.
+pub fn tunable_workgroup_size(
+    words: &[u32],
+    entry_point: &str,
+    spec_id: u32,
+) -> Result<Vec<u32>, ReflectError> {
+    if words.len() < 5 {
+        return Err(ReflectError("module has no SPIR-V header".to_string()));
+    }
+    if words[1] < 0x0001_0200 {
+        return Err(ReflectError(
+            "LocalSizeId needs SPIR-V 1.2 or later".to_string(),
+        ));
+    }
+    let module = Module::parse(words)?;
+    let function = module
+        .entry_points
+        .iter()
+        .find(|(name, ..)| name == entry_point)
+        .map(|(_, function, _)| *function)
+        .ok_or_else(|| ReflectError(format!("the module has no entry point `{entry_point}`")))?;
+    let Some(&[width, height, depth]) = module.local_sizes.get(&function) else {
+        return Err(ReflectError(format!(
+            "{entry_point} has no literal LocalSize"
+        )));
+    };
+    if module
+        .spec_constants()
+        .iter()
+        .any(|constant| constant.id == spec_id)
+    {
+        return Err(ReflectError(format!("SpecId {spec_id} is already in use")));
+    }
+
+    // The three constants get fresh ids past the module's bound
+    let bound = words[3];
+    let ids = [bound, bound + 1, bound + 2];
+    let mut rewritten = words[..5].to_vec();
+    rewritten[3] = bound + 3;
+    let (mut decorated, mut declared) = (false, false);
+    let mut offset = 5;
+    while offset < words.len() {
+        let word_count = (words[offset] >> 16) as usize;
+        let opcode = words[offset] & 0xffff;
+        let mut instruction = words[offset..offset + word_count].to_vec();
+        offset += word_count;
+
+        // Annotations precede the types, so the `SpecId` goes before either
+        if !decorated
+            && (opcode == OP_DECORATE
+                || opcode == OP_MEMBER_DECORATE
+                || TYPE_OPCODES.contains(&opcode))
+        {
+            rewritten.extend([(4 << 16) | OP_DECORATE, ids[0], DECORATION_SPEC_ID, spec_id]);
+            decorated = true;
+        }
+        if opcode == OP_EXECUTION_MODE && instruction[1..3] == [function, EXECUTION_MODE_LOCAL_SIZE]
+        {
+            instruction = vec![
+                (6 << 16) | OP_EXECUTION_MODE_ID,
+                function,
+                EXECUTION_MODE_LOCAL_SIZE_ID,
+                ids[0],
+                ids[1],
+                ids[2],
+            ];
+        }
+        let uint = opcode == OP_TYPE_INT && instruction[2..] == [32, 0];
+        rewritten.extend(&instruction);
+        if uint && !declared {
+            let uint = instruction[1];
+            rewritten.extend([(4 << 16) | OP_SPEC_CONSTANT, uint, ids[0], width]);
+            rewritten.extend([(4 << 16) | OP_CONSTANT, uint, ids[1], height]);
+            rewritten.extend([(4 << 16) | OP_CONSTANT, uint, ids[2], depth]);
+            declared = true;
+        }
+    }
+    if !declared {
+        return Err(ReflectError(
+            "the module declares no 32-bit unsigned integer type".to_string(),
+        ));
+    }
+    Ok(rewritten)
+}
+
+/// Replace every `LocalSizeId` of the module `words` with the literal `LocalSize` it resolves to
+///
+/// Spec constants whose `SpecId` is listed in `values` take that value, all
+/// others their default. The constants stay declared, so the module can still
+/// be specialized otherwise. This is for consumers that do not accept
+/// `LocalSizeId`, such as Vulkan devices without `maintenance4` and naga.
+pub fn freeze_workgroup_size(
+    words: &[u32],
+    values: &[(u32, u32)],
+) -> Result<Vec<u32>, ReflectError> {
+    if words.len() < 5 {
+        return Err(ReflectError("module has no SPIR-V header".to_string()));
+    }
+    let module = Module::parse(words)?;
+    let mut frozen = words.to_vec();
+    let mut offset = 5;
+    while offset < frozen.len() {
+        let word_count = (frozen[offset] >> 16) as usize;
+        let opcode = frozen[offset] & 0xffff;
+        if opcode == OP_EXECUTION_MODE_ID
+            && word_count == 6
+            && frozen[offset + 2] == EXECUTION_MODE_LOCAL_SIZE_ID
+        {
+            let function = frozen[offset + 1];
+            if let Some(size) = module.workgroup_size(function, values)? {
+                frozen[offset] = (6 << 16) | OP_EXECUTION_MODE;
+                frozen[offset + 2] = EXECUTION_MODE_LOCAL_SIZE;
+                frozen[offset + 3..offset + 6].copy_from_slice(&size);
+            }
+        }
+        offset += word_count;
+    }
+    Ok(frozen)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Assemble instructions into a module after a SPIR-V 1.5 header
+    fn assemble(instructions: &[(u32, Vec<u32>)]) -> Vec<u32> {
+        let mut words = vec![0x0723_0203, 0x0001_0500, 0, 100, 0];
+        for (opcode, operands) in instructions {
+            words.push(((operands.len() as u32 + 1) << 16) | opcode);
+            words.extend(operands);
+        }
+        words
+    }
+
+    fn string(value: &str) -> Vec<u32> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+        bytes
+            .chunks(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    /// A compute shader shaped like the bitonic kernel: one storage buffer and
+    /// a push-constant block of `u32`s at `param_offsets`
+    pub(crate) fn bitonic_module(
+        workgroup_size: u32,
+        param_offsets: &[u32],
+        read_only: bool,
+    ) -> Vec<u32> {
+        assemble_bitonic(workgroup_size, param_offsets, read_only, false)
+    }
+
+    /// Like [`bitonic_module`], reading the params from a uniform buffer at binding 1
+    pub(crate) fn uniform_bitonic_module(workgroup_size: u32, param_offsets: &[u32]) -> Vec<u32> {
+        assemble_bitonic(workgroup_size, param_offsets, false, true)
+    }
+
+    fn assemble_bitonic(
+        workgroup_size: u32,
+        param_offsets: &[u32],
+        read_only: bool,
+        uniform: bool,
+    ) -> Vec<u32> {
+        let (main, uint, runtime_array, data_struct, data_pointer, data) = (1, 2, 3, 4, 5, 6);
+        let (params_struct, params_pointer, params, sort_order, params_block) = (7, 8, 9, 10, 11);
+        let (name, params_class) = if uniform {
+            ("bitonic_kernel_uniform", STORAGE_CLASS_UNIFORM)
+        } else {
+            ("bitonic_kernel", STORAGE_CLASS_PUSH_CONSTANT)
+        };
+
+        let mut entry_point = vec![EXECUTION_MODEL_GL_COMPUTE, main];
+        entry_point.extend(string(&format!("{name}_wg{workgroup_size}")));
+        entry_point.extend([data, params]);
+
+        let mut instructions = vec![
+            (OP_ENTRY_POINT, entry_point),
+            (
+                OP_EXECUTION_MODE,
+                vec![main, EXECUTION_MODE_LOCAL_SIZE, workgroup_size, 1, 1],
+            ),
+            (OP_DECORATE, vec![runtime_array, DECORATION_ARRAY_STRIDE, 4]),
+            (OP_DECORATE, vec![data_struct, DECORATION_BLOCK]),
+            (
+                OP_MEMBER_DECORATE,
+                vec![data_struct, 0, DECORATION_OFFSET, 0],
+            ),
+            (OP_DECORATE, vec![data, DECORATION_DESCRIPTOR_SET, 0]),
+            (OP_DECORATE, vec![data, DECORATION_BINDING, 0]),
+            // rust-gpu wraps the params struct in a one-member block
+            (OP_DECORATE, vec![params_block, DECORATION_BLOCK]),
+            (
+                OP_MEMBER_DECORATE,
+                vec![params_block, 0, DECORATION_OFFSET, 0],
+            ),
+            (OP_DECORATE, vec![sort_order, DECORATION_SPEC_ID, 0]),
+        ];
+        if uniform {
+            instructions.extend([
+                (OP_DECORATE, vec![params, DECORATION_DESCRIPTOR_SET, 0]),
+                (OP_DECORATE, vec![params, DECORATION_BINDING, 1]),
+            ]);
+        }
+        if read_only {
+            instructions.push((
+                OP_MEMBER_DECORATE,
+                vec![data_struct, 0, DECORATION_NON_WRITABLE],
+            ));
+        }
+        for (member, &offset) in param_offsets.iter().enumerate() {
+            instructions.push((
+                OP_MEMBER_DECORATE,
+                vec![params_struct, member as u32, DECORATION_OFFSET, offset],
+            ));
+        }
+        let mut params_members = vec![params_struct];
+        params_members.extend(param_offsets.iter().map(|_| uint));
+        instructions.extend([
+            (OP_TYPE_INT, vec![uint, 32, 0]),
+            (OP_TYPE_RUNTIME_ARRAY, vec![runtime_array, uint]),
+            (OP_TYPE_STRUCT, vec![data_struct, runtime_array]),
+            (
+                OP_TYPE_POINTER,
+                vec![data_pointer, STORAGE_CLASS_STORAGE_BUFFER, data_struct],
+            ),
+            (OP_SPEC_CONSTANT, vec![uint, sort_order, 2]),
+            (OP_TYPE_STRUCT, params_members),
+            (OP_TYPE_STRUCT, vec![params_block, params_struct]),
+            (
+                OP_TYPE_POINTER,
+                vec![params_pointer, params_class, params_block],
+            ),
+            (
+                OP_VARIABLE,
+                vec![data_pointer, data, STORAGE_CLASS_STORAGE_BUFFER],
+            ),
+            (OP_VARIABLE, vec![params_pointer, params, params_class]),
+        ]);
+        assemble(&instructions)
+    }
+
+    /// Remove the first occurrence of `instruction` from `words`
+    pub(crate) fn remove_instruction(mut words: Vec<u32>, instruction: &[u32]) -> Vec<u32> {
+        let start = words
+            .windows(instruction.len())
+            .position(|window| window == instruction)
+            .unwrap();
+        words.drain(start..start + instruction.len());
+        words
+    }
+
+    /// Decoration giving the bitonic module's sort-order constant its `SpecId`
+    pub(crate) const SORT_ORDER_SPEC_ID: [u32; 4] =
+        [(4 << 16) | OP_DECORATE, 10, DECORATION_SPEC_ID, 0];
+
+    pub(crate) const BITONIC_OFFSETS: [u32; 4] = [0, 4, 8, 12];
+
+    #[test]
+    fn test_reflect_bitonic_layout() {
+        let entry_points = reflect(&bitonic_module(64, &BITONIC_OFFSETS, false)).unwrap();
+        assert_eq!(
+            entry_points,
+            vec![EntryPointInfo {
+                name: "bitonic_kernel_wg64".to_string(),
+                workgroup_size: Some([64, 1, 1]),
+                workgroup_size_spec_id: None,
+                bindings: vec![BindingLayout {
+                    set: 0,
+                    binding: 0,
+                    ty: BindingType::StorageBuffer { read_only: false },
+                }],
+                push_constants: Some(BlockLayout {
+                    size: 16,
+                    members: vec![(0, 4), (4, 4), (8, 4), (12, 4)],
+                }),
+                uniform_blocks: vec![],
+                spec_constants: vec![SpecConstant { id: 0, default: 2 }],
+            }]
+        );
+
+        let uniform = reflect(&uniform_bitonic_module(64, &BITONIC_OFFSETS)).unwrap();
+        assert_eq!(uniform[0].name, "bitonic_kernel_uniform_wg64");
+        assert_eq!(uniform[0].push_constants, None);
+        assert_eq!(
+            uniform[0].bindings[1],
+            BindingLayout {
+                set: 0,
+                binding: 1,
+                ty: BindingType::UniformBuffer,
+            }
+        );
+        assert_eq!(
+            uniform[0].uniform_blocks,
+            vec![(
+                (0, 1),
+                BlockLayout {
+                    size: 16,
+                    members: vec![(0, 4), (4, 4), (8, 4), (12, 4)],
+                }
+            )]
+        );
+
+        let read_only = reflect(&bitonic_module(64, &BITONIC_OFFSETS, true)).unwrap();
+        assert_eq!(
+            read_only[0].bindings[0].ty,
+            BindingType::StorageBuffer { read_only: true }
+        );
+
+        // Spec constants without a SpecId cannot be set by pipelines
+        let unnamed = remove_instruction(
+            bitonic_module(64, &BITONIC_OFFSETS, false),
+            &SORT_ORDER_SPEC_ID,
+        );
+        assert_eq!(reflect(&unnamed).unwrap()[0].spec_constants, vec![]);
+    }
+
+    #[test]
+    fn test_specialize() {
+        let module = bitonic_module(64, &BITONIC_OFFSETS, false);
+        let sort_order = |value| [(4 << 16) | OP_CONSTANT, 2, 10, value];
+
+        let descending = specialize(&module, &[(0, 1)]).unwrap();
+        assert_eq!(reflect(&descending).unwrap()[0].spec_constants, vec![]);
+        assert!(descending.windows(4).any(|words| words == sort_order(1)));
+        assert_eq!(descending.len(), module.len() - SORT_ORDER_SPEC_ID.len());
+
+        // Constants without a value keep their default
+        let dynamic = specialize(&module, &[(7, 1)]).unwrap();
+        assert!(dynamic.windows(4).any(|words| words == sort_order(2)));
+        assert!(specialize(&module[..4], &[]).is_err());
+    }
+
+    #[test]
+    fn test_tunable_workgroup_size() {
+        let module = bitonic_module(256, &BITONIC_OFFSETS, false);
+        let tunable = tunable_workgroup_size(&module, "bitonic_kernel_wg256", 1).unwrap();
+        let entry_point = &reflect(&tunable).unwrap()[0];
+        assert_eq!(entry_point.workgroup_size, Some([256, 1, 1]));
+        assert_eq!(entry_point.workgroup_size_spec_id, Some(1));
+        assert_eq!(
+            entry_point.spec_constants,
+            vec![
+                SpecConstant { id: 0, default: 2 },
+                SpecConstant {
+                    id: 1,
+                    default: 256
+                },
+            ]
+        );
+        assert_eq!(tunable[3], module[3] + 3);
+
+        // Freezing resolves the width and keeps the constants specializable
+        let frozen = freeze_workgroup_size(&tunable, &[(1, 1024)]).unwrap();
+        let entry_point = &reflect(&frozen).unwrap()[0];
+        assert_eq!(entry_point.workgroup_size, Some([1024, 1, 1]));
+        assert_eq!(entry_point.workgroup_size_spec_id, None);
+        assert_eq!(entry_point.spec_constants.len(), 2);
+        assert_eq!(frozen.len(), tunable.len());
+        assert_eq!(freeze_workgroup_size(&module, &[(1, 64)]).unwrap(), module);
+
+        let specialized = specialize(&tunable, &[(1, 64)]).unwrap();
+        let entry_point = &reflect(&specialized).unwrap()[0];
+        assert_eq!(entry_point.workgroup_size, Some([64, 1, 1]));
+        assert_eq!(entry_point.spec_constants, vec![]);
+
+        // The sort order already owns SpecId 0
+        assert!(tunable_workgroup_size(&module, "bitonic_kernel_wg256", 0).is_err());
+        assert!(tunable_workgroup_size(&module, "bitonic_kernel_wg64", 1).is_err());
+        assert!(tunable_workgroup_size(&tunable, "bitonic_kernel_wg256", 2).is_err());
+        let mut old = module.clone();
+        old[1] = 0x0001_0100;
+        assert!(tunable_workgroup_size(&old, "bitonic_kernel_wg256", 1)
+            .unwrap_err()
+            .0
+            .contains("SPIR-V 1.2"));
+    }
+
+    #[test]
+    fn test_reflect_errors() {
+        let mut truncated = bitonic_module(64, &BITONIC_OFFSETS, false);
+        truncated.truncate(truncated.len() - 2);
+        assert!(reflect(&truncated).is_err());
+        assert!(reflect(&[0x0723_0203]).is_err());
+
+        // A buffer without a binding cannot be laid out
+        let unbound = remove_instruction(
+            bitonic_module(64, &BITONIC_OFFSETS, false),
+            &[(4 << 16) | OP_DECORATE, 6, DECORATION_BINDING, 0],
+        );
+        assert!(reflect(&unbound)
+            .unwrap_err()
+            .0
+            .contains("no descriptor set or binding"));
+    }
+}
@@ -0,0 +1,399 @@
+//! Pooled device memory for the ash runner
+//!
+//! Device memory is allocated in large blocks, one pool per memory type, and
+//! buffers are sub-allocated from them with first-fit placement. Freed ranges
+//! return to their block and are reused by later sorts. A block that becomes
+//! empty is released unless it is the only empty block of its memory type,
+//! which is kept for the next sort; blocks are never larger than the
+//! device's `maxMemoryAllocationSize`.
+
+use crate::config::{DebugMessage, DebugSeverity, DebugSink};
+use crate::error::{ChimeraError, Result};
+use ash::{vk, Device};
+use parking_lot::Mutex;
+use std::ptr::NonNull;
+
+/// Size of a pooled memory block; larger requests get a block of their own size
+const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Memory usage of a [`MemoryAllocator`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Device memory blocks currently held
+    pub blocks: usize,
+    /// Bytes of device memory held in blocks
+    pub bytes_allocated: u64,
+    /// Bytes handed out to live sub-allocations
+    pub bytes_in_use: u64,
+    /// Sub-allocations that have not been freed yet
+    pub live_allocations: usize,
+}
+
+/// Host pointer to the start of a persistently mapped block
+#[derive(Copy, Clone)]
+struct MappedPtr(NonNull<u8>);
+
+// The mapping is owned by the block and only accessed through `Allocation`s,
+// which never overlap
+unsafe impl Send for MappedPtr {}
+unsafe impl Sync for MappedPtr {}
+
+struct Block {
+    memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    mapped: Option<MappedPtr>,
+    /// Free ranges as `(offset, size)`, sorted by offset and never adjacent
+    free: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+}
+
+impl Block {
+    fn is_empty(&self) -> bool {
+        self.free == [(0, self.size)]
+    }
+
+    /// Carve `size` bytes aligned to `alignment` out of the first range that fits
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let (index, offset) = self
+            .free
+            .iter()
+            .enumerate()
+            .find_map(|(i, &(start, len))| {
+                let offset = start.next_multiple_of(alignment);
+                (offset + size <= start + len).then_some((i, offset))
+            })?;
+
+        let (start, len) = self.free.remove(index);
+        let end = start + len;
+        let mut insert = index;
+        if offset > start {
+            self.free.insert(insert, (start, offset - start));
+            insert += 1;
+        }
+        if offset + size < end {
+            self.free
+                .insert(insert, (offset + size, end - offset - size));
+        }
+        Some(offset)
+    }
+
+    /// Return a range, merging it with its free neighbours
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self.free.partition_point(|&(start, _)| start < offset);
+        self.free.insert(index, (offset, size));
+
+        if index + 1 < self.free.len() {
+            let (next_start, next_len) = self.free[index + 1];
+            if offset + size == next_start {
+                self.free[index].1 += next_len;
+                self.free.remove(index + 1);
+            }
+        }
+        if index > 0 {
+            let (prev_start, prev_len) = self.free[index - 1];
+            if prev_start + prev_len == offset {
+                self.free[index - 1].1 += self.free[index].1;
+                self.free.remove(index);
+            }
+        }
+    }
+}
+
+/// A range of device memory handed out by [`MemoryAllocator::allocate`]
+pub struct Allocation {
+    memory_type_index: u32,
+    block: usize,
+    memory: vk::DeviceMemory,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    mapped: Option<MappedPtr>,
+}
+
+impl Allocation {
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+
+    /// Host pointer to the start of the allocation, if its memory is host-visible
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        self.mapped
+            .map(|ptr| unsafe { ptr.0.as_ptr().add(self.offset as usize) })
+    }
+}
+
+struct Pools {
+    /// Block slots per memory type index; released blocks leave a `None` so
+    /// the indices held by allocations stay valid
+    blocks: Vec<Vec<Option<Block>>>,
+    stats: MemoryStats,
+}
+
+/// Sub-allocating device memory manager, bucketed by memory type
+///
+/// Dropping it releases every block, so it must be dropped before `device`
+/// is destroyed.
+pub struct MemoryAllocator {
+    device: Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    block_size: vk::DeviceSize,
+    /// The device's `maxMemoryAllocationSize`
+    max_allocation_size: vk::DeviceSize,
+    /// Receives leak reports when debugging is enabled
+    debug_sink: Option<DebugSink>,
+    pools: Mutex<Pools>,
+}
+
+impl MemoryAllocator {
+    pub fn new(
+        device: Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        max_allocation_size: vk::DeviceSize,
+        debug_sink: Option<DebugSink>,
+    ) -> Self {
+        Self {
+            device,
+            memory_properties,
+            block_size: DEFAULT_BLOCK_SIZE.min(max_allocation_size),
+            max_allocation_size,
+            debug_sink,
+            pools: Mutex::new(Pools {
+                blocks: (0..memory_properties.memory_type_count)
+                    .map(|_| Vec::new())
+                    .collect(),
+                stats: MemoryStats::default(),
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        self.pools.lock().stats
+    }
+
+    /// Sub-allocate memory satisfying `requirements` from `memory_type_index`
+    ///
+    /// Host-visible memory types are mapped for the lifetime of their block.
+    pub unsafe fn allocate(
+        &self,
+        requirements: vk::MemoryRequirements,
+        memory_type_index: u32,
+    ) -> Result<Allocation> {
+        if requirements.size > self.max_allocation_size {
+            return Err(ChimeraError::Unsupported(format!(
+                "{} bytes exceed the device's maxMemoryAllocationSize of {} bytes",
+                requirements.size, self.max_allocation_size
+            )));
+        }
+        let mut pools = self.pools.lock();
+        let Pools { blocks, stats } = &mut *pools;
+        let pool = blocks.get_mut(memory_type_index as usize).ok_or_else(|| {
+            ChimeraError::Other(format!("invalid memory type index {memory_type_index}"))
+        })?;
+        let alignment = requirements.alignment.max(1);
+
+        let found = pool.iter_mut().enumerate().find_map(|(index, slot)| {
+            slot.as_mut()?
+                .allocate(requirements.size, alignment)
+                .map(|offset| (index, offset))
+        });
+
+        let (block_index, offset) = match found {
+            Some(found) => found,
+            None => {
+                let mut block =
+                    self.create_block(memory_type_index, requirements.size.max(self.block_size))?;
+                let offset = block
+                    .allocate(requirements.size, alignment)
+                    .expect("new block fits the request");
+                stats.blocks += 1;
+                stats.bytes_allocated += block.size;
+                let index = match pool.iter().position(Option::is_none) {
+                    Some(index) => index,
+                    None => {
+                        pool.push(None);
+                        pool.len() - 1
+                    }
+                };
+                pool[index] = Some(block);
+                (index, offset)
+            }
+        };
+
+        stats.bytes_in_use += requirements.size;
+        stats.live_allocations += 1;
+
+        let block = pool[block_index].as_ref().expect("block was just used");
+        Ok(Allocation {
+            memory_type_index,
+            block: block_index,
+            memory: block.memory,
+            offset,
+            size: requirements.size,
+            mapped: block.mapped,
+        })
+    }
+
+    /// Return an allocation to its block for reuse
+    ///
+    /// A block left empty is released if its memory type already has another
+    /// empty block, or if it was sized for a single oversized request.
+    pub fn free(&self, allocation: Allocation) {
+        let mut pools = self.pools.lock();
+        let Pools { blocks, stats } = &mut *pools;
+        let pool = &mut blocks[allocation.memory_type_index as usize];
+        let block = pool[allocation.block]
+            .as_mut()
+            .expect("allocation outlived its block");
+        block.free(allocation.offset, allocation.size);
+        stats.bytes_in_use -= allocation.size;
+        stats.live_allocations -= 1;
+
+        if !block.is_empty() {
+            return;
+        }
+        let oversized = block.size > self.block_size;
+        let other_empty = pool.iter().enumerate().any(|(index, slot)| {
+            index != allocation.block && slot.as_ref().is_some_and(Block::is_empty)
+        });
+        if oversized || other_empty {
+            let block = pool[allocation.block].take().expect("block is live");
+            stats.blocks -= 1;
+            stats.bytes_allocated -= block.size;
+            unsafe { self.release(block) };
+        }
+    }
+
+    unsafe fn release(&self, block: Block) {
+        if block.mapped.is_some() {
+            self.device.unmap_memory(block.memory);
+        }
+        self.device.free_memory(block.memory, None);
+    }
+
+    unsafe fn create_block(&self, memory_type_index: u32, size: vk::DeviceSize) -> Result<Block> {
+        let device = &self.device;
+        let memory = device.allocate_memory(
+            &vk::MemoryAllocateInfo::default()
+                .allocation_size(size)
+                .memory_type_index(memory_type_index),
+            None,
+        )?;
+
+        let host_visible = self.memory_properties.memory_types[memory_type_index as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+        let mapped = if host_visible {
+            match device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()) {
+                Ok(ptr) => NonNull::new(ptr as *mut u8).map(MappedPtr),
+                Err(e) => {
+                    device.free_memory(memory, None);
+                    return Err(e.into());
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Block {
+            memory,
+            size,
+            mapped,
+            free: vec![(0, size)],
+        })
+    }
+}
+
+impl Drop for MemoryAllocator {
+    /// Release every block, reporting allocations that were never freed
+    fn drop(&mut self) {
+        let pools = self.pools.get_mut();
+        let leaked = pools.stats.live_allocations;
+        if leaked > 0 {
+            if let Some(sink) = &self.debug_sink {
+                sink.emit(&DebugMessage {
+                    severity: DebugSeverity::Error,
+                    kind: "general",
+                    id: None,
+                    message: format!(
+                        "ash memory allocator dropped with {leaked} live allocation(s) of {} bytes",
+                        pools.stats.bytes_in_use
+                    ),
+                });
+            }
+            debug_assert!(
+                std::thread::panicking(),
+                "ash memory allocator dropped with {leaked} live allocation(s)"
+            );
+        }
+
+        let blocks: Vec<Block> = std::mem::take(&mut pools.blocks)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect();
+        for block in blocks {
+            unsafe { self.release(block) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Block;
+    use ash::vk;
+
+    fn block(size: vk::DeviceSize) -> Block {
+        Block {
+            memory: vk::DeviceMemory::null(),
+            size,
+            mapped: None,
+            free: vec![(0, size)],
+        }
+    }
+
+    #[test]
+    fn test_suballocation_alignment() {
+        let mut block = block(1024);
+        assert_eq!(block.allocate(10, 4), Some(0));
+        assert_eq!(block.allocate(100, 256), Some(256));
+        assert_eq!(block.allocate(16, 16), Some(16));
+        assert_eq!(block.free, vec![(10, 6), (32, 224), (356, 668)]);
+        assert_eq!(block.allocate(2048, 1), None);
+    }
+
+    #[test]
+    fn test_free_coalesces_and_reuses() {
+        let mut block = block(1024);
+        let a = block.allocate(256, 1).unwrap();
+        let b = block.allocate(256, 1).unwrap();
+        let c = block.allocate(256, 1).unwrap();
+
+        block.free(a, 256);
+        block.free(c, 256);
+        assert_eq!(block.free, vec![(0, 256), (512, 512)]);
+
+        block.free(b, 256);
+        assert_eq!(block.free, vec![(0, 1024)]);
+        assert_eq!(block.allocate(1024, 64), Some(0));
+    }
+
+    #[test]
+    fn test_block_empty_once_fully_freed() {
+        let mut block = block(1024);
+        assert!(block.is_empty());
+        let a = block.allocate(100, 1).unwrap();
+        let b = block.allocate(100, 1).unwrap();
+        assert!(!block.is_empty());
+
+        block.free(b, 100);
+        assert!(!block.is_empty());
+        block.free(a, 100);
+        assert!(block.is_empty());
+    }
+}
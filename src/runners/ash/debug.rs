@@ -0,0 +1,164 @@
+//! Validation layers and `VK_EXT_debug_utils` support for the ash runner
+
+use crate::{
+    config::{DebugMessage, DebugSeverity, DebugSink},
+    error::Result,
+};
+use ash::{ext::debug_utils, vk, Device, Entry, Instance};
+use std::ffi::{c_void, CStr, CString};
+
+/// Khronos validation layer
+pub const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+/// Debug features supported by the installed Vulkan loader
+pub struct DebugSupport {
+    pub validation_layer: bool,
+    pub debug_utils: bool,
+}
+
+impl DebugSupport {
+    pub unsafe fn query(entry: &Entry) -> Result<Self> {
+        let validation_layer = entry
+            .enumerate_instance_layer_properties()?
+            .iter()
+            .any(|layer| CStr::from_ptr(layer.layer_name.as_ptr()) == VALIDATION_LAYER);
+
+        // Extensions provided by the layer are only listed when asking the layer itself
+        let layer = validation_layer.then_some(VALIDATION_LAYER);
+        let debug_utils = entry
+            .enumerate_instance_extension_properties(None)?
+            .iter()
+            .chain(
+                layer
+                    .map(|layer| entry.enumerate_instance_extension_properties(Some(layer)))
+                    .transpose()?
+                    .iter()
+                    .flatten(),
+            )
+            .any(|ext| CStr::from_ptr(ext.extension_name.as_ptr()) == debug_utils::NAME);
+
+        Ok(Self {
+            validation_layer,
+            debug_utils,
+        })
+    }
+}
+
+/// Messenger create info forwarding warnings and errors to `sink`
+///
+/// `sink` must outlive every object created with this info.
+pub fn messenger_create_info(sink: &DebugSink) -> vk::DebugUtilsMessengerCreateInfoEXT<'_> {
+    vk::DebugUtilsMessengerCreateInfoEXT::default()
+        .message_severity(
+            vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+        )
+        .message_type(
+            vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+        )
+        .pfn_user_callback(Some(debug_callback))
+        .user_data(sink as *const DebugSink as *mut c_void)
+}
+
+unsafe extern "system" fn debug_callback(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    types: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+    user_data: *mut c_void,
+) -> vk::Bool32 {
+    if data.is_null() || user_data.is_null() {
+        return vk::FALSE;
+    }
+    let data = &*data;
+    let sink = &*(user_data as *const DebugSink);
+
+    let severity = if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        DebugSeverity::Error
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        DebugSeverity::Warning
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        DebugSeverity::Info
+    } else {
+        DebugSeverity::Verbose
+    };
+    let kind = if types.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) {
+        "validation"
+    } else if types.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE) {
+        "performance"
+    } else {
+        "general"
+    };
+
+    let message = DebugMessage {
+        severity,
+        kind,
+        id: data
+            .message_id_name_as_c_str()
+            .map(|id| id.to_string_lossy().into_owned()),
+        message: data
+            .message_as_c_str()
+            .map(|message| message.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+
+    // Unwinding into the Vulkan loader is undefined behaviour
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| sink.emit(&message)));
+
+    // The application must not abort the call that triggered the message
+    vk::FALSE
+}
+
+/// Debug messenger and object naming for one runner
+pub struct DebugUtils {
+    instance_fn: debug_utils::Instance,
+    device_fn: Option<debug_utils::Device>,
+    messenger: vk::DebugUtilsMessengerEXT,
+    /// Boxed so the pointer handed to the messenger stays valid
+    _sink: Box<DebugSink>,
+}
+
+impl DebugUtils {
+    /// Register a messenger on `instance`, which must have `VK_EXT_debug_utils` enabled
+    ///
+    /// `sink` is kept alive until the runner is dropped, so it may also have
+    /// been chained into the instance create info.
+    pub unsafe fn new(entry: &Entry, instance: &Instance, sink: Box<DebugSink>) -> Result<Self> {
+        let instance_fn = debug_utils::Instance::new(entry, instance);
+        let messenger =
+            instance_fn.create_debug_utils_messenger(&messenger_create_info(&sink), None)?;
+
+        Ok(Self {
+            instance_fn,
+            device_fn: None,
+            messenger,
+            _sink: sink,
+        })
+    }
+
+    /// Enable object naming on `device`
+    pub fn attach_device(&mut self, instance: &Instance, device: &Device) {
+        self.device_fn = Some(debug_utils::Device::new(instance, device));
+    }
+
+    /// Give `handle` a name shown in validation messages and debuggers
+    pub unsafe fn set_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        let (Some(device_fn), Ok(name)) = (&self.device_fn, CString::new(name)) else {
+            return;
+        };
+        // Naming is best effort and never fails a sort
+        let _ = device_fn.set_debug_utils_object_name(
+            &vk::DebugUtilsObjectNameInfoEXT::default()
+                .object_handle(handle)
+                .object_name(&name),
+        );
+    }
+
+    /// Destroy the messenger; must be called before the instance is destroyed
+    pub unsafe fn destroy(&mut self) {
+        self.instance_fn
+            .destroy_debug_utils_messenger(self.messenger, None);
+        self.messenger = vk::DebugUtilsMessengerEXT::null();
+    }
+}
@@ -0,0 +1,1589 @@
+//! ash runner implementation - Direct Vulkan API via ash
+
+mod allocator;
+mod debug;
+mod submission;
+
+pub use allocator::MemoryStats;
+pub use submission::{BufferSubmission, Submission};
+
+use crate::{
+    bitonic_passes,
+    capabilities::Capabilities,
+    config::{
+        Backends, DebugMessage, DebugSeverity, DebugSink, DeviceInfo, MemoryStrategy, RunnerConfig,
+    },
+    error::{ChimeraError, Result},
+    kernel::{BitonicLayout, KernelSource, KernelVariant, ParamsSource, SPIRV_KERNELS},
+    pipeline_cache::PipelineCacheFile,
+    profile::SortProfile,
+    reflect::{freeze_workgroup_size, BindingType},
+    SortRunner,
+};
+use allocator::{Allocation, MemoryAllocator};
+use ash::{vk, Device, Entry, Instance};
+use debug::{DebugSupport, DebugUtils, VALIDATION_LAYER};
+use parking_lot::Mutex;
+use shared::{BitonicParams, SortOrder, SortableKey, SORT_ORDER_SPEC_ID, WORKGROUP_SIZE_SPEC_ID};
+use std::ffi::CString;
+use std::fmt::{self, Display};
+use std::mem::ManuallyDrop;
+
+/// Number of sorts that may be submitted to one runner at the same time
+pub const MAX_IN_FLIGHT_SORTS: u32 = 64;
+
+/// Error of `vkCreateInstance`, where `ERROR_INCOMPATIBLE_DRIVER` means the
+/// loader found no driver to run on
+pub(crate) fn loader_error(err: vk::Result) -> ChimeraError {
+    match err {
+        vk::Result::ERROR_INCOMPATIBLE_DRIVER => {
+            ChimeraError::VulkanLoader(format!("{err:?}: no compatible Vulkan driver is installed"))
+        }
+        err => err.into(),
+    }
+}
+
+/// Queue families used by an [`AshRunner`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QueueFamilies {
+    /// Family the sort kernel is dispatched on
+    pub compute: u32,
+    /// Whether `compute` lacks graphics support, so sorts never stall rendering
+    pub compute_only: bool,
+    /// Family running staging uploads and downloads, if separate from `compute`
+    pub transfer: Option<u32>,
+}
+
+impl Display for QueueFamilies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.compute_only {
+            "compute-only"
+        } else {
+            "shared"
+        };
+        write!(f, "{kind} compute queue family {}", self.compute)?;
+        if let Some(transfer) = self.transfer {
+            write!(f, ", transfer queue family {transfer}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A transfer-only queue used for staging copies
+struct TransferQueue {
+    family_index: u32,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+}
+
+/// Instance, debug messenger and device created by the runner itself
+///
+/// Destroys them when dropped, so construction can bail out with `?` at any
+/// point until [`CreatedHandles::disarm`] hands them to the finished runner.
+struct CreatedHandles {
+    instance: Instance,
+    debug: Option<DebugUtils>,
+    device: Option<Device>,
+}
+
+impl CreatedHandles {
+    /// Give up ownership to the runner, returning the debug messenger it now destroys
+    fn disarm(mut self) -> Option<DebugUtils> {
+        self.device = None;
+        let debug = self.debug.take();
+        std::mem::forget(self);
+        debug
+    }
+}
+
+impl Drop for CreatedHandles {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(device) = &self.device {
+                device.destroy_device(None);
+            }
+            if let Some(debug) = &mut self.debug {
+                debug.destroy();
+            }
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+/// Handles a runner is built on, created by the runner or provided by the host
+struct DeviceParts {
+    entry: Option<Entry>,
+    instance: Instance,
+    physical_device: vk::PhysicalDevice,
+    device: Device,
+    queue: vk::Queue,
+    queue_family_index: u32,
+    transfer_family_index: Option<u32>,
+    /// Set when the runner created the device; `None` when the host owns it
+    created: Option<CreatedHandles>,
+    /// Whether `VK_KHR_maintenance4` is enabled, so modules may use `LocalSizeId`
+    local_size_id: bool,
+}
+
+/// Vulkan-based runner for bitonic sort using raw Vulkan API via ash
+pub struct AshRunner {
+    /// Keeps the Vulkan library loaded; `None` when the host owns the instance
+    _entry: Option<Entry>,
+    instance: Instance,
+    #[allow(dead_code)]
+    physical_device: vk::PhysicalDevice,
+    device: Device,
+    /// Whether dropping the runner destroys `device` and `instance`
+    owns_device: bool,
+    queue_family_index: u32,
+    compute_only: bool,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    transfer: Option<TransferQueue>,
+    /// Serializes use of the command pools, descriptor pool and queues, which
+    /// Vulkan requires to be externally synchronized
+    pool_lock: Mutex<()>,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    min_storage_buffer_offset_alignment: vk::DeviceSize,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    timestamp_valid_bits: u32,
+    device_name: String,
+    capabilities: Capabilities,
+    /// Kernel variant the pipeline is built from
+    variant: KernelVariant,
+    /// Whether the device takes `LocalSizeId`, so the workgroup size can be
+    /// specialized instead of frozen into the module
+    local_size_id: bool,
+    /// Binding of the key buffer, as reflected from the kernel
+    key_binding: u32,
+    memory_strategy: MemoryStrategy,
+    /// Dropped by hand so its blocks are released before the device
+    allocator: ManuallyDrop<MemoryAllocator>,
+    debug: Option<DebugUtils>,
+    pipeline_cache_file: Option<PipelineCacheFile>,
+    // Cached pipeline resources
+    pipeline_cache: Option<vk::PipelineCache>,
+    /// Pipelines sorting in each [`SortOrder`], indexed by it
+    pipelines: Option<[vk::Pipeline; 2]>,
+    pipeline_layout: Option<vk::PipelineLayout>,
+    descriptor_set_layout: Option<vk::DescriptorSetLayout>,
+    descriptor_pool: Option<vk::DescriptorPool>,
+    shader_module: Option<vk::ShaderModule>,
+}
+
+impl AshRunner {
+    /// Create a new Vulkan runner using raw Vulkan API via ash
+    pub fn new() -> Result<Self> {
+        Self::with_config(&RunnerConfig::default())
+    }
+
+    /// Create a new Vulkan runner on the physical device selected by `config`
+    pub fn with_config(config: &RunnerConfig) -> Result<Self> {
+        if !config.backends.contains(Backends::VULKAN) {
+            return Err(ChimeraError::Unsupported(
+                "the Vulkan backend is disabled in RunnerConfig".to_string(),
+            ));
+        }
+
+        unsafe {
+            // Load Vulkan entry point
+            let entry = Entry::load().map_err(|e| ChimeraError::VulkanLoader(format!("{e:?}")))?;
+
+            let debug_sink = config.debug.then_some(&config.debug_sink);
+            let (instance, debug) = Self::create_instance(&entry, debug_sink)?;
+            let mut created = CreatedHandles {
+                instance: instance.clone(),
+                debug,
+                device: None,
+            };
+
+            // Select physical device among those with a compute queue
+            let physical_devices = instance.enumerate_physical_devices()?;
+            let device_count = physical_devices.len();
+            let candidates: Vec<(vk::PhysicalDevice, DeviceInfo)> = physical_devices
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, pd)| Self::find_compute_queue_family(&instance, pd).is_some())
+                .map(|(index, pd)| (pd, Self::device_info(&instance, index, pd)))
+                .collect();
+            let infos: Vec<DeviceInfo> = candidates.iter().map(|(_, info)| info.clone()).collect();
+            let physical_device = config
+                .select_device(&infos)
+                .map(|position| candidates[position].0)
+                .ok_or(ChimeraError::NoVulkanDevice(device_count))?;
+
+            // Staging copies only happen with device-local memory
+            let properties = instance.get_physical_device_properties(physical_device);
+            let memory_strategy = Self::resolve_memory_strategy(
+                config.memory_strategy,
+                &properties,
+                &instance.get_physical_device_memory_properties(physical_device),
+            );
+            let queue_families =
+                instance.get_physical_device_queue_family_properties(physical_device);
+            let queue_family_index = Self::find_compute_queue_family(&instance, physical_device)
+                .ok_or(ChimeraError::NoComputeQueue)?;
+            let transfer_family_index = (config.dedicated_transfer_queue
+                && memory_strategy == MemoryStrategy::DeviceLocal)
+                .then(|| Self::find_transfer_queue_family(&queue_families, queue_family_index))
+                .flatten();
+
+            // Create logical device
+            let queue_priorities = [1.0];
+            let queue_infos: Vec<vk::DeviceQueueCreateInfo> = std::iter::once(queue_family_index)
+                .chain(transfer_family_index)
+                .map(|family_index| {
+                    vk::DeviceQueueCreateInfo::default()
+                        .queue_family_index(family_index)
+                        .queue_priorities(&queue_priorities)
+                })
+                .collect();
+
+            let device_features = vk::PhysicalDeviceFeatures::default();
+
+            // Enable device extensions
+            let mut device_extension_names = vec![];
+
+            // The instance targets Vulkan 1.2, where modules declaring their
+            // workgroup size with LocalSizeId need maintenance4
+            let has_maintenance4 = instance
+                .enumerate_device_extension_properties(physical_device)?
+                .iter()
+                .any(|ext| ext.extension_name_as_c_str() == Ok(ash::khr::maintenance4::NAME));
+            let mut maintenance4 = vk::PhysicalDeviceMaintenance4Features::default();
+            if has_maintenance4 {
+                let mut features =
+                    vk::PhysicalDeviceFeatures2::default().push_next(&mut maintenance4);
+                instance.get_physical_device_features2(physical_device, &mut features);
+            }
+            let local_size_id = maintenance4.maintenance4 == vk::TRUE;
+            if local_size_id {
+                device_extension_names.push(ash::khr::maintenance4::NAME.as_ptr());
+            }
+
+            // Keep portability extension name alive for the duration of device creation
+            #[cfg(target_os = "macos")]
+            let _portability_subset_name = {
+                // Get available device extensions
+                let available_device_extensions =
+                    instance.enumerate_device_extension_properties(physical_device)?;
+                let has_portability_subset = available_device_extensions.iter().any(|ext| {
+                    let name = std::ffi::CStr::from_ptr(ext.extension_name.as_ptr());
+                    name.to_bytes() == b"VK_KHR_portability_subset"
+                });
+
+                if has_portability_subset {
+                    let name = CString::new("VK_KHR_portability_subset").unwrap();
+                    device_extension_names.push(name.as_ptr());
+                    Some(name)
+                } else {
+                    None
+                }
+            };
+
+            let mut device_info = vk::DeviceCreateInfo::default()
+                .queue_create_infos(&queue_infos)
+                .enabled_features(&device_features)
+                .enabled_extension_names(&device_extension_names);
+            if local_size_id {
+                device_info = device_info.push_next(&mut maintenance4);
+            }
+            let device = instance.create_device(physical_device, &device_info, None)?;
+            created.device = Some(device.clone());
+            if let Some(debug) = &mut created.debug {
+                debug.attach_device(&instance, &device);
+            }
+
+            // Get queue
+            let queue = device.get_device_queue(queue_family_index, 0);
+
+            Self::from_parts(
+                DeviceParts {
+                    entry: Some(entry),
+                    instance,
+                    physical_device,
+                    device,
+                    queue,
+                    queue_family_index,
+                    transfer_family_index,
+                    created: Some(created),
+                    local_size_id,
+                },
+                config,
+            )
+        }
+    }
+
+    /// Create a runner on a Vulkan device owned by the host application
+    ///
+    /// The runner creates its own pipeline, pools and memory, and destroys
+    /// only those when dropped; `instance` and `device` stay alive and usable
+    /// by the host. Validation and the dedicated transfer queue options of
+    /// `config` are ignored, since the host configured the device. Not
+    /// knowing whether the host enabled `maintenance4`, the runner freezes
+    /// the workgroup size into the module instead of specializing it.
+    ///
+    /// # Safety
+    ///
+    /// `instance` and `device` must be valid, `physical_device` must be the
+    /// device `device` was created from, and `queue` must belong to
+    /// `queue_family_index`, which must support compute. All of them must
+    /// outlive the runner. The host must not submit to `queue` while the
+    /// runner does, as Vulkan requires queue access to be externally synchronized.
+    pub unsafe fn from_raw(
+        instance: Instance,
+        physical_device: vk::PhysicalDevice,
+        device: Device,
+        queue: vk::Queue,
+        queue_family_index: u32,
+        config: &RunnerConfig,
+    ) -> Result<Self> {
+        let queue_families = instance.get_physical_device_queue_family_properties(physical_device);
+        let supports_compute = queue_families
+            .get(queue_family_index as usize)
+            .is_some_and(|props| props.queue_flags.contains(vk::QueueFlags::COMPUTE));
+        if !supports_compute {
+            return Err(ChimeraError::NoComputeQueue);
+        }
+
+        Self::from_parts(
+            DeviceParts {
+                entry: None,
+                instance,
+                physical_device,
+                device,
+                queue,
+                queue_family_index,
+                transfer_family_index: None,
+                created: None,
+                local_size_id: false,
+            },
+            config,
+        )
+    }
+
+    /// Create the runner's own objects on an existing device
+    ///
+    /// A device the runner created is destroyed again if this fails.
+    unsafe fn from_parts(parts: DeviceParts, config: &RunnerConfig) -> Result<Self> {
+        let DeviceParts {
+            entry,
+            instance,
+            physical_device,
+            device,
+            queue,
+            queue_family_index,
+            transfer_family_index,
+            created,
+            local_size_id,
+        } = parts;
+
+        // Get device properties
+        let properties = instance.get_physical_device_properties(physical_device);
+        let device_name = std::ffi::CStr::from_ptr(properties.device_name.as_ptr())
+            .to_string_lossy()
+            .to_string();
+
+        let memory_properties = instance.get_physical_device_memory_properties(physical_device);
+        let memory_strategy =
+            Self::resolve_memory_strategy(config.memory_strategy, &properties, &memory_properties);
+
+        let queue_families = instance.get_physical_device_queue_family_properties(physical_device);
+        let queue_family = &queue_families[queue_family_index as usize];
+        let limits = &properties.limits;
+        let variant = KernelVariant::select(
+            SPIRV_KERNELS,
+            ParamsSource::PushConstants,
+            limits
+                .max_compute_work_group_invocations
+                .min(limits.max_compute_work_group_size[0]),
+            config.workgroup_size,
+        )?;
+        let capabilities = Self::query_capabilities(
+            &instance,
+            physical_device,
+            &properties,
+            queue_family,
+            variant.workgroup_size,
+        );
+        let compute_only = !queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS);
+        let timestamp_valid_bits = queue_family.timestamp_valid_bits;
+
+        let transfer = match transfer_family_index {
+            Some(family_index) => Some(TransferQueue {
+                family_index,
+                queue: device.get_device_queue(family_index, 0),
+                command_pool: device.create_command_pool(
+                    &vk::CommandPoolCreateInfo::default().queue_family_index(family_index),
+                    None,
+                )?,
+            }),
+            None => None,
+        };
+
+        // Create command pool
+        let command_pool = match device.create_command_pool(
+            &vk::CommandPoolCreateInfo::default()
+                .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+                .queue_family_index(queue_family_index),
+            None,
+        ) {
+            Ok(command_pool) => command_pool,
+            Err(err) => {
+                if let Some(transfer) = &transfer {
+                    device.destroy_command_pool(transfer.command_pool, None);
+                }
+                return Err(err.into());
+            }
+        };
+
+        let pipeline_cache_file = config.pipeline_cache_dir.as_ref().map(|dir| {
+            // Identical GPUs share vendor and device IDs, so the UUID tells their files apart
+            let mut name = format!(
+                "ash-{:08x}-{:08x}",
+                properties.vendor_id, properties.device_id
+            );
+            if let Some(uuid) = Self::device_uuid(&instance, physical_device, &properties) {
+                name.push('-');
+                name.extend(uuid.iter().map(|byte| format!("{byte:02x}")));
+            }
+            PipelineCacheFile::new(
+                dir,
+                &name,
+                Self::pipeline_cache_identity(&instance, physical_device, &properties),
+            )
+        });
+        let allocator = ManuallyDrop::new(MemoryAllocator::new(
+            device.clone(),
+            memory_properties,
+            Self::max_allocation_size(&instance, physical_device, &properties),
+            config.debug.then(|| config.debug_sink.clone()),
+        ));
+
+        // From here on the runner's `Drop` cleans up
+        let owns_device = created.is_some();
+        let debug = created.and_then(CreatedHandles::disarm);
+        let mut runner = Self {
+            _entry: entry,
+            instance,
+            physical_device,
+            device,
+            owns_device,
+            queue_family_index,
+            compute_only,
+            queue,
+            command_pool,
+            transfer,
+            pool_lock: Mutex::new(()),
+            memory_properties,
+            min_storage_buffer_offset_alignment: properties
+                .limits
+                .min_storage_buffer_offset_alignment,
+            timestamp_period: properties.limits.timestamp_period,
+            timestamp_valid_bits,
+            device_name,
+            capabilities,
+            variant,
+            local_size_id,
+            key_binding: 0,
+            memory_strategy,
+            allocator,
+            debug,
+            pipeline_cache_file,
+            pipeline_cache: None,
+            pipelines: None,
+            pipeline_layout: None,
+            descriptor_set_layout: None,
+            descriptor_pool: None,
+            shader_module: None,
+        };
+
+        // Initialize the pipeline
+        runner.create_pipeline(&config.spirv_kernel)?;
+
+        Ok(runner)
+    }
+
+    /// List the Vulkan physical devices
+    ///
+    /// The returned indices can be passed to [`RunnerConfig::device_index`].
+    pub fn enumerate_devices() -> Result<Vec<DeviceInfo>> {
+        unsafe {
+            let entry = Entry::load().map_err(|e| ChimeraError::VulkanLoader(format!("{e:?}")))?;
+            let (instance, _) = Self::create_instance(&entry, None)?;
+
+            let devices = instance
+                .enumerate_physical_devices()
+                .map(|physical_devices| {
+                    physical_devices
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, pd)| Self::device_info(&instance, index, pd))
+                        .collect()
+                });
+
+            instance.destroy_instance(None);
+            Ok(devices?)
+        }
+    }
+
+    /// Create the instance, with validation and a debug messenger if `debug_sink` is set
+    unsafe fn create_instance(
+        entry: &Entry,
+        debug_sink: Option<&DebugSink>,
+    ) -> Result<(Instance, Option<DebugUtils>)> {
+        let app_name = CString::new("Rust GPU Chimera Demo")
+            .map_err(|e| ChimeraError::Other(e.to_string()))?;
+        let engine_name =
+            CString::new("rust-gpu-chimera").map_err(|e| ChimeraError::Other(e.to_string()))?;
+
+        let app_info = vk::ApplicationInfo::default()
+            .application_name(&app_name)
+            .application_version(vk::make_api_version(0, 1, 0, 0))
+            .engine_name(&engine_name)
+            .engine_version(vk::make_api_version(0, 1, 0, 0))
+            .api_version(vk::API_VERSION_1_2);
+
+        // Enable portability extensions for MoltenVK on macOS
+        #[allow(unused_mut)] // Only modified on macOS
+        let mut extension_names = vec![];
+        #[allow(unused_mut)] // Only modified on macOS
+        let mut create_flags = vk::InstanceCreateFlags::empty();
+
+        #[cfg(target_os = "macos")]
+        {
+            // Get available extensions
+            let available_extensions = entry.enumerate_instance_extension_properties(None)?;
+            let has_portability = available_extensions.iter().any(|ext| {
+                let name = std::ffi::CStr::from_ptr(ext.extension_name.as_ptr());
+                name.to_bytes() == b"VK_KHR_portability_enumeration"
+            });
+
+            if has_portability {
+                extension_names.push(ash::khr::portability_enumeration::NAME.as_ptr());
+                create_flags |= vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR;
+            }
+        }
+
+        let mut layer_names = vec![];
+        let debug_sink = match debug_sink {
+            Some(sink) => {
+                let support = DebugSupport::query(entry)?;
+                if support.validation_layer {
+                    layer_names.push(VALIDATION_LAYER.as_ptr());
+                } else {
+                    sink.emit(&DebugMessage {
+                        severity: DebugSeverity::Warning,
+                        kind: "general",
+                        id: None,
+                        message: format!(
+                            "{} is not installed; running without validation",
+                            VALIDATION_LAYER.to_string_lossy()
+                        ),
+                    });
+                }
+                if support.debug_utils {
+                    extension_names.push(ash::ext::debug_utils::NAME.as_ptr());
+                }
+                support.debug_utils.then(|| Box::new(sink.clone()))
+            }
+            None => None,
+        };
+
+        // Chaining the messenger info also reports instance creation and destruction
+        let mut messenger_info = debug_sink.as_deref().map(debug::messenger_create_info);
+        let mut create_info = vk::InstanceCreateInfo::default()
+            .application_info(&app_info)
+            .enabled_layer_names(&layer_names)
+            .enabled_extension_names(&extension_names)
+            .flags(create_flags);
+        if let Some(messenger_info) = &mut messenger_info {
+            create_info = create_info.push_next(messenger_info);
+        }
+
+        let instance = entry
+            .create_instance(&create_info, None)
+            .map_err(loader_error)?;
+        let debug = match debug_sink {
+            Some(sink) => match DebugUtils::new(entry, &instance, sink) {
+                Ok(debug) => Some(debug),
+                Err(e) => {
+                    instance.destroy_instance(None);
+                    return Err(e);
+                }
+            },
+            None => None,
+        };
+
+        Ok((instance, debug))
+    }
+
+    /// Name `handle` for validation messages and debuggers when debugging is enabled
+    unsafe fn set_debug_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        if let Some(debug) = &self.debug {
+            debug.set_name(handle, name);
+        }
+    }
+
+    unsafe fn device_info(
+        instance: &Instance,
+        index: usize,
+        physical_device: vk::PhysicalDevice,
+    ) -> DeviceInfo {
+        let properties = instance.get_physical_device_properties(physical_device);
+        let name = std::ffi::CStr::from_ptr(properties.device_name.as_ptr())
+            .to_string_lossy()
+            .to_string();
+
+        DeviceInfo {
+            host: "ash",
+            backend: "Vulkan",
+            index,
+            name,
+            device_type: properties.device_type.into(),
+            driver: None,
+        }
+    }
+
+    /// Prefer a compute-only (async compute) family, falling back to any compute family
+    unsafe fn find_compute_queue_family(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<u32> {
+        let families = instance.get_physical_device_queue_family_properties(physical_device);
+        let compute = |props: &&vk::QueueFamilyProperties| {
+            props.queue_flags.contains(vk::QueueFlags::COMPUTE)
+        };
+
+        let idx = families
+            .iter()
+            .position(|props| {
+                compute(&props) && !props.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+            })
+            .or_else(|| families.iter().position(|props| compute(&props)))?;
+        Some(idx as u32)
+    }
+
+    /// Find a transfer-only family (a DMA engine) other than `compute_family`
+    fn find_transfer_queue_family(
+        families: &[vk::QueueFamilyProperties],
+        compute_family: u32,
+    ) -> Option<u32> {
+        families
+            .iter()
+            .enumerate()
+            .position(|(idx, props)| {
+                idx as u32 != compute_family
+                    && props.queue_count > 0
+                    && props.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                    && !props
+                        .queue_flags
+                        .intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            })
+            .map(|idx| idx as u32)
+    }
+
+    /// Queue families chosen for compute and, if enabled, staging transfers
+    pub fn queue_families(&self) -> QueueFamilies {
+        QueueFamilies {
+            compute: self.queue_family_index,
+            compute_only: self.compute_only,
+            transfer: self.transfer.as_ref().map(|transfer| transfer.family_index),
+        }
+    }
+
+    /// Largest single device memory allocation, `maxMemoryAllocationSize`
+    ///
+    /// The limit is core in Vulkan 1.1; older devices get the spec's
+    /// guaranteed minimum of 1 GiB.
+    unsafe fn max_allocation_size(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+    ) -> vk::DeviceSize {
+        if properties.api_version < vk::API_VERSION_1_1 {
+            return 1 << 30;
+        }
+        let mut maintenance3 = vk::PhysicalDeviceMaintenance3Properties::default();
+        let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut maintenance3);
+        instance.get_physical_device_properties2(physical_device, &mut properties2);
+        maintenance3.max_memory_allocation_size
+    }
+
+    /// Derive runner capabilities from the physical device properties, limits
+    /// and the selected queue family
+    unsafe fn query_capabilities(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+        queue_family: &vk::QueueFamilyProperties,
+        workgroup_size: u32,
+    ) -> Capabilities {
+        let limits = &properties.limits;
+
+        // Subgroup properties are core in Vulkan 1.1
+        let subgroup_operations = if properties.api_version >= vk::API_VERSION_1_1 {
+            let mut subgroup = vk::PhysicalDeviceSubgroupProperties::default();
+            let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut subgroup);
+            instance.get_physical_device_properties2(physical_device, &mut properties2);
+            subgroup
+                .supported_stages
+                .contains(vk::ShaderStageFlags::COMPUTE)
+                && subgroup
+                    .supported_operations
+                    .contains(vk::SubgroupFeatureFlags::BASIC)
+        } else {
+            false
+        };
+
+        let max_by_binding =
+            u64::from(limits.max_storage_buffer_range) / std::mem::size_of::<u32>() as u64;
+        let max_by_dispatch =
+            u64::from(limits.max_compute_work_group_count[0]) * u64::from(workgroup_size);
+
+        Capabilities {
+            max_elements: Capabilities::clamp_max_elements(max_by_binding.min(max_by_dispatch)),
+            // Only `u32` kernels are built
+            supports_64bit_keys: false,
+            max_push_constant_size: limits.max_push_constants_size,
+            subgroup_operations,
+            timestamp_queries: queue_family.timestamp_valid_bits > 0,
+            is_software: properties.device_type == vk::PhysicalDeviceType::CPU,
+        }
+    }
+
+    /// Bytes identifying the device and driver a pipeline cache was built for
+    unsafe fn pipeline_cache_identity(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+    ) -> Vec<u8> {
+        let mut identity = Vec::new();
+        identity.extend_from_slice(&properties.vendor_id.to_le_bytes());
+        identity.extend_from_slice(&properties.device_id.to_le_bytes());
+        identity.extend_from_slice(&properties.driver_version.to_le_bytes());
+        identity.extend_from_slice(&properties.pipeline_cache_uuid);
+        if let Some(uuid) = Self::device_uuid(instance, physical_device, properties) {
+            identity.extend_from_slice(&uuid);
+        }
+        identity
+    }
+
+    /// The device UUID, which is core in Vulkan 1.1
+    unsafe fn device_uuid(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+    ) -> Option<[u8; vk::UUID_SIZE]> {
+        if properties.api_version < vk::API_VERSION_1_1 {
+            return None;
+        }
+        let mut id = vk::PhysicalDeviceIDProperties::default();
+        let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut id);
+        instance.get_physical_device_properties2(physical_device, &mut properties2);
+        Some(id.device_uuid)
+    }
+
+    /// Create the pipeline cache, seeded from disk when a cache directory is configured
+    unsafe fn create_pipeline_cache(&self) -> Result<vk::PipelineCache> {
+        let initial_data = self
+            .pipeline_cache_file
+            .as_ref()
+            .and_then(|file| file.load());
+
+        if let Some(data) = &initial_data {
+            let cache = self.device.create_pipeline_cache(
+                &vk::PipelineCacheCreateInfo::default().initial_data(data),
+                None,
+            );
+            if let Ok(cache) = cache {
+                return Ok(cache);
+            }
+            // The driver rejected the data; start from an empty cache instead
+        }
+
+        Ok(self
+            .device
+            .create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)?)
+    }
+
+    /// Write the pipeline cache back to disk
+    unsafe fn save_pipeline_cache(&self) -> Result<()> {
+        if let (Some(file), Some(cache)) = (&self.pipeline_cache_file, self.pipeline_cache) {
+            file.store(&self.device.get_pipeline_cache_data(cache)?)?;
+        }
+        Ok(())
+    }
+
+    fn create_pipeline(&mut self, kernel: &KernelSource) -> Result<()> {
+        unsafe {
+            let kernel_code = kernel.spirv(&self.variant)?;
+            // Lay out the descriptors and push constants the module declares
+            let layout = BitonicLayout::reflect(&kernel_code, &self.variant)?;
+            // A tunable workgroup size is specialized like the order where
+            // the device takes LocalSizeId, and frozen into the module elsewhere
+            let workgroup_size = self.variant.workgroup_size;
+            let specialize_size = layout.workgroup_size_constant && self.local_size_id;
+            let kernel_code = if layout.workgroup_size_constant && !self.local_size_id {
+                freeze_workgroup_size(&kernel_code, &[(WORKGROUP_SIZE_SPEC_ID, workgroup_size)])?
+            } else {
+                kernel_code
+            };
+            let pipeline_cache = self.create_pipeline_cache()?;
+            self.pipeline_cache = Some(pipeline_cache);
+
+            let shader_module = self.device.create_shader_module(
+                &vk::ShaderModuleCreateInfo::default().code(&kernel_code),
+                None,
+            )?;
+
+            let bindings: Vec<vk::DescriptorSetLayoutBinding> = layout
+                .bindings
+                .iter()
+                .map(|binding| {
+                    vk::DescriptorSetLayoutBinding::default()
+                        .binding(binding.binding)
+                        .descriptor_type(descriptor_type(binding.ty))
+                        .descriptor_count(1)
+                        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                })
+                .collect();
+            let descriptor_set_layout = self.device.create_descriptor_set_layout(
+                &vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings),
+                None,
+            )?;
+
+            let pipeline_layout = self.device.create_pipeline_layout(
+                &vk::PipelineLayoutCreateInfo::default()
+                    .set_layouts(&[descriptor_set_layout])
+                    .push_constant_ranges(&[vk::PushConstantRange::default()
+                        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                        .offset(0)
+                        .size(layout.push_constant_size)]),
+                None,
+            )?;
+
+            // Create compute pipeline
+            let entry_name = CString::new(self.variant.entry_point)
+                .map_err(|e| ChimeraError::Other(e.to_string()))?;
+
+            // One pipeline per order when the module lets the order be
+            // specialized, otherwise one reading it from the push constants.
+            // Each is specialized with its order and the workgroup size.
+            let stage = vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::COMPUTE)
+                .module(shader_module)
+                .name(&entry_name);
+            let map_entry = |constant_id, offset| {
+                vk::SpecializationMapEntry::default()
+                    .constant_id(constant_id)
+                    .offset(offset)
+                    .size(std::mem::size_of::<u32>())
+            };
+            let mut map_entries = Vec::new();
+            if layout.sort_order_constant {
+                map_entries.push(map_entry(SORT_ORDER_SPEC_ID, 0));
+            }
+            if specialize_size {
+                map_entries.push(map_entry(WORKGROUP_SIZE_SPEC_ID, 4));
+            }
+            let orders: &[SortOrder] = if layout.sort_order_constant {
+                &[SortOrder::Ascending, SortOrder::Descending]
+            } else {
+                &[SortOrder::Ascending]
+            };
+            let constants: Vec<[u32; 2]> = orders
+                .iter()
+                .map(|&order| [order.into(), workgroup_size])
+                .collect();
+            let specializations: Vec<vk::SpecializationInfo> = constants
+                .iter()
+                .map(|constants| {
+                    vk::SpecializationInfo::default()
+                        .map_entries(&map_entries)
+                        .data(bytemuck::bytes_of(constants))
+                })
+                .collect();
+            let stages: Vec<vk::PipelineShaderStageCreateInfo> = specializations
+                .iter()
+                .map(|specialization| {
+                    if map_entries.is_empty() {
+                        stage
+                    } else {
+                        stage.specialization_info(specialization)
+                    }
+                })
+                .collect();
+            let create_infos: Vec<vk::ComputePipelineCreateInfo> = stages
+                .iter()
+                .map(|stage| {
+                    vk::ComputePipelineCreateInfo::default()
+                        .stage(*stage)
+                        .layout(pipeline_layout)
+                })
+                .collect();
+            let created = self
+                .device
+                .create_compute_pipelines(pipeline_cache, &create_infos, None)
+                .map_err(|(_, e)| e)?;
+            let pipelines = [created[0], created[created.len() - 1]];
+
+            // Create descriptor pool with one set per in-flight sort
+            let pool_sizes: Vec<vk::DescriptorPoolSize> = layout
+                .bindings
+                .iter()
+                .map(|binding| {
+                    vk::DescriptorPoolSize::default()
+                        .ty(descriptor_type(binding.ty))
+                        .descriptor_count(MAX_IN_FLIGHT_SORTS)
+                })
+                .collect();
+            let descriptor_pool = self.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(MAX_IN_FLIGHT_SORTS)
+                    .pool_sizes(&pool_sizes)
+                    .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET),
+                None,
+            )?;
+
+            self.set_debug_name(shader_module, "bitonic shader module");
+            self.set_debug_name(descriptor_set_layout, "bitonic descriptor set layout");
+            self.set_debug_name(pipeline_layout, "bitonic pipeline layout");
+            self.set_debug_name(pipelines[0], "bitonic pipeline (ascending)");
+            self.set_debug_name(pipelines[1], "bitonic pipeline (descending)");
+            self.set_debug_name(descriptor_pool, "bitonic descriptor pool");
+
+            // Store the created resources
+            self.shader_module = Some(shader_module);
+            self.descriptor_set_layout = Some(descriptor_set_layout);
+            self.pipeline_layout = Some(pipeline_layout);
+            self.pipelines = Some(pipelines);
+            self.descriptor_pool = Some(descriptor_pool);
+            self.key_binding = layout.key_binding;
+
+            Ok(())
+        }
+    }
+
+    fn find_memory_type(
+        &self,
+        type_filter: u32,
+        properties: vk::MemoryPropertyFlags,
+    ) -> Result<u32> {
+        (0..self.memory_properties.memory_type_count)
+            .find(|&i| {
+                (type_filter & (1 << i)) != 0
+                    && self.memory_properties.memory_types[i as usize]
+                        .property_flags
+                        .contains(properties)
+            })
+            .ok_or_else(|| ChimeraError::Other("Failed to find suitable memory type".to_string()))
+    }
+}
+
+/// Vulkan descriptor type of a reflected binding
+fn descriptor_type(ty: BindingType) -> vk::DescriptorType {
+    match ty {
+        BindingType::StorageBuffer { .. } => vk::DescriptorType::STORAGE_BUFFER,
+        BindingType::UniformBuffer => vk::DescriptorType::UNIFORM_BUFFER,
+    }
+}
+
+impl SortRunner for AshRunner {
+    fn backend_info(
+        &self,
+    ) -> (
+        &'static str,
+        Option<&'static str>,
+        Option<String>,
+        Option<String>,
+    ) {
+        (
+            "ash",
+            Some("Vulkan"),
+            Some(self.device_name.clone()),
+            Some(format!(
+                "{} memory, {}",
+                self.memory_strategy,
+                self.queue_families()
+            )),
+        )
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn execute_kernel_pass(&self, data: &mut [u32], params: BitonicParams) -> Result<()> {
+        self.run_passes(data, &[params])
+    }
+
+    /// Upload once, record every pass into one command buffer and download once
+    fn run_bitonic_stages(&self, data: &mut [u32], order: SortOrder) -> Result<()> {
+        self.run_passes(data, &bitonic_passes(data.len() as u32, order))
+    }
+
+    fn sort_profiled<T: SortableKey + bytemuck::Pod + Send + Sync>(
+        &self,
+        data: &mut [T],
+        order: SortOrder,
+    ) -> Result<SortProfile> {
+        self.sort_with_timestamps(data, order)
+    }
+}
+
+/// A buffer and the pooled memory bound to it
+struct BufferAllocation {
+    buffer: vk::Buffer,
+    allocation: Allocation,
+    size: vk::DeviceSize,
+}
+
+impl AshRunner {
+    /// Pick the memory strategy for the device
+    ///
+    /// On unified-memory devices (integrated GPUs, software rasterizers, or
+    /// devices whose heaps are all device-local) staging only adds a copy, so
+    /// device-local requests fall back to host-visible memory.
+    fn resolve_memory_strategy(
+        requested: MemoryStrategy,
+        properties: &vk::PhysicalDeviceProperties,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    ) -> MemoryStrategy {
+        let unified_memory = matches!(
+            properties.device_type,
+            vk::PhysicalDeviceType::INTEGRATED_GPU | vk::PhysicalDeviceType::CPU
+        ) || memory_properties.memory_heaps
+            [..memory_properties.memory_heap_count as usize]
+            .iter()
+            .all(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL));
+
+        match requested {
+            MemoryStrategy::HostVisible => MemoryStrategy::HostVisible,
+            MemoryStrategy::Auto | MemoryStrategy::DeviceLocal if unified_memory => {
+                MemoryStrategy::HostVisible
+            }
+            MemoryStrategy::Auto | MemoryStrategy::DeviceLocal => MemoryStrategy::DeviceLocal,
+        }
+    }
+
+    /// Memory strategy in use after resolving [`MemoryStrategy::Auto`] and UMA fallback
+    pub fn memory_strategy(&self) -> MemoryStrategy {
+        self.memory_strategy
+    }
+
+    /// Find the first memory type matching one of `candidates`, in order of preference
+    fn find_preferred_memory_type(
+        &self,
+        type_filter: u32,
+        candidates: &[vk::MemoryPropertyFlags],
+    ) -> Result<u32> {
+        candidates
+            .iter()
+            .find_map(|&properties| self.find_memory_type(type_filter, properties).ok())
+            .ok_or_else(|| ChimeraError::Other("Failed to find suitable memory type".to_string()))
+    }
+
+    /// Device memory usage of the runner's pooled allocator
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.allocator.stats()
+    }
+
+    unsafe fn create_buffer(
+        &self,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        memory_candidates: &[vk::MemoryPropertyFlags],
+    ) -> Result<BufferAllocation> {
+        let buffer = self.device.create_buffer(
+            &vk::BufferCreateInfo::default()
+                .size(size)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+            None,
+        )?;
+
+        let requirements = self.device.get_buffer_memory_requirements(buffer);
+        let allocation = self
+            .find_preferred_memory_type(requirements.memory_type_bits, memory_candidates)
+            .and_then(|memory_type_index| self.allocator.allocate(requirements, memory_type_index));
+        let allocation = match allocation {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                self.device.destroy_buffer(buffer, None);
+                return Err(e);
+            }
+        };
+
+        if let Err(e) =
+            self.device
+                .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
+        {
+            self.allocator.free(allocation);
+            self.device.destroy_buffer(buffer, None);
+            return Err(e.into());
+        }
+
+        Ok(BufferAllocation {
+            buffer,
+            allocation,
+            size,
+        })
+    }
+
+    unsafe fn destroy_buffer(&self, buffer: BufferAllocation) {
+        self.device.destroy_buffer(buffer.buffer, None);
+        self.allocator.free(buffer.allocation);
+    }
+
+    fn mapped_ptr(buffer: &BufferAllocation) -> Result<*mut u32> {
+        buffer
+            .allocation
+            .mapped_ptr()
+            .map(|ptr| ptr as *mut u32)
+            .ok_or_else(|| ChimeraError::Other("Buffer memory is not host-visible".to_string()))
+    }
+
+    unsafe fn write_buffer(&self, buffer: &BufferAllocation, data: &[u32]) -> Result<()> {
+        std::ptr::copy_nonoverlapping(data.as_ptr(), Self::mapped_ptr(buffer)?, data.len());
+        Ok(())
+    }
+
+    unsafe fn read_buffer(&self, buffer: &BufferAllocation, data: &mut [u32]) -> Result<()> {
+        std::ptr::copy_nonoverlapping(Self::mapped_ptr(buffer)?, data.as_mut_ptr(), data.len());
+        Ok(())
+    }
+
+    unsafe fn cmd_buffer_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        src: (vk::PipelineStageFlags, vk::AccessFlags),
+        dst: (vk::PipelineStageFlags, vk::AccessFlags),
+    ) {
+        self.cmd_ownership_barrier(
+            command_buffer,
+            buffer,
+            src,
+            dst,
+            (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+        );
+    }
+
+    /// Buffer barrier that also moves ownership between queue families
+    ///
+    /// The releasing and the acquiring queue must both record it.
+    unsafe fn cmd_ownership_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        (src_stage, src_access): (vk::PipelineStageFlags, vk::AccessFlags),
+        (dst_stage, dst_access): (vk::PipelineStageFlags, vk::AccessFlags),
+        (src_family, dst_family): (u32, u32),
+    ) {
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[vk::BufferMemoryBarrier::default()
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(src_family)
+                .dst_queue_family_index(dst_family)
+                .buffer(buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)],
+            &[],
+        );
+    }
+}
+
+impl Drop for AshRunner {
+    fn drop(&mut self) {
+        unsafe {
+            // Failing to persist the cache only costs a recompile next time
+            let _ = self.save_pipeline_cache();
+
+            // Destroy cached pipeline resources
+            if let Some([ascending, descending]) = self.pipelines {
+                self.device.destroy_pipeline(ascending, None);
+                if descending != ascending {
+                    self.device.destroy_pipeline(descending, None);
+                }
+            }
+            if let Some(pipeline_layout) = self.pipeline_layout {
+                self.device.destroy_pipeline_layout(pipeline_layout, None);
+            }
+            if let Some(descriptor_set_layout) = self.descriptor_set_layout {
+                self.device
+                    .destroy_descriptor_set_layout(descriptor_set_layout, None);
+            }
+            if let Some(descriptor_pool) = self.descriptor_pool {
+                self.device.destroy_descriptor_pool(descriptor_pool, None);
+            }
+            if let Some(shader_module) = self.shader_module {
+                self.device.destroy_shader_module(shader_module, None);
+            }
+            if let Some(pipeline_cache) = self.pipeline_cache {
+                self.device.destroy_pipeline_cache(pipeline_cache, None);
+            }
+
+            self.device.destroy_command_pool(self.command_pool, None);
+            if let Some(transfer) = &self.transfer {
+                self.device
+                    .destroy_command_pool(transfer.command_pool, None);
+            }
+            ManuallyDrop::drop(&mut self.allocator);
+            if let Some(debug) = &mut self.debug {
+                debug.destroy();
+            }
+            if self.owns_device {
+                self.device.destroy_device(None);
+                self.instance.destroy_instance(None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AshRunner;
+    use crate::config::{MemoryStrategy, RunnerConfig};
+    use crate::runners::testing;
+    use crate::{verify_sorted, SortRunner};
+    use ash::vk;
+    use shared::SortOrder;
+
+    #[test]
+    fn test_bitonic_u32() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let mut data = vec![42u32, 7, 999, 0, 13, 256, 128, 511];
+
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Ascending));
+        assert_eq!(data, vec![0, 7, 13, 42, 128, 256, 511, 999]);
+    }
+
+    #[test]
+    fn test_bitonic_i32() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let mut data = vec![-42i32, 7, -999, 0, 13, -256, 128, -1];
+
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Ascending));
+        assert_eq!(data, vec![-999, -256, -42, -1, 0, 7, 13, 128]);
+    }
+
+    #[test]
+    fn test_bitonic_f32() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let mut data = vec![3.14f32, -2.71, 0.0, -0.0, 1.41, -99.9, 42.0];
+
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Ascending));
+    }
+
+    #[test]
+    fn test_bitonic_u32_descending() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let mut data = vec![42u32, 7, 999, 0, 13, 256, 128, 511];
+
+        runner.sort(&mut data, SortOrder::Descending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Descending));
+        assert_eq!(data, vec![999, 511, 256, 128, 42, 13, 7, 0]);
+    }
+
+    #[test]
+    fn test_bitonic_i32_descending() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let mut data = vec![-42i32, 7, -999, 0, 13, -256, 128, -1];
+
+        runner.sort(&mut data, SortOrder::Descending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Descending));
+        assert_eq!(data, vec![128, 13, 7, 0, -1, -42, -256, -999]);
+    }
+
+    #[test]
+    fn test_bitonic_f32_descending() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let mut data = vec![3.14f32, -2.71, 0.0, -0.0, 1.41, -99.9, 42.0];
+
+        runner.sort(&mut data, SortOrder::Descending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Descending));
+    }
+
+    #[test]
+    fn test_software_adapter() {
+        let Some(runner) = testing::software_ash_runner() else {
+            return;
+        };
+        assert!(runner.capabilities().is_software);
+
+        let mut data = vec![42u32, 7, 999, 0, 13, 256, 128, 511, 3, 1000];
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, vec![0, 3, 7, 13, 42, 128, 256, 511, 999, 1000]);
+    }
+
+    #[test]
+    fn test_specialized_workgroup_size() {
+        // No entry point is built for 96 invocations, so the pipeline specializes one
+        let config = RunnerConfig::new().workgroup_size(96);
+        let Some(runner) = testing::validated_ash_runner(config) else {
+            return;
+        };
+        assert_eq!(runner.variant.workgroup_size, 96);
+
+        let mut data: Vec<u32> = (0..5000).map(|i| (i * 7919) % 4099).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_device_local_memory() {
+        let config = crate::RunnerConfig::new().memory_strategy(MemoryStrategy::DeviceLocal);
+        let Some(runner) = testing::validated_ash_runner(config) else {
+            return;
+        };
+        // UMA devices fall back to host-visible memory, discrete ones stage
+        assert_ne!(runner.memory_strategy(), MemoryStrategy::Auto);
+
+        let mut data: Vec<u32> = (0..5000).map(|i| (i * 7919) % 4099).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_memory_reused_across_sorts() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let mut data: Vec<u32> = (0..4096).rev().collect();
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        let stats = runner.memory_stats();
+        assert_eq!(stats.live_allocations, 0);
+        assert!(stats.blocks > 0);
+
+        let mut data: Vec<u32> = (0..4096).collect();
+        runner.sort(&mut data, SortOrder::Descending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Descending));
+        assert_eq!(runner.memory_stats(), stats);
+    }
+
+    #[test]
+    fn test_pipeline_cache_persisted() {
+        let dir = testing::TempDir::new("ash-pipeline-cache");
+        let config = crate::RunnerConfig::new().pipeline_cache_dir(dir.path());
+        let Some(runner) = testing::validated_ash_runner(config.clone()) else {
+            return;
+        };
+        let path = runner
+            .pipeline_cache_file
+            .as_ref()
+            .unwrap()
+            .path()
+            .to_path_buf();
+        drop(runner);
+        assert!(path.exists());
+
+        // A second runner starts from the stored cache
+        let runner = testing::validated_ash_runner(config).unwrap();
+        let mut data = vec![5u32, 3, 9, 1];
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, vec![1, 3, 5, 9]);
+    }
+
+    #[test]
+    fn test_overlapping_submissions() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let inputs: Vec<Vec<i32>> = (0..4)
+            .map(|seed| {
+                (0..3000)
+                    .map(|i| (i * 7919 + seed * 104_729) % 6007 - 3000)
+                    .collect()
+            })
+            .collect();
+
+        // Submit everything before waiting for anything
+        let submissions: Vec<_> = inputs
+            .iter()
+            .map(|input| runner.submit(input, SortOrder::Descending).unwrap())
+            .collect();
+        assert!(submissions[3]
+            .wait(std::time::Duration::from_secs(60))
+            .unwrap());
+        assert!(submissions[3].is_complete().unwrap());
+
+        for (input, submission) in inputs.iter().zip(submissions) {
+            let mut expected = input.clone();
+            expected.sort_unstable_by(|a, b| b.cmp(a));
+            assert_eq!(submission.finish().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_dropped_submission_waits() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let data: Vec<u32> = (0..1 << 16).rev().collect();
+        drop(runner.submit(&data, SortOrder::Ascending).unwrap());
+        assert_eq!(runner.memory_stats().live_allocations, 0);
+    }
+
+    #[test]
+    fn test_dedicated_transfer_queue() {
+        let config = crate::RunnerConfig::new()
+            .memory_strategy(MemoryStrategy::DeviceLocal)
+            .dedicated_transfer_queue(true);
+        let Some(runner) = testing::validated_ash_runner(config) else {
+            return;
+        };
+        // Staging only happens with device-local memory, and the runner must
+        // take the device's transfer-only family whenever it has one
+        let queue_families = unsafe {
+            runner
+                .instance
+                .get_physical_device_queue_family_properties(runner.physical_device)
+        };
+        let families = runner.queue_families();
+        let expected = (runner.memory_strategy() == MemoryStrategy::DeviceLocal)
+            .then(|| AshRunner::find_transfer_queue_family(&queue_families, families.compute))
+            .flatten();
+        assert_eq!(families.transfer, expected);
+        if let Some(transfer) = families.transfer {
+            let flags = queue_families[transfer as usize].queue_flags;
+            assert!(flags.contains(vk::QueueFlags::TRANSFER));
+            assert!(!flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE));
+        }
+
+        // Large enough for the uploads and downloads to go through staging
+        let mut data: Vec<u32> = (0..10_000).map(|i| (i * 7919) % 10_007).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_find_transfer_queue_family() {
+        let family = |queue_flags, queue_count| vk::QueueFamilyProperties {
+            queue_flags,
+            queue_count,
+            ..Default::default()
+        };
+        let graphics = vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE;
+        let transfer = vk::QueueFlags::TRANSFER | vk::QueueFlags::SPARSE_BINDING;
+        let families = [
+            family(graphics | vk::QueueFlags::TRANSFER, 16),
+            family(vk::QueueFlags::COMPUTE | vk::QueueFlags::TRANSFER, 8),
+            family(transfer, 0),
+            family(transfer, 2),
+        ];
+        // Families with compute or no queues are skipped
+        assert_eq!(AshRunner::find_transfer_queue_family(&families, 1), Some(3));
+        assert_eq!(
+            AshRunner::find_transfer_queue_family(&families[..3], 1),
+            None
+        );
+        // The compute family itself never doubles as the transfer family
+        assert_eq!(
+            AshRunner::find_transfer_queue_family(&families[3..], 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sort_profiled() {
+        let Some(runner) = testing::ash_runner() else {
+            return;
+        };
+        let mut data: Vec<u32> = (0..1000u32).rev().collect();
+
+        let profile = runner
+            .sort_profiled(&mut data, SortOrder::Ascending)
+            .unwrap();
+        assert_eq!(data, (0..1000).collect::<Vec<_>>());
+        // 1024 padded keys take 10 stages of 1..=10 passes
+        assert_eq!(profile.passes.len(), 55);
+        assert_eq!(profile.passes.last().map(|pass| pass.stage), Some(9));
+        if runner.capabilities().timestamp_queries {
+            assert_eq!(profile.source, crate::profile::TimingSource::GpuTimestamps);
+        }
+    }
+
+    #[test]
+    fn test_external_device_and_buffer() {
+        let Some(host) = testing::HostDevice::new() else {
+            return;
+        };
+        let runner = unsafe {
+            AshRunner::from_raw(
+                host.instance.clone(),
+                host.physical_device,
+                host.device.clone(),
+                host.queue,
+                host.queue_family_index,
+                &RunnerConfig::default(),
+            )
+        }
+        .unwrap();
+
+        // Keys start at the first aligned offset past the buffer start
+        let alignment = unsafe {
+            host.instance
+                .get_physical_device_properties(host.physical_device)
+                .limits
+                .min_storage_buffer_offset_alignment
+        };
+        let skipped = (alignment as usize).div_ceil(4).max(1);
+        let keys: Vec<u32> = (0..1024u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+        let contents: Vec<u32> = std::iter::repeat_n(7, skipped)
+            .chain(keys.iter().copied())
+            .collect();
+        let offset = (skipped * 4) as u64;
+
+        unsafe {
+            let buffer = host.create_buffer(&contents);
+            runner
+                .sort_buffer(buffer.0, offset, keys.len() as u32, SortOrder::Ascending)
+                .unwrap()
+                .finish()
+                .unwrap();
+            assert!(runner
+                .sort_buffer(buffer.0, offset, 1000, SortOrder::Ascending)
+                .is_err());
+
+            // The runner leaves the host's device and buffer usable
+            drop(runner);
+            let sorted = host.read_buffer(buffer.1, contents.len());
+            let mut expected = keys;
+            expected.sort_unstable();
+            assert_eq!(&sorted[..skipped], &vec![7; skipped][..]);
+            assert_eq!(&sorted[skipped..], &expected[..]);
+            host.destroy_buffer(buffer);
+        }
+    }
+}
@@ -0,0 +1,997 @@
+//! Fence-tracked submissions for the ash runner
+//!
+//! Every sort is recorded into its own command buffer and submitted with its
+//! own fence, so several sorts can be in flight on the queue at once and the
+//! host only waits for the ones it needs.
+//!
+//! Sorts either run on buffers the runner allocates ([`AshRunner::submit`])
+//! or in place on a buffer owned by the host application
+//! ([`AshRunner::sort_buffer`]).
+
+use super::{AshRunner, BufferAllocation};
+use crate::{
+    bitonic_passes,
+    config::MemoryStrategy,
+    error::{ChimeraError, Result},
+    profile::{self, PassTiming, SortProfile, SpanRecorder, TimingSource},
+    SortRunner,
+};
+use ash::vk;
+use shared::{BitonicParams, SortOrder, SortableKey};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+/// Stage and access of the sort kernel on the storage buffer
+const SHADER_ACCESS: (vk::PipelineStageFlags, vk::AccessFlags) = (
+    vk::PipelineStageFlags::COMPUTE_SHADER,
+    vk::AccessFlags::from_raw(
+        vk::AccessFlags::SHADER_READ.as_raw() | vk::AccessFlags::SHADER_WRITE.as_raw(),
+    ),
+);
+
+/// Any use of a host application's buffer before or after the sort
+const EXTERNAL_ACCESS: (vk::PipelineStageFlags, vk::AccessFlags) = (
+    vk::PipelineStageFlags::from_raw(
+        vk::PipelineStageFlags::ALL_COMMANDS.as_raw() | vk::PipelineStageFlags::HOST.as_raw(),
+    ),
+    vk::AccessFlags::from_raw(
+        vk::AccessFlags::MEMORY_READ.as_raw()
+            | vk::AccessFlags::MEMORY_WRITE.as_raw()
+            | vk::AccessFlags::HOST_READ.as_raw()
+            | vk::AccessFlags::HOST_WRITE.as_raw(),
+    ),
+);
+
+/// Range of a buffer the kernel sorts in place
+#[derive(Clone, Copy)]
+struct BufferRange {
+    buffer: vk::Buffer,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+}
+
+/// GPU resources owned by one submitted sort until its fence signals
+pub(super) struct InFlight {
+    fence: vk::Fence,
+    command_buffer: vk::CommandBuffer,
+    descriptor_set: vk::DescriptorSet,
+    target: BufferRange,
+    /// Storage buffer allocated by the runner; `None` when sorting a host buffer
+    storage: Option<BufferAllocation>,
+    staging: Option<BufferAllocation>,
+    /// Upload and download command buffers on the dedicated transfer queue
+    transfer_command_buffers: Vec<vk::CommandBuffer>,
+    /// Order the upload, compute and download submissions across queues
+    semaphores: Vec<vk::Semaphore>,
+    /// Timestamps around the upload, after each pass and after the download; null if not profiled
+    query_pool: vk::QueryPool,
+    /// Host time spent writing the keys into mapped memory
+    host_write: Duration,
+}
+
+impl InFlight {
+    /// Host-visible buffer holding the result once the fence has signalled
+    fn readback(&self) -> Result<&BufferAllocation> {
+        self.staging
+            .as_ref()
+            .or(self.storage.as_ref())
+            .ok_or_else(|| ChimeraError::Other("Sort has no runner-owned buffer".to_string()))
+    }
+}
+
+/// A sort submitted with [`AshRunner::submit`] that may still be running
+///
+/// Dropping an unfinished submission waits for the GPU before releasing its
+/// resources.
+pub struct Submission<'a, T: SortableKey> {
+    runner: &'a AshRunner,
+    in_flight: Option<InFlight>,
+    /// Padded keys; receives the sorted result
+    data: Vec<u32>,
+    len: usize,
+    _keys: PhantomData<T>,
+}
+
+impl<T: SortableKey> Submission<'_, T> {
+    /// Whether the GPU has finished the sort, without blocking
+    pub fn is_complete(&self) -> Result<bool> {
+        match &self.in_flight {
+            Some(in_flight) => unsafe { Ok(self.runner.device.get_fence_status(in_flight.fence)?) },
+            None => Ok(true),
+        }
+    }
+
+    /// Wait up to `timeout` for the sort to finish; returns whether it did
+    pub fn wait(&self, timeout: Duration) -> Result<bool> {
+        match &self.in_flight {
+            Some(in_flight) => unsafe { self.runner.wait_in_flight(in_flight, timeout) },
+            None => Ok(true),
+        }
+    }
+
+    /// Wait for the sort to finish and return the sorted keys
+    pub fn finish(mut self) -> Result<Vec<T>> {
+        if let Some(in_flight) = self.in_flight.take() {
+            unsafe {
+                let result = self
+                    .runner
+                    .wait_in_flight(&in_flight, Duration::MAX)
+                    .and_then(|_| {
+                        self.runner
+                            .read_buffer(in_flight.readback()?, &mut self.data)
+                    });
+                self.runner.release(in_flight);
+                result?;
+            }
+        }
+
+        self.data.truncate(self.len);
+        Ok(self
+            .data
+            .iter()
+            .map(|&key| T::from_sortable_u32(key))
+            .collect())
+    }
+}
+
+impl<T: SortableKey> Drop for Submission<'_, T> {
+    fn drop(&mut self) {
+        if let Some(in_flight) = self.in_flight.take() {
+            unsafe {
+                // Resources must not be freed while the GPU may still use them
+                let _ = self.runner.wait_in_flight(&in_flight, Duration::MAX);
+                self.runner.release(in_flight);
+            }
+        }
+    }
+}
+
+/// A sort of a host application's buffer started with [`AshRunner::sort_buffer`]
+///
+/// Dropping an unfinished submission waits for the GPU before releasing its
+/// resources.
+pub struct BufferSubmission<'a> {
+    runner: &'a AshRunner,
+    in_flight: Option<InFlight>,
+}
+
+impl BufferSubmission<'_> {
+    /// Whether the GPU has finished the sort, without blocking
+    pub fn is_complete(&self) -> Result<bool> {
+        match &self.in_flight {
+            Some(in_flight) => unsafe { Ok(self.runner.device.get_fence_status(in_flight.fence)?) },
+            None => Ok(true),
+        }
+    }
+
+    /// Wait up to `timeout` for the sort to finish; returns whether it did
+    pub fn wait(&self, timeout: Duration) -> Result<bool> {
+        match &self.in_flight {
+            Some(in_flight) => unsafe { self.runner.wait_in_flight(in_flight, timeout) },
+            None => Ok(true),
+        }
+    }
+
+    /// Wait for the sort to finish; the buffer then holds the sorted keys
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(in_flight) = self.in_flight.take() {
+            unsafe {
+                let result = self.runner.wait_in_flight(&in_flight, Duration::MAX);
+                self.runner.release(in_flight);
+                result?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BufferSubmission<'_> {
+    fn drop(&mut self) {
+        if let Some(in_flight) = self.in_flight.take() {
+            unsafe {
+                let _ = self.runner.wait_in_flight(&in_flight, Duration::MAX);
+                self.runner.release(in_flight);
+            }
+        }
+    }
+}
+
+impl AshRunner {
+    /// Start sorting `data` on the GPU and return without waiting for it
+    ///
+    /// Up to [`MAX_IN_FLIGHT_SORTS`](super::MAX_IN_FLIGHT_SORTS) submissions
+    /// may be outstanding at once; they run concurrently where the device allows.
+    pub fn submit<T: SortableKey>(
+        &self,
+        data: &[T],
+        order: SortOrder,
+    ) -> Result<Submission<'_, T>> {
+        let (mut keys, len) = self.prepare_data(data);
+        let mut in_flight = None;
+
+        if len > 1 {
+            self.capabilities()
+                .check_sort(len, std::mem::size_of::<T>())?;
+            self.pad_data(&mut keys, len, order);
+            in_flight = Some(self.submit_passes(
+                &keys,
+                &bitonic_passes(keys.len() as u32, order),
+                false,
+            )?);
+        }
+
+        Ok(Submission {
+            runner: self,
+            in_flight,
+            data: keys,
+            len,
+            _keys: PhantomData,
+        })
+    }
+
+    /// Start sorting `len` keys in place in a buffer owned by the host application
+    ///
+    /// The keys are `u32`s starting at byte `offset` of `buffer`; other key
+    /// types must be stored in their [`SortableKey::to_sortable_u32`] form.
+    /// `len` must be a power of two, padded by the host with keys that sort
+    /// last, and `offset` must be a multiple of the device's
+    /// `minStorageBufferOffsetAlignment`.
+    ///
+    /// Earlier work on the runner's queue that writes the buffer is complete
+    /// before the sort reads it, and the sort's writes are visible to later
+    /// work and to the host once the submission has finished.
+    ///
+    /// # Safety
+    ///
+    /// `buffer` must have been created on the runner's device with
+    /// `STORAGE_BUFFER` usage, be bound to memory, and hold at least
+    /// `offset + 4 * len` bytes. It must not be used by other queues or
+    /// destroyed until the returned submission has finished or been dropped.
+    pub unsafe fn sort_buffer(
+        &self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        len: u32,
+        order: SortOrder,
+    ) -> Result<BufferSubmission<'_>> {
+        if len > 1 && !len.is_power_of_two() {
+            return Err(ChimeraError::Unsupported(format!(
+                "sort_buffer needs a power-of-two key count, got {len}"
+            )));
+        }
+        if offset % self.min_storage_buffer_offset_alignment != 0 {
+            return Err(ChimeraError::Unsupported(format!(
+                "buffer offset {offset} is not a multiple of the storage buffer alignment {}",
+                self.min_storage_buffer_offset_alignment
+            )));
+        }
+
+        let mut in_flight = None;
+        if len > 1 {
+            self.capabilities()
+                .check_sort(len as usize, std::mem::size_of::<u32>())?;
+            let target = BufferRange {
+                buffer,
+                offset,
+                size: len as vk::DeviceSize * std::mem::size_of::<u32>() as vk::DeviceSize,
+            };
+            let fence = self
+                .device
+                .create_fence(&vk::FenceCreateInfo::default(), None)?;
+            in_flight = Some(self.submit_in_flight(
+                InFlight {
+                    fence,
+                    command_buffer: vk::CommandBuffer::null(),
+                    descriptor_set: vk::DescriptorSet::null(),
+                    target,
+                    storage: None,
+                    staging: None,
+                    transfer_command_buffers: Vec::new(),
+                    semaphores: Vec::new(),
+                    query_pool: vk::QueryPool::null(),
+                    host_write: Duration::ZERO,
+                },
+                &bitonic_passes(len, order),
+            )?);
+        }
+
+        Ok(BufferSubmission {
+            runner: self,
+            in_flight,
+        })
+    }
+
+    /// Sort `data` with timestamp queries around the upload, each pass and the download
+    ///
+    /// Profiled sorts always run on the compute queue, so all timestamps share one timeline.
+    pub(super) fn sort_with_timestamps<T: SortableKey>(
+        &self,
+        data: &mut [T],
+        order: SortOrder,
+    ) -> Result<SortProfile> {
+        if !self.capabilities().timestamp_queries {
+            return profile::wall_clock_profile(self, data, order);
+        }
+
+        let mut spans = SpanRecorder::start();
+        let mut profile = SortProfile::new(TimingSource::GpuTimestamps);
+        if data.len() > 1 {
+            self.capabilities()
+                .check_sort(data.len(), std::mem::size_of::<T>())?;
+            let (mut keys, len) = spans.time("prepare_data", || self.prepare_data(data));
+            spans.time("pad_data", || self.pad_data(&mut keys, len, order));
+            let passes = bitonic_passes(keys.len() as u32, order);
+
+            profile.device_start = spans.elapsed();
+            let in_flight = spans.time("submit", || self.submit_passes(&keys, &passes, true))?;
+            unsafe {
+                let result = spans
+                    .time("wait", || self.wait_in_flight(&in_flight, Duration::MAX))
+                    .and_then(|_| {
+                        spans.time("read_buffer", || {
+                            self.read_buffer(in_flight.readback()?, &mut keys)
+                        })
+                    })
+                    .and_then(|()| {
+                        let host_read = spans.last();
+
+                        // Start, upload done, one per pass, download done
+                        let ticks = self.read_timestamps(in_flight.query_pool, passes.len() + 3)?;
+                        let elapsed = |from: u64, to: u64| {
+                            profile::ticks_to_duration(
+                                to.wrapping_sub(from) & self.timestamp_mask(),
+                                f64::from(self.timestamp_period),
+                            )
+                        };
+                        profile.upload = in_flight.host_write + elapsed(ticks[0], ticks[1]);
+                        for (params, pair) in passes.iter().zip(ticks[1..].windows(2)) {
+                            profile
+                                .passes
+                                .push(PassTiming::new(params, elapsed(pair[0], pair[1])));
+                        }
+                        let last = ticks.len() - 1;
+                        profile.download = elapsed(ticks[last - 1], ticks[last]) + host_read;
+                        Ok(())
+                    });
+                self.release(in_flight);
+                result?;
+            }
+
+            keys.truncate(len);
+            spans.time("finalize_data", || self.finalize_data(&keys, data));
+        }
+        spans.finish(&mut profile);
+        Ok(profile)
+    }
+
+    /// Read `count` timestamps from a query pool whose submission has finished
+    unsafe fn read_timestamps(&self, query_pool: vk::QueryPool, count: usize) -> Result<Vec<u64>> {
+        let mut ticks = vec![0u64; count];
+        self.device.get_query_pool_results(
+            query_pool,
+            0,
+            &mut ticks,
+            vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+        )?;
+        Ok(ticks)
+    }
+
+    /// Bits of a timestamp that hold the counter
+    fn timestamp_mask(&self) -> u64 {
+        match self.timestamp_valid_bits {
+            64.. => u64::MAX,
+            bits => (1 << bits) - 1,
+        }
+    }
+
+    /// Upload `data`, run every pass in `passes` in one submission and read the result back
+    pub(super) fn run_passes(&self, data: &mut [u32], passes: &[BitonicParams]) -> Result<()> {
+        if passes.is_empty() {
+            return Ok(());
+        }
+
+        let in_flight = self.submit_passes(data, passes, false)?;
+        unsafe {
+            let result = self
+                .wait_in_flight(&in_flight, Duration::MAX)
+                .and_then(|_| self.read_buffer(in_flight.readback()?, data));
+            self.release(in_flight);
+            result
+        }
+    }
+
+    /// Wait for the fence of `in_flight`; returns `false` if `timeout` elapsed first
+    unsafe fn wait_in_flight(&self, in_flight: &InFlight, timeout: Duration) -> Result<bool> {
+        let timeout_ns = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        match self
+            .device
+            .wait_for_fences(&[in_flight.fence], true, timeout_ns)
+        {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Free everything owned by a submission whose fence has signalled
+    unsafe fn release(&self, in_flight: InFlight) {
+        {
+            let _pools = self.pool_lock.lock();
+            self.device
+                .free_command_buffers(self.command_pool, &[in_flight.command_buffer]);
+            if let Some(descriptor_pool) = self.descriptor_pool {
+                let _ = self
+                    .device
+                    .free_descriptor_sets(descriptor_pool, &[in_flight.descriptor_set]);
+            }
+            if let Some(transfer) = &self.transfer {
+                if !in_flight.transfer_command_buffers.is_empty() {
+                    self.device.free_command_buffers(
+                        transfer.command_pool,
+                        &in_flight.transfer_command_buffers,
+                    );
+                }
+            }
+        }
+        for semaphore in in_flight.semaphores {
+            self.device.destroy_semaphore(semaphore, None);
+        }
+        self.device.destroy_fence(in_flight.fence, None);
+        if in_flight.query_pool != vk::QueryPool::null() {
+            self.device.destroy_query_pool(in_flight.query_pool, None);
+        }
+        if let Some(storage) = in_flight.storage {
+            self.destroy_buffer(storage);
+        }
+        if let Some(staging) = in_flight.staging {
+            self.destroy_buffer(staging);
+        }
+    }
+
+    /// Allocate buffers for `data`, upload it and submit all `passes` behind one fence
+    ///
+    /// With `profiled`, timestamps are written into a query pool owned by the submission.
+    fn submit_passes(
+        &self,
+        data: &[u32],
+        passes: &[BitonicParams],
+        profiled: bool,
+    ) -> Result<InFlight> {
+        unsafe {
+            let buffer_size = std::mem::size_of_val(data) as vk::DeviceSize;
+            let staged = self.memory_strategy == MemoryStrategy::DeviceLocal;
+
+            let host_memory =
+                [vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT];
+            let (storage_memory, staging) = if staged {
+                let staging = self.create_buffer(
+                    buffer_size,
+                    vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+                    &host_memory,
+                )?;
+                self.set_debug_name(staging.buffer, "bitonic staging buffer");
+                (&[vk::MemoryPropertyFlags::DEVICE_LOCAL][..], Some(staging))
+            } else {
+                // Prefer memory that is both host-visible and device-local on UMA devices
+                (
+                    &[
+                        vk::MemoryPropertyFlags::DEVICE_LOCAL
+                            | vk::MemoryPropertyFlags::HOST_VISIBLE
+                            | vk::MemoryPropertyFlags::HOST_COHERENT,
+                        host_memory[0],
+                    ][..],
+                    None,
+                )
+            };
+
+            let storage = match self.create_buffer(
+                buffer_size,
+                vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_DST
+                    | vk::BufferUsageFlags::TRANSFER_SRC,
+                storage_memory,
+            ) {
+                Ok(storage) => storage,
+                Err(e) => {
+                    if let Some(staging) = staging {
+                        self.destroy_buffer(staging);
+                    }
+                    return Err(e);
+                }
+            };
+            self.set_debug_name(storage.buffer, "bitonic storage buffer");
+
+            let host_write = Instant::now();
+            let fence = match self
+                .write_buffer(staging.as_ref().unwrap_or(&storage), data)
+                .and_then(|()| {
+                    Ok(self
+                        .device
+                        .create_fence(&vk::FenceCreateInfo::default(), None)?)
+                }) {
+                Ok(fence) => fence,
+                Err(e) => {
+                    self.destroy_buffer(storage);
+                    if let Some(staging) = staging {
+                        self.destroy_buffer(staging);
+                    }
+                    return Err(e);
+                }
+            };
+
+            let host_write = host_write.elapsed();
+
+            let target = BufferRange {
+                buffer: storage.buffer,
+                offset: 0,
+                size: storage.size,
+            };
+            let mut in_flight = InFlight {
+                fence,
+                command_buffer: vk::CommandBuffer::null(),
+                descriptor_set: vk::DescriptorSet::null(),
+                target,
+                storage: Some(storage),
+                staging,
+                transfer_command_buffers: Vec::new(),
+                semaphores: Vec::new(),
+                query_pool: vk::QueryPool::null(),
+                host_write,
+            };
+            if profiled {
+                match self.device.create_query_pool(
+                    &vk::QueryPoolCreateInfo::default()
+                        .query_type(vk::QueryType::TIMESTAMP)
+                        .query_count(passes.len() as u32 + 3),
+                    None,
+                ) {
+                    Ok(query_pool) => in_flight.query_pool = query_pool,
+                    Err(e) => {
+                        self.release(in_flight);
+                        return Err(e.into());
+                    }
+                }
+            }
+            self.submit_in_flight(in_flight, passes)
+        }
+    }
+
+    /// Record and submit `in_flight`, releasing it if submission fails
+    ///
+    /// Submissions that fail part-way wait for the queues to drain first, so
+    /// the release never frees resources the GPU still uses.
+    unsafe fn submit_in_flight(
+        &self,
+        mut in_flight: InFlight,
+        passes: &[BitonicParams],
+    ) -> Result<InFlight> {
+        let pools = self.pool_lock.lock();
+        let submitted = self.record_and_submit(&mut in_flight, passes);
+        drop(pools);
+
+        match submitted {
+            Ok(()) => Ok(in_flight),
+            Err(e) => {
+                // Nothing submitted is still running, so the resources can be released right away
+                self.release(in_flight);
+                Err(e)
+            }
+        }
+    }
+
+    /// Record the upload, every pass and the download, and submit them
+    ///
+    /// Must be called with `pool_lock` held.
+    unsafe fn record_and_submit(
+        &self,
+        in_flight: &mut InFlight,
+        passes: &[BitonicParams],
+    ) -> Result<()> {
+        let descriptor_pool = self
+            .descriptor_pool
+            .ok_or_else(|| ChimeraError::Other("Descriptor pool not initialized".to_string()))?;
+        let descriptor_set_layout = self.descriptor_set_layout.ok_or_else(|| {
+            ChimeraError::Other("Descriptor set layout not initialized".to_string())
+        })?;
+
+        // Allocate descriptor set
+        let descriptor_set = self
+            .device
+            .allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(&[descriptor_set_layout]),
+            )
+            .map_err(|e| match e {
+                vk::Result::ERROR_OUT_OF_POOL_MEMORY => ChimeraError::Other(format!(
+                    "More than {} sorts in flight",
+                    super::MAX_IN_FLIGHT_SORTS
+                )),
+                e => e.into(),
+            })?[0];
+        in_flight.descriptor_set = descriptor_set;
+
+        // Update descriptor set
+        self.device.update_descriptor_sets(
+            &[vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(self.key_binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&[vk::DescriptorBufferInfo::default()
+                    .buffer(in_flight.target.buffer)
+                    .offset(in_flight.target.offset)
+                    .range(in_flight.target.size)])],
+            &[],
+        );
+
+        // Create command buffer
+        let command_buffer = self.allocate_command_buffer(self.command_pool)?;
+        in_flight.command_buffer = command_buffer;
+        self.set_debug_name(command_buffer, "bitonic sort");
+
+        match (&self.transfer, &in_flight.staging) {
+            (Some(transfer), Some(_)) if in_flight.query_pool == vk::QueryPool::null() => {
+                self.submit_with_transfer_queue(in_flight, transfer, passes)
+            }
+            _ => self.submit_on_compute_queue(in_flight, passes),
+        }
+    }
+
+    unsafe fn allocate_command_buffer(
+        &self,
+        command_pool: vk::CommandPool,
+    ) -> Result<vk::CommandBuffer> {
+        let command_buffer = self.device.allocate_command_buffers(
+            &vk::CommandBufferAllocateInfo::default()
+                .command_pool(command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1),
+        )?[0];
+        self.device.begin_command_buffer(
+            command_buffer,
+            &vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+        )?;
+        Ok(command_buffer)
+    }
+
+    /// Record the upload, every pass and the download into one compute command buffer
+    unsafe fn submit_on_compute_queue(
+        &self,
+        in_flight: &mut InFlight,
+        passes: &[BitonicParams],
+    ) -> Result<()> {
+        let command_buffer = in_flight.command_buffer;
+        let target = in_flight.target.buffer;
+        let query_pool = in_flight.query_pool;
+        let profiled = query_pool != vk::QueryPool::null();
+        let query_count = passes.len() as u32 + 3;
+
+        if profiled {
+            self.device
+                .cmd_reset_query_pool(command_buffer, query_pool, 0, query_count);
+            self.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                query_pool,
+                0,
+            );
+        }
+
+        match (&in_flight.storage, &in_flight.staging) {
+            (Some(storage), Some(staging)) => {
+                self.device.cmd_copy_buffer(
+                    command_buffer,
+                    staging.buffer,
+                    storage.buffer,
+                    &[vk::BufferCopy::default().size(storage.size)],
+                );
+                self.cmd_buffer_barrier(
+                    command_buffer,
+                    storage.buffer,
+                    (
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                    SHADER_ACCESS,
+                );
+            }
+            // Wait for whatever the host application did with its buffer
+            (None, _) => {
+                self.cmd_buffer_barrier(command_buffer, target, EXTERNAL_ACCESS, SHADER_ACCESS)
+            }
+            (Some(_), None) => {}
+        }
+
+        if profiled {
+            self.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                query_pool,
+                1,
+            );
+        }
+
+        self.record_passes(
+            command_buffer,
+            in_flight.descriptor_set,
+            target,
+            passes,
+            profiled.then_some((query_pool, 2)),
+        )?;
+
+        match (&in_flight.storage, &in_flight.staging) {
+            (Some(storage), Some(staging)) => {
+                self.cmd_buffer_barrier(
+                    command_buffer,
+                    storage.buffer,
+                    SHADER_ACCESS,
+                    (
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                );
+                self.record_download(command_buffer, storage, staging);
+            }
+            (None, _) => {
+                self.cmd_buffer_barrier(command_buffer, target, SHADER_ACCESS, EXTERNAL_ACCESS)
+            }
+            (Some(_), None) => self.cmd_buffer_barrier(
+                command_buffer,
+                target,
+                SHADER_ACCESS,
+                (vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ),
+            ),
+        }
+
+        if profiled {
+            self.device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                query_pool,
+                query_count - 1,
+            );
+        }
+
+        self.device.end_command_buffer(command_buffer)?;
+
+        // The fence signals once this sort alone has finished
+        self.device.queue_submit(
+            self.queue,
+            &[vk::SubmitInfo::default().command_buffers(&[command_buffer])],
+            in_flight.fence,
+        )?;
+        Ok(())
+    }
+
+    /// Upload on the transfer queue, sort on the compute queue, download on the transfer queue
+    ///
+    /// The storage buffer is exclusively owned, so each hand-over between the
+    /// two families is a release barrier on one queue and a matching acquire
+    /// barrier on the other, ordered by a semaphore.
+    unsafe fn submit_with_transfer_queue(
+        &self,
+        in_flight: &mut InFlight,
+        transfer: &super::TransferQueue,
+        passes: &[BitonicParams],
+    ) -> Result<()> {
+        let compute_family = self.queue_family_index;
+        let transfer_family = transfer.family_index;
+        let storage_buffer = in_flight.target.buffer;
+        let released = (
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::AccessFlags::empty(),
+        );
+        // Acquire barriers start at the stage their semaphore wait blocks, so they chain with it
+        let acquired_at = |stage| (stage, vk::AccessFlags::empty());
+
+        for _ in 0..2 {
+            let semaphore = self
+                .device
+                .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)?;
+            in_flight.semaphores.push(semaphore);
+        }
+        let (uploaded, sorted) = (in_flight.semaphores[0], in_flight.semaphores[1]);
+
+        let upload = self.allocate_command_buffer(transfer.command_pool)?;
+        in_flight.transfer_command_buffers.push(upload);
+        let download = self.allocate_command_buffer(transfer.command_pool)?;
+        in_flight.transfer_command_buffers.push(download);
+        self.set_debug_name(upload, "bitonic upload");
+        self.set_debug_name(download, "bitonic download");
+
+        let (Some(storage), Some(staging)) = (&in_flight.storage, &in_flight.staging) else {
+            return Err(ChimeraError::Other(
+                "Transfer queue needs storage and staging buffers".to_string(),
+            ));
+        };
+
+        // Upload, then release the storage buffer to the compute family
+        self.device.cmd_copy_buffer(
+            upload,
+            staging.buffer,
+            storage_buffer,
+            &[vk::BufferCopy::default().size(storage.size)],
+        );
+        self.cmd_ownership_barrier(
+            upload,
+            storage_buffer,
+            (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+            released,
+            (transfer_family, compute_family),
+        );
+        self.device.end_command_buffer(upload)?;
+
+        // Acquire, sort, and release back to the transfer family
+        let command_buffer = in_flight.command_buffer;
+        self.cmd_ownership_barrier(
+            command_buffer,
+            storage_buffer,
+            acquired_at(vk::PipelineStageFlags::COMPUTE_SHADER),
+            SHADER_ACCESS,
+            (transfer_family, compute_family),
+        );
+        self.record_passes(
+            command_buffer,
+            in_flight.descriptor_set,
+            storage_buffer,
+            passes,
+            None,
+        )?;
+        self.cmd_ownership_barrier(
+            command_buffer,
+            storage_buffer,
+            SHADER_ACCESS,
+            released,
+            (compute_family, transfer_family),
+        );
+        self.device.end_command_buffer(command_buffer)?;
+
+        // Acquire and download
+        self.cmd_ownership_barrier(
+            download,
+            storage_buffer,
+            acquired_at(vk::PipelineStageFlags::TRANSFER),
+            (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            (compute_family, transfer_family),
+        );
+        self.record_download(download, storage, staging);
+        self.device.end_command_buffer(download)?;
+
+        self.device.queue_submit(
+            transfer.queue,
+            &[vk::SubmitInfo::default()
+                .command_buffers(&[upload])
+                .signal_semaphores(&[uploaded])],
+            vk::Fence::null(),
+        )?;
+        // Once the upload is submitted, a failed submission must not return
+        // before the GPU is done with the resources `release` frees
+        let drain_queues = |e: vk::Result| {
+            let _ = self.device.queue_wait_idle(transfer.queue);
+            let _ = self.device.queue_wait_idle(self.queue);
+            e
+        };
+        self.device
+            .queue_submit(
+                self.queue,
+                &[vk::SubmitInfo::default()
+                    .wait_semaphores(&[uploaded])
+                    .wait_dst_stage_mask(&[vk::PipelineStageFlags::COMPUTE_SHADER])
+                    .command_buffers(&[command_buffer])
+                    .signal_semaphores(&[sorted])],
+                vk::Fence::null(),
+            )
+            .map_err(drain_queues)?;
+        // The fence signals once this sort alone has finished
+        self.device
+            .queue_submit(
+                transfer.queue,
+                &[vk::SubmitInfo::default()
+                    .wait_semaphores(&[sorted])
+                    .wait_dst_stage_mask(&[vk::PipelineStageFlags::TRANSFER])
+                    .command_buffers(&[download])],
+                in_flight.fence,
+            )
+            .map_err(drain_queues)?;
+        Ok(())
+    }
+
+    /// Bind the pipeline and record one dispatch per pass
+    ///
+    /// With `timestamps`, the end of pass `i` is written to query `first + i` of the pool.
+    unsafe fn record_passes(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        descriptor_set: vk::DescriptorSet,
+        buffer: vk::Buffer,
+        passes: &[BitonicParams],
+        timestamps: Option<(vk::QueryPool, u32)>,
+    ) -> Result<()> {
+        let workgroup_size = self.variant.workgroup_size;
+
+        // Use cached pipeline resources; every pass of a sort has the same order
+        let descending = passes
+            .first()
+            .is_some_and(|params| params.sort_order == u32::from(SortOrder::Descending));
+        let pipeline = self
+            .pipelines
+            .map(|pipelines| pipelines[usize::from(descending)])
+            .ok_or_else(|| ChimeraError::Other("Pipeline not initialized".to_string()))?;
+        let pipeline_layout = self
+            .pipeline_layout
+            .ok_or_else(|| ChimeraError::Other("Pipeline layout not initialized".to_string()))?;
+
+        self.device
+            .cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+        self.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            pipeline_layout,
+            0,
+            &[descriptor_set],
+            &[],
+        );
+
+        for (i, params) in passes.iter().enumerate() {
+            if i > 0 {
+                // Each pass reads what the previous one wrote
+                self.cmd_buffer_barrier(command_buffer, buffer, SHADER_ACCESS, SHADER_ACCESS);
+            }
+
+            // Push constants - use the params for this pass
+            self.device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                bytemuck::bytes_of(params),
+            );
+            self.device.cmd_dispatch(
+                command_buffer,
+                params.num_elements.div_ceil(workgroup_size),
+                1,
+                1,
+            );
+            if let Some((query_pool, first)) = timestamps {
+                self.device.cmd_write_timestamp(
+                    command_buffer,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    query_pool,
+                    first + i as u32,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the sorted keys into the staging buffer and make them visible to the host
+    unsafe fn record_download(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        storage: &BufferAllocation,
+        staging: &BufferAllocation,
+    ) {
+        self.device.cmd_copy_buffer(
+            command_buffer,
+            storage.buffer,
+            staging.buffer,
+            &[vk::BufferCopy::default().size(storage.size)],
+        );
+        self.cmd_buffer_barrier(
+            command_buffer,
+            staging.buffer,
+            (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+            (vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ),
+        );
+    }
+}
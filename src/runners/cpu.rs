@@ -1,6 +1,10 @@
 //! CPU execution for compute kernels
 
-use crate::{error::Result, SortRunner};
+use crate::{
+    capabilities::{Capabilities, MAX_BITONIC_ELEMENTS},
+    error::Result,
+    SortRunner,
+};
 use kernel::bitonic_sort_step;
 use shared::{BitonicParams, SortOrder, ThreadId};
 
@@ -19,6 +23,18 @@ impl SortRunner for CpuRunner {
         ("cpu", Some("Native"), None, None)
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            max_elements: MAX_BITONIC_ELEMENTS,
+            // Every `SortableKey` maps to a `u32`
+            supports_64bit_keys: false,
+            max_push_constant_size: 0,
+            subgroup_operations: false,
+            timestamp_queries: false,
+            is_software: true,
+        }
+    }
+
     fn execute_kernel_pass(&self, data: &mut [u32], params: BitonicParams) -> Result<()> {
         // Process all threads (on CPU, we simulate parallel execution)
         for thread_idx in 0..params.num_elements {
@@ -42,8 +58,34 @@ impl SortRunner for CpuRunner {
 #[cfg(test)]
 mod tests {
     use super::CpuRunner;
-    use crate::{verify_sorted, SortRunner};
-    use shared::SortOrder;
+    use crate::{error::ChimeraError, verify_sorted, SortRunner};
+    use shared::{SortOrder, SortableKey};
+
+    /// An 8-byte key, which no runner can sort yet
+    #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+    #[repr(transparent)]
+    struct WideKey(u64);
+
+    unsafe impl bytemuck::Zeroable for WideKey {}
+    unsafe impl bytemuck::Pod for WideKey {}
+
+    impl SortableKey for WideKey {
+        fn to_sortable_u32(&self) -> u32 {
+            self.0 as u32
+        }
+
+        fn from_sortable_u32(val: u32) -> Self {
+            WideKey(u64::from(val))
+        }
+
+        fn max_value() -> Self {
+            WideKey(u64::MAX)
+        }
+
+        fn min_value() -> Self {
+            WideKey(u64::MIN)
+        }
+    }
 
     #[test]
     fn test_bitonic_u32() {
@@ -102,4 +144,16 @@ mod tests {
         runner.sort(&mut data, SortOrder::Descending).unwrap();
         assert!(verify_sorted(&data, SortOrder::Descending));
     }
+
+    #[test]
+    fn test_rejects_64bit_keys() {
+        let runner = CpuRunner;
+        let mut data = vec![WideKey(3), WideKey(1 << 40), WideKey(2)];
+
+        assert!(matches!(
+            runner.sort(&mut data, SortOrder::Ascending),
+            Err(ChimeraError::Unsupported(_))
+        ));
+        assert_eq!(data, vec![WideKey(3), WideKey(1 << 40), WideKey(2)]);
+    }
 }
@@ -1,94 +1,609 @@
 //! CUDA runner implementation
 
-use crate::{error::Result, SortRunner};
+use crate::{
+    capabilities::Capabilities,
+    config::{Backends, DeviceInfo, DeviceType, RunnerConfig},
+    error::{ChimeraError, Result},
+    kernel::{KernelVariant, ParamsSource, PTX_KERNELS},
+    profile::{PassTiming, SortProfile, SpanRecorder, TimingSource},
+    SortRunner,
+};
+use cust::device::DeviceAttribute;
+use cust::event::{Event, EventFlags};
+use cust::function::Function;
+use cust::memory::{AsyncCopyDestination, LockedBuffer};
 use cust::prelude::*;
-use shared::{BitonicParams, BLOCK_SIZE};
-use std::sync::OnceLock;
+use cust::sys;
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+use shared::{BitonicParams, SortOrder, SortableKey};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+use std::time::Duration;
 
-// Global CUDA context to ensure single initialization
-static CUDA_CONTEXT: OnceLock<Context> = OnceLock::new();
+/// A device's primary context and the kernel modules loaded into it
+struct DeviceContext {
+    context: Context,
+    /// Modules by PTX source, kept for the lifetime of the context
+    modules: Mutex<HashMap<String, &'static Module>>,
+}
+
+impl DeviceContext {
+    /// Load `ptx` into this context, reusing the module of an earlier runner with the same PTX
+    ///
+    /// The context must be current.
+    fn module(&self, ptx: &str) -> Result<&'static Module> {
+        let mut modules = self.modules.lock();
+        if let Some(&module) = modules.get(ptx) {
+            return Ok(module);
+        }
+        let module: &'static Module = Box::leak(Box::new(Module::from_ptx(ptx, &[])?));
+        modules.insert(ptx.to_string(), module);
+        Ok(module)
+    }
+}
+
+// Contexts by device ordinal, created on first use and shared by every runner on that device.
+// They live for the rest of the process, at most one per device.
+static CUDA_CONTEXTS: LazyLock<Mutex<HashMap<u32, &'static DeviceContext>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Makes a context current on this thread until dropped, then restores the previous one
+struct ContextGuard {
+    // Context stacks are per thread
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ContextGuard {
+    fn push(context: &Context) -> Result<Self> {
+        check(
+            unsafe { sys::cuCtxPushCurrent_v2(context.as_raw()) },
+            "cuCtxPushCurrent",
+        )?;
+        Ok(Self {
+            _not_send: PhantomData,
+        })
+    }
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let mut popped = std::ptr::null_mut();
+        unsafe { sys::cuCtxPopCurrent_v2(&mut popped) };
+    }
+}
+
+/// The shared context of device `ordinal`, creating it on first use
+fn device_context(device: Device, ordinal: u32) -> Result<&'static DeviceContext> {
+    let mut contexts = CUDA_CONTEXTS.lock();
+    if let Some(&device_context) = contexts.get(&ordinal) {
+        return Ok(device_context);
+    }
+
+    let device_context = Box::leak(Box::new(DeviceContext {
+        context: Context::new(device)?,
+        modules: Mutex::new(HashMap::new()),
+    }));
+    contexts.insert(ordinal, device_context);
+    Ok(device_context)
+}
+
+/// First driver version with `cuGraphInstantiateWithFlags` (CUDA 11.4)
+const MIN_GRAPH_DRIVER_VERSION: i32 = 11040;
+
+/// Captured sorts kept per runner; each owns a device buffer of its padded length
+const MAX_CACHED_GRAPHS: usize = 8;
+
+/// Map a raw driver API result to an error naming the failed call
+fn check(result: sys::CUresult, call: &str) -> Result<()> {
+    if result == sys::CUresult::CUDA_SUCCESS {
+        Ok(())
+    } else {
+        Err(ChimeraError::Other(format!("{call} failed: {result:?}")))
+    }
+}
+
+/// Like [`check`], but `Ok(false)` if the result means the driver cannot build graphs
+fn check_graph(result: sys::CUresult, call: &str) -> Result<bool> {
+    match result {
+        sys::CUresult::CUDA_ERROR_NOT_SUPPORTED
+        | sys::CUresult::CUDA_ERROR_STREAM_CAPTURE_UNSUPPORTED => Ok(false),
+        result => check(result, call).map(|()| true),
+    }
+}
+
+/// Every pass of a sort captured as a CUDA graph
+///
+/// Captured launches point at `device_data`, so the graph owns the buffer it sorts.
+struct SortGraph {
+    graph: sys::CUgraph,
+    exec: sys::CUgraphExec,
+    device_data: DeviceBuffer<u32>,
+}
+
+// The graph handles are only used while holding the runner's graph cache lock
+unsafe impl Send for SortGraph {}
+
+impl Drop for SortGraph {
+    fn drop(&mut self) {
+        unsafe {
+            sys::cuGraphExecDestroy(self.exec);
+            sys::cuGraphDestroy(self.graph);
+        }
+    }
+}
 
 /// CUDA-based runner for bitonic sort using NVIDIA GPUs
+///
+/// Runners on the same device share its context; every call makes that
+/// context current on the calling thread, so a runner can be used from any
+/// thread and runners on different devices can be mixed freely.
 pub struct CudaRunner {
-    module: Module,
-    stream: Stream,
+    device: &'static DeviceContext,
+    ordinal: u32,
+    /// Kernels sorting in each [`SortOrder`], indexed by it
+    kernels: [Function<'static>; 2],
+    /// Kernel variant in use; its workgroup size is the launch block size
+    variant: &'static KernelVariant,
+    /// Pinned host buffer sized to the largest sort so far; freed in `Drop`
+    staging: Mutex<Option<LockedBuffer<u32>>>,
+    /// Destroyed in `Drop` while the context is current
+    stream: ManuallyDrop<Stream>,
+    /// Only used for graph captures, under the `graphs` lock, so no other
+    /// work lands in a capture; destroyed in `Drop` like `stream`
+    capture_stream: ManuallyDrop<Stream>,
     device_name: String,
+    capabilities: Capabilities,
+    /// Captured sorts keyed by padded length and sort order, least recently used first
+    graphs: Mutex<Vec<((u32, u32), SortGraph)>>,
+    /// Cleared when the driver is too old or cannot capture graphs
+    graphs_supported: AtomicBool,
 }
 
 impl CudaRunner {
-    /// Create a new CUDA runner, initializing the CUDA context if needed
+    /// Create a new CUDA runner on the device chosen by the default [`RunnerConfig`]
     pub fn new() -> Result<Self> {
-        // Initialize CUDA context only once
-        let _ctx = CUDA_CONTEXT.get_or_try_init(cust::quick_init)?;
+        Self::with_config(&RunnerConfig::default())
+    }
+
+    /// Create a new CUDA runner on the device selected by `config`
+    ///
+    /// Without an explicit selector the power preference picks the device:
+    /// discrete GPUs before integrated ones by default, and the lowest ordinal
+    /// among devices of the same type.
+    /// The device's context is created on first use and shared with later runners.
+    pub fn with_config(config: &RunnerConfig) -> Result<Self> {
+        if !config.backends.contains(Backends::CUDA) {
+            return Err(ChimeraError::Unsupported(
+                "the CUDA backend is disabled in RunnerConfig".to_string(),
+            ));
+        }
+
+        let devices = Self::enumerate_devices()?;
+        let position = config
+            .select_device(&devices)
+            .ok_or(ChimeraError::NoAdapter)?;
+        let ordinal = devices[position].index as u32;
+        let device = Device::get_device(ordinal)?;
+
+        let device_context = device_context(device, ordinal)?;
+        let _current = ContextGuard::push(&device_context.context)?;
 
         // Get device info
-        let device = Device::get_device(0)?;
         let device_name = device.name()?;
+        let max_block_size = device
+            .get_attribute(DeviceAttribute::MaxThreadsPerBlock)?
+            .min(device.get_attribute(DeviceAttribute::MaxBlockDimX)?)
+            as u32;
+        let variant = KernelVariant::select(
+            PTX_KERNELS,
+            ParamsSource::KernelArgument,
+            max_block_size,
+            config.workgroup_size,
+        )?;
+        let capabilities = Self::query_capabilities(&device, variant.workgroup_size)?;
 
-        // Use the embedded PTX from the main crate
-        let ptx_data = crate::BITONIC_PTX;
-        let module = Module::from_ptx(ptx_data, &[])?;
+        let (ptx, target) = config.ptx_kernel.ptx(variant)?;
+        let major = device.get_attribute(DeviceAttribute::ComputeCapabilityMajor)? as u32;
+        let minor = device.get_attribute(DeviceAttribute::ComputeCapabilityMinor)? as u32;
+        let compute_capability = 10 * major + minor;
+        if target > compute_capability {
+            return Err(ChimeraError::InvalidKernel(format!(
+                "the PTX targets sm_{target}, but {device_name} is sm_{compute_capability}"
+            )));
+        }
+        // Prefer the entry points with the order compiled in; PTX loaded from a
+        // file may only have the one reading the order from its params
+        let module = device_context.module(&ptx)?;
+        let kernel = |order| -> Result<Function<'static>> {
+            let entry_point = variant
+                .specialized(PTX_KERNELS, order)
+                .map_or(variant.entry_point, |specialized| specialized.entry_point);
+            Ok(module
+                .get_function(entry_point)
+                .or_else(|_| module.get_function(variant.entry_point))?)
+        };
+        let kernels = [
+            kernel(SortOrder::Ascending)?,
+            kernel(SortOrder::Descending)?,
+        ];
 
-        // Create stream
+        // Create streams
         let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+        let capture_stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+
+        let mut driver_version = 0;
+        check(
+            unsafe { sys::cuDriverGetVersion(&mut driver_version) },
+            "cuDriverGetVersion",
+        )?;
 
         Ok(Self {
-            module,
-            stream,
+            device: device_context,
+            ordinal,
+            kernels,
+            variant,
+            staging: Mutex::new(None),
+            stream: ManuallyDrop::new(stream),
+            capture_stream: ManuallyDrop::new(capture_stream),
             device_name,
+            capabilities,
+            graphs: Mutex::new(Vec::new()),
+            graphs_supported: AtomicBool::new(driver_version >= MIN_GRAPH_DRIVER_VERSION),
         })
     }
-}
 
-impl SortRunner for CudaRunner {
-    fn backend_info(
-        &self,
-    ) -> (
-        &'static str,
-        Option<&'static str>,
-        Option<String>,
-        Option<String>,
-    ) {
-        ("cust", Some("CUDA"), Some(self.device_name.clone()), None)
+    /// List the CUDA devices
+    ///
+    /// The returned indices can be passed to [`RunnerConfig::device_index`].
+    pub fn enumerate_devices() -> Result<Vec<DeviceInfo>> {
+        cust::init(cust::CudaFlags::empty())?;
+
+        (0..Device::num_devices()?)
+            .map(|ordinal| {
+                let device = Device::get_device(ordinal)?;
+                let device_type = if device.get_attribute(DeviceAttribute::Integrated)? != 0 {
+                    DeviceType::IntegratedGpu
+                } else {
+                    DeviceType::DiscreteGpu
+                };
+
+                Ok(DeviceInfo {
+                    host: "cust",
+                    backend: "CUDA",
+                    index: ordinal as usize,
+                    name: device.name()?,
+                    device_type,
+                    driver: None,
+                })
+            })
+            .collect()
     }
 
-    fn execute_kernel_pass(&self, data: &mut [u32], params: BitonicParams) -> Result<()> {
-        // Get kernel function
-        let kernel = self.module.get_function("bitonic_kernel")?;
+    /// Derive runner capabilities from CUDA device attributes
+    fn query_capabilities(device: &Device, block_size: u32) -> Result<Capabilities> {
+        let max_grid_x = device.get_attribute(DeviceAttribute::MaxGridDimX)? as u64;
+        let max_by_launch = max_grid_x * u64::from(block_size);
+        let max_by_memory = device.total_memory()? as u64 / std::mem::size_of::<u32>() as u64;
 
-        // Set up launch configuration
-        let block_size = BLOCK_SIZE;
-        let grid_size = params.num_elements.div_ceil(block_size);
+        Ok(Capabilities {
+            max_elements: Capabilities::clamp_max_elements(max_by_launch.min(max_by_memory)),
+            // Only `u32` kernels are built
+            supports_64bit_keys: false,
+            // Kernel parameters are limited to 4 KiB
+            max_push_constant_size: 4096,
+            // Warp shuffles and votes are always available
+            subgroup_operations: true,
+            // Timing is done with CUDA events
+            timestamp_queries: true,
+            is_software: false,
+        })
+    }
+}
 
-        // Allocate device memory
-        let device_data = DeviceBuffer::from_slice(data)?;
+impl CudaRunner {
+    /// Ordinal of the device this runner sorts on
+    pub fn device_ordinal(&self) -> u32 {
+        self.ordinal
+    }
 
-        // Launch kernel
-        let stream = &self.stream;
+    /// Make this runner's context current on the calling thread
+    fn make_current(&self) -> Result<ContextGuard> {
+        ContextGuard::push(&self.device.context)
+    }
+
+    /// Lock the pinned staging buffer, growing it to hold at least `len` keys
+    ///
+    /// Pinning costs more than the copies it speeds up, so the buffer is kept
+    /// across sorts. The context must be current.
+    fn staging(&self, len: usize) -> Result<MappedMutexGuard<'_, LockedBuffer<u32>>> {
+        let mut staging = self.staging.lock();
+        if staging.as_ref().is_none_or(|buffer| buffer.len() < len) {
+            // Unpin the old buffer before pinning the larger one
+            *staging = None;
+            *staging = Some(LockedBuffer::new(&0, len)?);
+        }
+        Ok(MutexGuard::map(staging, |staging| {
+            staging
+                .as_mut()
+                .expect("the staging buffer was just allocated")
+        }))
+    }
+
+    /// Enqueue one bitonic pass over `device_data` on `stream`
+    fn launch_pass(
+        &self,
+        device_data: &DeviceBuffer<u32>,
+        params: &BitonicParams,
+        stream: &Stream,
+    ) -> Result<()> {
+        let descending = params.sort_order == u32::from(SortOrder::Descending);
+        let kernel = &self.kernels[usize::from(descending)];
+        let block_size = self.variant.workgroup_size;
+        let grid_size = params.num_elements.div_ceil(block_size);
         unsafe {
             launch!(
                 kernel<<<grid_size, block_size, 0, stream>>>(
                     device_data.as_device_ptr(),
-                    params
+                    *params
                 )
             )?;
         }
+        Ok(())
+    }
 
-        // Wait for completion
+    /// Upload `data` through pinned memory into one device buffer, enqueue
+    /// `passes` on the stream and synchronize once after the download
+    fn run_passes(&self, data: &mut [u32], passes: &[BitonicParams]) -> Result<()> {
+        let _current = self.make_current()?;
+        let mut staging = self.staging(data.len())?;
+        let staging = &mut staging[..data.len()];
+        staging.copy_from_slice(data);
+        let mut device_data = unsafe { DeviceBuffer::uninitialized(data.len())? };
+        unsafe { device_data.async_copy_from(&staging[..], &self.stream)? };
+        for params in passes {
+            self.launch_pass(&device_data, params, &self.stream)?;
+        }
+        unsafe { device_data.async_copy_to(&mut staging[..], &self.stream)? };
         self.stream.synchronize()?;
 
-        // Copy back to host
-        device_data.copy_to(data)?;
+        data.copy_from_slice(&staging);
+        Ok(())
+    }
+
+    /// Capture every pass of a sort of `len` keys into a graph with its own device buffer
+    ///
+    /// Captures on the private capture stream. Returns `Ok(None)` if the driver
+    /// cannot capture graphs; any other failure is an error.
+    fn capture_graph(&self, len: usize, order: SortOrder) -> Result<Option<SortGraph>> {
+        let device_data = unsafe { DeviceBuffer::uninitialized(len)? };
+        let stream = &self.capture_stream;
+
+        let begun = unsafe {
+            sys::cuStreamBeginCapture_v2(
+                stream.as_inner(),
+                sys::CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_THREAD_LOCAL,
+            )
+        };
+        if !check_graph(begun, "cuStreamBeginCapture_v2")? {
+            return Ok(None);
+        }
+        let launched = crate::bitonic_passes(len as u32, order)
+            .iter()
+            .try_for_each(|params| self.launch_pass(&device_data, params, stream));
+        // End the capture even if a launch failed, so the stream is usable again
+        let mut graph = std::ptr::null_mut();
+        let ended = unsafe { sys::cuStreamEndCapture(stream.as_inner(), &mut graph) };
+
+        let mut exec = std::ptr::null_mut();
+        let captured = launched
+            .and_then(|()| check_graph(ended, "cuStreamEndCapture"))
+            .and_then(|captured| {
+                if !captured {
+                    return Ok(false);
+                }
+                check_graph(
+                    unsafe { sys::cuGraphInstantiateWithFlags(&mut exec, graph, 0) },
+                    "cuGraphInstantiateWithFlags",
+                )
+            });
+        if !matches!(captured, Ok(true)) {
+            if !graph.is_null() {
+                unsafe { sys::cuGraphDestroy(graph) };
+            }
+            return captured.map(|_| None);
+        }
+
+        Ok(Some(SortGraph {
+            graph,
+            exec,
+            device_data,
+        }))
+    }
+
+    /// Sort `data` by replaying the cached graph for its length and order, capturing it first if needed
+    ///
+    /// Returns `Ok(false)` without touching `data` if the driver cannot capture graphs.
+    fn run_graph(&self, data: &mut [u32], order: SortOrder) -> Result<bool> {
+        let _current = self.make_current()?;
+        let mut graphs = self.graphs.lock();
+        let key = (data.len() as u32, order as u32);
+        if let Some(position) = graphs.iter().position(|(cached, _)| *cached == key) {
+            let entry = graphs.remove(position);
+            graphs.push(entry);
+        } else {
+            let Some(graph) = self.capture_graph(data.len(), order)? else {
+                self.graphs_supported.store(false, Ordering::Relaxed);
+                return Ok(false);
+            };
+            // Only a successful capture displaces the least recently used graph
+            if graphs.len() >= MAX_CACHED_GRAPHS {
+                graphs.remove(0);
+            }
+            graphs.push((key, graph));
+        }
+        let (_, sort_graph) = graphs.last().expect("the graph was just cached");
+
+        let mut staging = self.staging(data.len())?;
+        let staging = &mut staging[..data.len()];
+        staging.copy_from_slice(data);
+        let stream = &self.stream;
+        unsafe {
+            sort_graph
+                .device_data
+                .async_copy_from(&staging[..], stream)?
+        };
+        check(
+            unsafe { sys::cuGraphLaunch(sort_graph.exec, stream.as_inner()) },
+            "cuGraphLaunch",
+        )?;
+        unsafe {
+            sort_graph
+                .device_data
+                .async_copy_to(&mut staging[..], stream)?
+        };
+        stream.synchronize()?;
+
+        data.copy_from_slice(&staging);
+        Ok(true)
+    }
 
+    /// Drop every captured graph and the device buffers they own
+    pub fn clear_graph_cache(&self) -> Result<()> {
+        let _current = self.make_current()?;
+        self.graphs.lock().clear();
         Ok(())
     }
+
+    /// Sort `data` with one device buffer, recording an event after the upload,
+    /// each pass and the download
+    fn sort_with_events<T: SortableKey>(
+        &self,
+        data: &mut [T],
+        order: SortOrder,
+    ) -> Result<SortProfile> {
+        let mut spans = SpanRecorder::start();
+        let mut profile = SortProfile::new(TimingSource::CudaEvents);
+        if data.len() <= 1 {
+            spans.finish(&mut profile);
+            return Ok(profile);
+        }
+        let _current = self.make_current()?;
+
+        self.capabilities
+            .check_sort(data.len(), std::mem::size_of::<T>())?;
+        let (mut keys, len) = spans.time("prepare_data", || self.prepare_data(data));
+        spans.time("pad_data", || self.pad_data(&mut keys, len, order));
+        let passes = crate::bitonic_passes(keys.len() as u32, order);
+
+        let stream = &self.stream;
+        let record = || -> Result<Event> {
+            let event = Event::new(EventFlags::DEFAULT)?;
+            event.record(stream)?;
+            Ok(event)
+        };
+
+        profile.device_start = spans.elapsed();
+        let launch = spans.elapsed();
+        let mut staging = self.staging(keys.len())?;
+        let staging = &mut staging[..keys.len()];
+        staging.copy_from_slice(&keys);
+        let start = record()?;
+        let mut device_data = unsafe { DeviceBuffer::uninitialized(keys.len())? };
+        unsafe { device_data.async_copy_from(&staging[..], stream)? };
+        let uploaded = record()?;
+
+        let mut pass_events = Vec::with_capacity(passes.len());
+        for params in &passes {
+            self.launch_pass(&device_data, params, stream)?;
+            pass_events.push(record()?);
+        }
+
+        unsafe { device_data.async_copy_to(&mut staging[..], stream)? };
+        let downloaded = record()?;
+        spans.record("launch", launch);
+        spans.time("synchronize", || self.stream.synchronize())?;
+
+        let elapsed = |from: &Event, to: &Event| -> Result<Duration> {
+            let ms = to.elapsed_time_f32(from)?;
+            Ok(Duration::from_secs_f64(f64::from(ms.max(0.0)) / 1000.0))
+        };
+        profile.upload = elapsed(&start, &uploaded)?;
+        let mut previous = &uploaded;
+        for (params, event) in passes.iter().zip(&pass_events) {
+            profile
+                .passes
+                .push(PassTiming::new(params, elapsed(previous, event)?));
+            previous = event;
+        }
+        profile.download = elapsed(previous, &downloaded)?;
+
+        keys.copy_from_slice(&staging);
+        keys.truncate(len);
+        spans.time("finalize_data", || self.finalize_data(&keys, data));
+        spans.finish(&mut profile);
+        Ok(profile)
+    }
+}
+
+impl Drop for CudaRunner {
+    fn drop(&mut self) {
+        // Graph buffers, pinned memory and the streams belong to the device's context
+        let _current = self.make_current();
+        self.graphs.get_mut().clear();
+        *self.staging.get_mut() = None;
+        unsafe {
+            ManuallyDrop::drop(&mut self.stream);
+            ManuallyDrop::drop(&mut self.capture_stream);
+        }
+    }
+}
+
+impl SortRunner for CudaRunner {
+    fn backend_info(
+        &self,
+    ) -> (
+        &'static str,
+        Option<&'static str>,
+        Option<String>,
+        Option<String>,
+    ) {
+        ("cust", Some("CUDA"), Some(self.device_name.clone()), None)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn execute_kernel_pass(&self, data: &mut [u32], params: BitonicParams) -> Result<()> {
+        self.run_passes(data, &[params])
+    }
+
+    /// Replay the captured graph for this length and order, or launch every pass on the stream
+    fn run_bitonic_stages(&self, data: &mut [u32], order: SortOrder) -> Result<()> {
+        if self.graphs_supported.load(Ordering::Relaxed) && self.run_graph(data, order)? {
+            return Ok(());
+        }
+        self.run_passes(data, &crate::bitonic_passes(data.len() as u32, order))
+    }
+
+    fn sort_profiled<T: SortableKey + bytemuck::Pod + Send + Sync>(
+        &self,
+        data: &mut [T],
+        order: SortOrder,
+    ) -> Result<SortProfile> {
+        self.sort_with_events(data, order)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::CudaRunner;
-    use crate::{verify_sorted, SortRunner};
+    use super::{CudaRunner, MAX_CACHED_GRAPHS};
+    use crate::{verify_sorted, RunnerConfig, SortRunner};
     use shared::SortOrder;
+    use std::sync::atomic::Ordering;
 
     #[test]
     fn test_bitonic_u32() {
@@ -153,4 +668,129 @@ mod tests {
             assert!(verify_sorted(&data, SortOrder::Descending));
         }
     }
+
+    #[test]
+    fn test_bitonic_u32_many_passes() {
+        if let Ok(runner) = CudaRunner::new() {
+            let mut data: Vec<u32> = (0..100_000u32)
+                .map(|i| i.wrapping_mul(2_654_435_761))
+                .collect();
+
+            runner.sort(&mut data, SortOrder::Ascending).unwrap();
+            assert!(verify_sorted(&data, SortOrder::Ascending));
+            assert_eq!(data.len(), 100_000);
+
+            // The runner keeps working across sorts on the same stream
+            runner.sort(&mut data, SortOrder::Descending).unwrap();
+            assert!(verify_sorted(&data, SortOrder::Descending));
+        }
+    }
+
+    #[test]
+    fn test_graph_replay() {
+        if let Ok(runner) = CudaRunner::new() {
+            // Two sorts per (length, order) capture once and replay once
+            for order in [
+                SortOrder::Ascending,
+                SortOrder::Descending,
+                SortOrder::Ascending,
+            ] {
+                for len in [1000u32, 1024, 5000] {
+                    let mut data: Vec<u32> =
+                        (0..len).map(|i| i.wrapping_mul(2_654_435_761)).collect();
+                    runner.sort(&mut data, order).unwrap();
+                    assert!(verify_sorted(&data, order));
+                }
+            }
+            if runner.graphs_supported.load(Ordering::Relaxed) {
+                // 1000 and 1024 keys share a padded length
+                assert_eq!(runner.graphs.lock().len(), 4);
+            }
+
+            runner.clear_graph_cache().unwrap();
+            let mut data = vec![3u32, 1, 2];
+            runner.sort(&mut data, SortOrder::Ascending).unwrap();
+            assert_eq!(data, vec![1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_graph_cache_evicts_least_recently_used() {
+        if let Ok(runner) = CudaRunner::new() {
+            let lengths: Vec<u32> = (0..MAX_CACHED_GRAPHS as u32 + 2)
+                .map(|shift| 16 << shift)
+                .collect();
+            for &len in &lengths {
+                let mut data: Vec<u32> = (0..len).rev().collect();
+                runner.sort(&mut data, SortOrder::Ascending).unwrap();
+                assert!(verify_sorted(&data, SortOrder::Ascending));
+            }
+            if runner.graphs_supported.load(Ordering::Relaxed) {
+                let graphs = runner.graphs.lock();
+                assert_eq!(graphs.len(), MAX_CACHED_GRAPHS);
+                // The two oldest lengths were evicted
+                assert!(graphs.iter().all(|((len, _), _)| *len > lengths[1]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_across_threads() {
+        if let Ok(runner) = CudaRunner::new() {
+            std::thread::scope(|scope| {
+                for seed in 1..=4u32 {
+                    let runner = &runner;
+                    scope.spawn(move || {
+                        let mut data: Vec<u32> =
+                            (0..4096u32).map(|i| i.wrapping_mul(seed * 7919)).collect();
+                        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+                        assert!(verify_sorted(&data, SortOrder::Ascending));
+                    });
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn test_runner_per_device() {
+        let Ok(devices) = CudaRunner::enumerate_devices() else {
+            return;
+        };
+        let runners: Vec<CudaRunner> = devices
+            .iter()
+            .map(|device| {
+                CudaRunner::with_config(&RunnerConfig::default().device_index(device.index))
+                    .unwrap()
+            })
+            .collect();
+        for (runner, device) in runners.iter().zip(&devices) {
+            assert_eq!(runner.device_ordinal() as usize, device.index);
+        }
+        // A second runner on the default device shares its context
+        let shared = devices.first().map(|_| CudaRunner::new().unwrap());
+        if let Some(shared) = &shared {
+            let position = RunnerConfig::default().select_device(&devices).unwrap();
+            assert_eq!(shared.device_ordinal() as usize, devices[position].index);
+        }
+
+        for runner in runners.iter().chain(&shared) {
+            let mut data = vec![42u32, 7, 999, 0, 13, 256, 128, 511];
+            runner.sort(&mut data, SortOrder::Descending).unwrap();
+            assert_eq!(data, vec![999, 511, 256, 128, 42, 13, 7, 0]);
+        }
+    }
+
+    #[test]
+    fn test_sort_profiled() {
+        if let Ok(runner) = CudaRunner::new() {
+            let mut data: Vec<u32> = (0..1000u32).rev().collect();
+
+            let profile = runner
+                .sort_profiled(&mut data, SortOrder::Ascending)
+                .unwrap();
+            assert_eq!(data, (0..1000).collect::<Vec<_>>());
+            // 1024 padded keys take 10 stages of 1..=10 passes
+            assert_eq!(profile.passes.len(), 55);
+        }
+    }
 }
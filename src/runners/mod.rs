@@ -1,6 +1,8 @@
 //! Runner implementations for different compute backends
 
 pub mod cpu;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod multi;
 
 #[cfg(feature = "cuda")]
 pub mod cuda;
@@ -11,8 +13,13 @@ pub mod wgpu;
 #[cfg(feature = "ash")]
 pub mod ash;
 
+#[cfg(all(test, any(feature = "wgpu", feature = "ash")))]
+pub(crate) mod testing;
+
 // Re-export runners at module level for convenience
 pub use cpu::CpuRunner;
+#[cfg(not(target_arch = "wasm32"))]
+pub use multi::MultiDeviceRunner;
 
 #[cfg(feature = "cuda")]
 pub use cuda::CudaRunner;
@@ -21,4 +28,29 @@ pub use cuda::CudaRunner;
 pub use self::wgpu::WgpuRunner;
 
 #[cfg(feature = "ash")]
-pub use self::ash::AshRunner;
+pub use self::ash::{AshRunner, MemoryStats};
+
+use crate::config::{DeviceInfo, RunnerConfig};
+
+/// List the devices every enabled GPU backend can run on
+///
+/// Backends that fail to initialize (missing driver, no loader) contribute no devices.
+#[allow(unused_variables, unused_mut)] // Unused when no GPU backend is enabled
+pub fn list_devices(config: &RunnerConfig) -> Vec<DeviceInfo> {
+    let mut devices = Vec::new();
+
+    #[cfg(feature = "cuda")]
+    if config.backends.contains(crate::config::Backends::CUDA) {
+        devices.extend(CudaRunner::enumerate_devices().unwrap_or_default());
+    }
+
+    #[cfg(feature = "wgpu")]
+    devices.extend(WgpuRunner::enumerate_devices(config));
+
+    #[cfg(feature = "ash")]
+    if config.backends.contains(crate::config::Backends::VULKAN) {
+        devices.extend(AshRunner::enumerate_devices().unwrap_or_default());
+    }
+
+    devices
+}
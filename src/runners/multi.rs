@@ -0,0 +1,382 @@
+//! Multi-device runner that partitions a sort across several runners
+
+use crate::{
+    capabilities::Capabilities,
+    error::{ChimeraError, Result},
+    profile::{SortProfile, TimingSource},
+    SortRunner,
+};
+use parking_lot::Mutex;
+use rand::Rng;
+use shared::{BitonicParams, SortOrder, SortableKey};
+use std::time::{Duration, Instant};
+
+/// Number of samples drawn per device when choosing splitters
+const DEFAULT_OVERSAMPLING: usize = 32;
+
+/// A runner that can be driven from its own thread
+pub type BoxedRunner = Box<dyn SortRunner + Send + Sync>;
+
+// Every runner must box into a `BoxedRunner`
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<super::CpuRunner>();
+    #[cfg(feature = "cuda")]
+    assert_send_sync::<super::CudaRunner>();
+    #[cfg(feature = "wgpu")]
+    assert_send_sync::<super::WgpuRunner>();
+    #[cfg(feature = "ash")]
+    assert_send_sync::<super::AshRunner>();
+};
+
+/// Time a single device spent sorting its partition
+#[derive(Clone, Debug)]
+pub struct DeviceTiming {
+    /// Position of the device in the [`MultiDeviceRunner`]
+    pub device: usize,
+    pub host: &'static str,
+    pub adapter: Option<String>,
+    /// Number of keys in the partition sent to this device
+    pub elements: usize,
+    /// Wall-clock time for padding and sorting the partition
+    pub duration: Duration,
+}
+
+/// Runner that sample-sorts across several per-device runners
+///
+/// The input is split into one key range per device using sampled splitters,
+/// every partition is sorted concurrently on its own device, and the sorted
+/// partitions are concatenated. Any mix of runners works, including several
+/// [`CpuRunner`](super::CpuRunner)s as stand-ins for GPUs.
+pub struct MultiDeviceRunner {
+    runners: Vec<BoxedRunner>,
+    oversampling: usize,
+    last_timings: Mutex<Vec<DeviceTiming>>,
+}
+
+impl MultiDeviceRunner {
+    /// Create a runner that distributes work over `runners`
+    pub fn new(runners: Vec<BoxedRunner>) -> Result<Self> {
+        if runners.is_empty() {
+            return Err(ChimeraError::Other(
+                "MultiDeviceRunner needs at least one runner".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            runners,
+            oversampling: DEFAULT_OVERSAMPLING,
+            last_timings: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Set how many samples are drawn per device when choosing splitters
+    pub fn with_oversampling(mut self, oversampling: usize) -> Self {
+        self.oversampling = oversampling.max(1);
+        self
+    }
+
+    /// Number of devices the work is partitioned across
+    pub fn device_count(&self) -> usize {
+        self.runners.len()
+    }
+
+    /// Per-device timings of the most recent sort
+    pub fn last_timings(&self) -> Vec<DeviceTiming> {
+        self.last_timings.lock().clone()
+    }
+
+    /// Choose `devices - 1` splitters from a random sample of `data`
+    fn select_splitters(&self, data: &[u32]) -> Vec<u32> {
+        let devices = self.runners.len();
+        if devices == 1 || data.is_empty() {
+            return Vec::new();
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut samples: Vec<u32> = (0..devices * self.oversampling)
+            .map(|_| data[rng.gen_range(0..data.len())])
+            .collect();
+        samples.sort_unstable();
+
+        (1..devices)
+            .map(|i| samples[i * self.oversampling])
+            .collect()
+    }
+
+    fn sort_partition(
+        device: usize,
+        runner: &dyn SortRunner,
+        partition: &mut Vec<u32>,
+        order: SortOrder,
+    ) -> Result<DeviceTiming> {
+        let (host, _, adapter, _) = runner.backend_info();
+        let elements = partition.len();
+        let start = Instant::now();
+
+        if elements > 1 {
+            runner
+                .capabilities()
+                .check_sort(elements, std::mem::size_of::<u32>())?;
+            runner.pad_data(partition, elements, order);
+            runner.run_bitonic_stages(partition, order)?;
+            partition.truncate(elements);
+        }
+
+        Ok(DeviceTiming {
+            device,
+            host,
+            adapter,
+            elements,
+            duration: start.elapsed(),
+        })
+    }
+}
+
+impl SortRunner for MultiDeviceRunner {
+    fn backend_info(
+        &self,
+    ) -> (
+        &'static str,
+        Option<&'static str>,
+        Option<String>,
+        Option<String>,
+    ) {
+        let adapters = self
+            .runners
+            .iter()
+            .map(|runner| {
+                let (host, _, adapter, _) = runner.backend_info();
+                adapter.unwrap_or_else(|| host.to_string())
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        ("multi", Some("Multi-device"), Some(adapters), None)
+    }
+
+    /// What every device supports
+    ///
+    /// `max_elements` is the smallest per-device limit rather than their sum:
+    /// every copy of a key lands in the same partition, so skewed or
+    /// duplicate-heavy input can send all keys to one device.
+    fn capabilities(&self) -> Capabilities {
+        self.runners
+            .iter()
+            .map(|runner| runner.capabilities())
+            .fold(
+                Capabilities {
+                    max_elements: u64::MAX,
+                    supports_64bit_keys: true,
+                    max_push_constant_size: u32::MAX,
+                    subgroup_operations: true,
+                    timestamp_queries: true,
+                    is_software: true,
+                },
+                |acc, caps| Capabilities {
+                    max_elements: acc.max_elements.min(caps.max_elements),
+                    supports_64bit_keys: acc.supports_64bit_keys && caps.supports_64bit_keys,
+                    max_push_constant_size: acc
+                        .max_push_constant_size
+                        .min(caps.max_push_constant_size),
+                    subgroup_operations: acc.subgroup_operations && caps.subgroup_operations,
+                    timestamp_queries: acc.timestamp_queries && caps.timestamp_queries,
+                    is_software: acc.is_software && caps.is_software,
+                },
+            )
+    }
+
+    fn execute_kernel_pass(&self, _data: &mut [u32], _params: BitonicParams) -> Result<()> {
+        Err(ChimeraError::Unsupported(
+            "MultiDeviceRunner partitions whole sorts and cannot run a single pass".to_string(),
+        ))
+    }
+
+    /// Partitions are padded individually on their devices
+    fn pad_data(&self, _data: &mut Vec<u32>, _original_size: usize, _order: SortOrder) {}
+
+    fn run_bitonic_stages(&self, data: &mut [u32], order: SortOrder) -> Result<()> {
+        let splitters = self.select_splitters(data);
+
+        // Bucket keys by range; bucket `i` holds keys below `splitters[i]`
+        let mut partitions = vec![Vec::new(); self.runners.len()];
+        for &key in data.iter() {
+            partitions[splitters.partition_point(|&s| s <= key)].push(key);
+        }
+
+        let timings = std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .runners
+                .iter()
+                .zip(partitions.iter_mut())
+                .enumerate()
+                .map(|(device, (runner, partition))| {
+                    scope.spawn(move || {
+                        Self::sort_partition(device, runner.as_ref(), partition, order)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle.join().unwrap_or_else(|_| {
+                        Err(ChimeraError::Other("device thread panicked".to_string()))
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        // Key ranges ascend with the bucket index, so descending output walks them backwards
+        let mut offset = 0;
+        let mut write = |partition: &Vec<u32>| {
+            data[offset..offset + partition.len()].copy_from_slice(partition);
+            offset += partition.len();
+        };
+        match order {
+            SortOrder::Ascending => partitions.iter().for_each(&mut write),
+            SortOrder::Descending => partitions.iter().rev().for_each(&mut write),
+        }
+
+        *self.last_timings.lock() = timings;
+        Ok(())
+    }
+
+    /// Partitions cannot be timed pass by pass; see [`MultiDeviceRunner::last_timings`] per device
+    fn sort_profiled<T: SortableKey + bytemuck::Pod + Send + Sync>(
+        &self,
+        data: &mut [T],
+        order: SortOrder,
+    ) -> Result<SortProfile> {
+        let started = Instant::now();
+        self.sort(data, order)?;
+        Ok(SortProfile {
+            wall_time: started.elapsed(),
+            ..SortProfile::new(TimingSource::WallClock)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoxedRunner, MultiDeviceRunner};
+    use crate::error::{ChimeraError, Result};
+    use crate::{verify_sorted, Capabilities, CpuRunner, SortRunner};
+    use rand::Rng;
+    use shared::{BitonicParams, SortOrder};
+
+    /// A CPU runner accepting at most `.0` keys, standing in for a small device
+    struct LimitedRunner(u64);
+
+    impl SortRunner for LimitedRunner {
+        fn backend_info(
+            &self,
+        ) -> (
+            &'static str,
+            Option<&'static str>,
+            Option<String>,
+            Option<String>,
+        ) {
+            CpuRunner.backend_info()
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities {
+                max_elements: self.0,
+                ..CpuRunner.capabilities()
+            }
+        }
+
+        fn execute_kernel_pass(&self, data: &mut [u32], params: BitonicParams) -> Result<()> {
+            CpuRunner.execute_kernel_pass(data, params)
+        }
+    }
+
+    fn cpu_runners(count: usize) -> MultiDeviceRunner {
+        let runners: Vec<BoxedRunner> = (0..count)
+            .map(|_| Box::new(CpuRunner) as BoxedRunner)
+            .collect();
+        MultiDeviceRunner::new(runners).unwrap()
+    }
+
+    #[test]
+    fn test_requires_a_runner() {
+        assert!(MultiDeviceRunner::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_multi_device_u32() {
+        let runner = cpu_runners(4);
+        let mut rng = rand::thread_rng();
+        let mut data: Vec<u32> = (0..3000).map(|_| rng.gen()).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, expected);
+
+        let timings = runner.last_timings();
+        assert_eq!(timings.len(), 4);
+        assert_eq!(timings.iter().map(|t| t.elements).sum::<usize>(), 3000);
+        assert!(timings.iter().all(|t| t.host == "cpu"));
+    }
+
+    #[test]
+    fn test_multi_device_i32_descending() {
+        let runner = cpu_runners(3);
+        let mut data: Vec<i32> = (0..1000).map(|i| (i * 7919 % 2001) - 1000).collect();
+
+        runner.sort(&mut data, SortOrder::Descending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Descending));
+    }
+
+    #[test]
+    fn test_multi_device_f32_with_duplicates() {
+        let runner = cpu_runners(8);
+        let mut data: Vec<f32> = (0..500).map(|i| (i % 5) as f32 - 2.5).collect();
+
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert!(verify_sorted(&data, SortOrder::Ascending));
+    }
+
+    #[test]
+    fn test_fewer_elements_than_devices() {
+        let runner = cpu_runners(8);
+        let mut data = vec![3u32, 1, 2];
+
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_max_elements_is_the_smallest_device_limit() {
+        let runners: Vec<BoxedRunner> = vec![
+            Box::new(LimitedRunner(1024)),
+            Box::new(LimitedRunner(256)),
+            Box::new(LimitedRunner(4096)),
+        ];
+        let runner = MultiDeviceRunner::new(runners).unwrap();
+        assert_eq!(runner.capabilities().max_elements, 256);
+
+        // All copies of one key share a partition, so any one device may get every key
+        let mut data = vec![7u32; 256];
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert!(matches!(
+            runner.sort(&mut vec![7u32; 257], SortOrder::Ascending),
+            Err(ChimeraError::TooManyElements { max: 256, .. })
+        ));
+    }
+
+    #[test]
+    fn test_sort_profiled() {
+        let runner = cpu_runners(2);
+        let mut data = vec![9u32, 4, 7, 1, 8, 2];
+
+        let profile = runner
+            .sort_profiled(&mut data, SortOrder::Ascending)
+            .unwrap();
+        assert_eq!(data, vec![1, 2, 4, 7, 8, 9]);
+        assert!(profile.passes.is_empty());
+        assert_eq!(runner.last_timings().len(), 2);
+    }
+}
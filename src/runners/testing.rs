@@ -0,0 +1,363 @@
+//! Test harness helpers for GPU runners
+//!
+//! GPU tests create their runner through these helpers so that machines without
+//! a suitable device skip the test with a message instead of panicking. Only a
+//! missing adapter, device or Vulkan loader skips; any other error creating
+//! the runner is a bug and fails the test.
+
+use crate::config::{Backends, RunnerConfig};
+#[cfg(feature = "ash")]
+use crate::config::{DebugMessage, DebugSeverity, DebugSink};
+use crate::error::ChimeraError;
+#[cfg(feature = "ash")]
+use crate::error::Result;
+#[cfg(feature = "ash")]
+use ash::vk;
+#[cfg(feature = "ash")]
+use parking_lot::Mutex;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "ash")]
+use std::sync::Arc;
+
+/// Configuration that selects a software Vulkan implementation (lavapipe, SwiftShader)
+pub fn software_config() -> RunnerConfig {
+    RunnerConfig::new()
+        .backends(Backends::VULKAN)
+        .force_software_adapter(true)
+}
+
+fn skip(test_kind: &str, reason: impl Display) {
+    eprintln!("skipping {test_kind} test: {reason}");
+}
+
+fn skip_software(host: &str, reason: impl Display) {
+    skip(
+        &format!("{host} software adapter"),
+        format_args!(
+            "{reason}; install lavapipe (mesa-vulkan-drivers) or SwiftShader and point \
+             VK_ICD_FILENAMES at its ICD json to run it"
+        ),
+    );
+}
+
+/// Whether `err` means the machine has no device to test on, rather than a bug
+fn is_unavailable(err: &ChimeraError) -> bool {
+    match err {
+        ChimeraError::NoAdapter | ChimeraError::NoVulkanDevice(_) => true,
+        #[cfg(feature = "ash")]
+        ChimeraError::VulkanLoader(_) => true,
+        _ => false,
+    }
+}
+
+/// Skip through `on_unavailable` if `err` means there is no device, panic otherwise
+fn skip_or_panic(test_kind: &str, err: ChimeraError, on_unavailable: impl FnOnce(ChimeraError)) {
+    if is_unavailable(&err) {
+        on_unavailable(err);
+    } else {
+        panic!("failed to create the {test_kind} runner: {err}");
+    }
+}
+
+/// Empty directory under the system temp dir, removed with its contents when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create a directory named after `name`, unique within the test run
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "chimera-{name}-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runner on the default adapter, or `None` if no adapter is available
+#[cfg(feature = "wgpu")]
+pub fn wgpu_runner() -> Option<super::WgpuRunner> {
+    futures::executor::block_on(super::WgpuRunner::new())
+        .map_err(|e| skip_or_panic("wgpu", e, |e| skip("wgpu", e)))
+        .ok()
+}
+
+/// Runner on the adapter selected by `config`, or `None` if no adapter is available
+#[cfg(feature = "wgpu")]
+pub fn wgpu_runner_with(config: &RunnerConfig) -> Option<super::WgpuRunner> {
+    futures::executor::block_on(super::WgpuRunner::with_config(config))
+        .map_err(|e| skip_or_panic("wgpu", e, |e| skip("wgpu", e)))
+        .ok()
+}
+
+/// Runner on a software Vulkan adapter, or `None` if none is installed
+#[cfg(feature = "wgpu")]
+pub fn software_wgpu_runner() -> Option<super::WgpuRunner> {
+    futures::executor::block_on(super::WgpuRunner::with_config(&software_config()))
+        .map_err(|e| skip_or_panic("wgpu", e, |e| skip_software("wgpu", e)))
+        .ok()
+}
+
+/// Device and queue created the way a host application would, or `None` if no adapter is available
+#[cfg(feature = "wgpu")]
+pub fn wgpu_device(required_features: wgpu::Features) -> Option<(wgpu::Device, wgpu::Queue)> {
+    futures::executor::block_on(async {
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await?;
+        // Same push constant budget as `WgpuRunner::with_config` asks for
+        let required_limits = wgpu::Limits {
+            max_push_constant_size: if required_features.contains(wgpu::Features::PUSH_CONSTANTS) {
+                128
+            } else {
+                0
+            },
+            ..Default::default()
+        };
+        Ok::<_, Box<dyn std::error::Error>>(
+            adapter
+                .request_device(&wgpu::DeviceDescriptor {
+                    required_features,
+                    required_limits,
+                    ..Default::default()
+                })
+                .await?,
+        )
+    })
+    .map_err(|e| skip("wgpu host device", e))
+    .ok()
+}
+
+/// A runner with validation enabled that fails the test on validation errors
+///
+/// Errors are checked after the runner is dropped, so destruction is covered too.
+#[cfg(feature = "ash")]
+pub struct Validated<R> {
+    runner: Option<R>,
+    errors: Arc<Mutex<Vec<DebugMessage>>>,
+}
+
+#[cfg(feature = "ash")]
+impl<R> std::ops::Deref for Validated<R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.runner.as_ref().unwrap()
+    }
+}
+
+#[cfg(feature = "ash")]
+impl<R> Drop for Validated<R> {
+    fn drop(&mut self) {
+        drop(self.runner.take());
+        let errors = self.errors.lock();
+        if !errors.is_empty() && !std::thread::panicking() {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            panic!("validation errors:\n{}", messages.join("\n"));
+        }
+    }
+}
+
+/// Enable validation on `config` and create the runner, collecting validation errors
+#[cfg(feature = "ash")]
+fn create_validated(
+    config: RunnerConfig,
+    on_unavailable: impl FnOnce(crate::error::ChimeraError),
+) -> Option<Validated<super::AshRunner>> {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let sink_errors = errors.clone();
+    let config = config
+        .debug(true)
+        .debug_sink(DebugSink::new(move |message| {
+            eprintln!("{message}");
+            if message.severity == DebugSeverity::Error {
+                sink_errors.lock().push(message.clone());
+            }
+        }));
+
+    super::AshRunner::with_config(&config)
+        .map_err(|e| skip_or_panic("ash", e, on_unavailable))
+        .ok()
+        .map(|runner| Validated {
+            runner: Some(runner),
+            errors,
+        })
+}
+
+/// Validated runner on the device selected by `config`, or `None` if there is none
+#[cfg(feature = "ash")]
+pub fn validated_ash_runner(config: RunnerConfig) -> Option<Validated<super::AshRunner>> {
+    create_validated(config, |e| skip("ash", e))
+}
+
+/// Validated runner on the default Vulkan device, or `None` if no device is available
+#[cfg(feature = "ash")]
+pub fn ash_runner() -> Option<Validated<super::AshRunner>> {
+    validated_ash_runner(RunnerConfig::default())
+}
+
+/// Validated runner on a software Vulkan device, or `None` if none is installed
+#[cfg(feature = "ash")]
+pub fn software_ash_runner() -> Option<Validated<super::AshRunner>> {
+    create_validated(software_config(), |e| skip_software("ash", e))
+}
+
+/// Vulkan device created the way a host application would, for import tests
+#[cfg(feature = "ash")]
+pub struct HostDevice {
+    _entry: ash::Entry,
+    pub instance: ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: ash::Device,
+    pub queue: vk::Queue,
+    pub queue_family_index: u32,
+}
+
+#[cfg(feature = "ash")]
+impl HostDevice {
+    /// Device on the first Vulkan adapter with a compute queue, or `None` if there is none
+    pub fn new() -> Option<Self> {
+        unsafe { Self::create() }
+            .map_err(|e| skip("ash host device", e))
+            .ok()
+    }
+
+    unsafe fn create() -> Result<Self> {
+        let entry = ash::Entry::load().map_err(|e| ChimeraError::VulkanLoader(format!("{e:?}")))?;
+        let app_info = vk::ApplicationInfo::default().api_version(vk::API_VERSION_1_2);
+        let instance = entry
+            .create_instance(
+                &vk::InstanceCreateInfo::default().application_info(&app_info),
+                None,
+            )
+            .map_err(super::ash::loader_error)?;
+
+        let physical_devices = instance.enumerate_physical_devices().unwrap_or_default();
+        let device_count = physical_devices.len();
+        let selected = physical_devices.into_iter().find_map(|pd| {
+            instance
+                .get_physical_device_queue_family_properties(pd)
+                .iter()
+                .position(|family| family.queue_flags.contains(vk::QueueFlags::COMPUTE))
+                .map(|index| (pd, index as u32))
+        });
+        let Some((physical_device, queue_family_index)) = selected else {
+            instance.destroy_instance(None);
+            return Err(ChimeraError::NoVulkanDevice(device_count));
+        };
+
+        let device = match instance.create_device(
+            physical_device,
+            &vk::DeviceCreateInfo::default().queue_create_infos(&[
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(queue_family_index)
+                    .queue_priorities(&[1.0]),
+            ]),
+            None,
+        ) {
+            Ok(device) => device,
+            Err(e) => {
+                instance.destroy_instance(None);
+                return Err(e.into());
+            }
+        };
+        let queue = device.get_device_queue(queue_family_index, 0);
+
+        Ok(Self {
+            _entry: entry,
+            instance,
+            physical_device,
+            device,
+            queue,
+            queue_family_index,
+        })
+    }
+
+    /// Host-visible storage buffer initialized with `keys`
+    pub unsafe fn create_buffer(&self, keys: &[u32]) -> (vk::Buffer, vk::DeviceMemory) {
+        let size = std::mem::size_of_val(keys) as vk::DeviceSize;
+        let buffer = self
+            .device
+            .create_buffer(
+                &vk::BufferCreateInfo::default()
+                    .size(size)
+                    .usage(vk::BufferUsageFlags::STORAGE_BUFFER),
+                None,
+            )
+            .unwrap();
+        let requirements = self.device.get_buffer_memory_requirements(buffer);
+        let wanted = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let memory_properties = self
+            .instance
+            .get_physical_device_memory_properties(self.physical_device);
+        let memory_type_index = (0..memory_properties.memory_type_count)
+            .find(|&index| {
+                requirements.memory_type_bits & (1 << index) != 0
+                    && memory_properties.memory_types[index as usize]
+                        .property_flags
+                        .contains(wanted)
+            })
+            .unwrap();
+        let memory = self
+            .device
+            .allocate_memory(
+                &vk::MemoryAllocateInfo::default()
+                    .allocation_size(requirements.size)
+                    .memory_type_index(memory_type_index),
+                None,
+            )
+            .unwrap();
+        self.device.bind_buffer_memory(buffer, memory, 0).unwrap();
+
+        let ptr = self
+            .device
+            .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
+            .unwrap();
+        std::ptr::copy_nonoverlapping(keys.as_ptr(), ptr.cast(), keys.len());
+        self.device.unmap_memory(memory);
+        (buffer, memory)
+    }
+
+    /// Read `len` keys back from memory created by [`HostDevice::create_buffer`]
+    pub unsafe fn read_buffer(&self, memory: vk::DeviceMemory, len: usize) -> Vec<u32> {
+        let ptr = self
+            .device
+            .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            .unwrap();
+        let keys = std::slice::from_raw_parts(ptr.cast::<u32>(), len).to_vec();
+        self.device.unmap_memory(memory);
+        keys
+    }
+
+    pub unsafe fn destroy_buffer(&self, (buffer, memory): (vk::Buffer, vk::DeviceMemory)) {
+        self.device.destroy_buffer(buffer, None);
+        self.device.free_memory(memory, None);
+    }
+}
+
+#[cfg(feature = "ash")]
+impl Drop for HostDevice {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.device.device_wait_idle();
+            self.device.destroy_device(None);
+            self.instance.destroy_instance(None);
+        }
+    }
+}
@@ -1,158 +1,850 @@
 //! wgpu runner implementation
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::profile::{self, PassTiming, SpanRecorder, TimingSource};
 use crate::{
+    capabilities::Capabilities,
+    config::{DeviceInfo, DeviceSelector, RunnerConfig},
     error::{ChimeraError, Result},
+    kernel::{BitonicLayout, KernelSource, KernelVariant, ParamsSource, SPIRV_KERNELS},
+    pipeline_cache::PipelineCacheFile,
+    profile::SortProfile,
+    reflect::BindingType,
     SortRunner,
 };
-use shared::{BitonicParams, WORKGROUP_SIZE};
+use shared::{BitonicParams, SortOrder, SortableKey, SORT_ORDER_SPEC_ID, WORKGROUP_SIZE_SPEC_ID};
+use std::ops::Range;
 use wgpu::util::DeviceExt;
 
 /// WebGPU-based runner for bitonic sort supporting multiple backends (Vulkan, Metal, DX12, etc.)
 pub struct WgpuRunner {
     device: wgpu::Device,
     queue: wgpu::Queue,
-    bitonic_pipeline: Option<wgpu::ComputePipeline>,
+    /// Pipelines sorting in each [`SortOrder`], indexed by it
+    bitonic_pipelines: Option<[wgpu::ComputePipeline; 2]>,
     bitonic_bind_group_layout: Option<wgpu::BindGroupLayout>,
-    backend_name: String,
+    pipeline_cache: Option<wgpu::PipelineCache>,
+    pipeline_cache_file: Option<PipelineCacheFile>,
+    backend_name: &'static str,
     adapter_name: String,
     driver_info: String,
+    capabilities: Capabilities,
+    /// Kernel variant the pipeline was built from
+    variant: KernelVariant,
+    /// Binding of the key buffer, as reflected from the kernel
+    key_binding: u32,
+    /// Binding of the uniform buffer holding the params, when the device has
+    /// no push constants
+    params_binding: Option<u32>,
+}
+
+/// Bind group for one sort and how each pass reaches its params
+struct PassBindings {
+    bind_group: wgpu::BindGroup,
+    /// Distance between the params of consecutive passes in the uniform
+    /// buffer; `None` when they are push constants
+    params_stride: Option<u32>,
+}
+
+/// Largest workgroup a device with `limits` can run along x
+fn max_workgroup_size(limits: &wgpu::Limits) -> u32 {
+    limits
+        .max_compute_invocations_per_workgroup
+        .min(limits.max_compute_workgroup_size_x)
+}
+
+/// How the kernel gets its params on a device with `features` and `limits`
+///
+/// Push constants are preferred; WebGPU and some native adapters lack them,
+/// so those bind the params as a uniform buffer at a dynamic offset per pass.
+fn params_source(features: wgpu::Features, limits: &wgpu::Limits) -> ParamsSource {
+    let params_size = std::mem::size_of::<BitonicParams>() as u32;
+    if features.contains(wgpu::Features::PUSH_CONSTANTS)
+        && limits.max_push_constant_size >= params_size
+    {
+        ParamsSource::PushConstants
+    } else {
+        ParamsSource::UniformBuffer
+    }
 }
 
 impl WgpuRunner {
     /// Create a new wgpu runner, automatically detecting the best available backend
     pub async fn new() -> Result<Self> {
-        // Initialize wgpu with appropriate backend
-        #[cfg(all(target_os = "macos", feature = "vulkan"))]
-        let backends = wgpu::Backends::VULKAN;
-
-        #[cfg(all(target_os = "macos", not(feature = "vulkan")))]
-        let backends = wgpu::Backends::METAL;
-
-        #[cfg(not(target_os = "macos"))]
-        let backends = wgpu::Backends::all();
+        Self::with_config(&RunnerConfig::default()).await
+    }
 
+    /// Create a new wgpu runner on the adapter selected by `config`
+    pub async fn with_config(config: &RunnerConfig) -> Result<Self> {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends,
+            backends: config.backends.into(),
             ..Default::default()
         });
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
-                compatible_surface: None,
-            })
-            .await
-            .map_err(|_| ChimeraError::NoAdapter)?;
+        let adapter = match config.device {
+            DeviceSelector::Auto if config.allow_software_adapter => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: config.power_preference.into(),
+                    force_fallback_adapter: config.force_software_adapter,
+                    compatible_surface: None,
+                })
+                .await
+                .map_err(|_| ChimeraError::NoAdapter)?,
+            // Browsers only hand out the adapter they pick
+            #[cfg(target_arch = "wasm32")]
+            _ => {
+                return Err(ChimeraError::Unsupported(
+                    "browsers do not list adapters; use DeviceSelector::Auto".to_string(),
+                ))
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            _ => {
+                let mut adapters = instance.enumerate_adapters(config.backends.into());
+                let devices: Vec<DeviceInfo> = adapters
+                    .iter()
+                    .enumerate()
+                    .map(|(index, adapter)| Self::device_info(index, &adapter.get_info()))
+                    .collect();
+                let position = config
+                    .select_device(&devices)
+                    .ok_or(ChimeraError::NoAdapter)?;
+                adapters.swap_remove(position)
+            }
+        };
 
         // Get adapter info to show which backend we're using
         let info = adapter.get_info();
-        let backend_name = match info.backend {
-            wgpu::Backend::Vulkan => "Vulkan",
-            wgpu::Backend::Metal => "Metal",
-            wgpu::Backend::Dx12 => "DirectX 12",
-            wgpu::Backend::Gl => "OpenGL",
-            wgpu::Backend::BrowserWebGpu => "WebGPU",
-            _ => "Unknown",
-        }
-        .to_string();
+        let backend_name = backend_name(info.backend);
         let adapter_name = info.name.clone();
         let driver_info = info.driver.clone();
 
         // Check if the backend supports SPIRV passthrough
         let adapter_features = adapter.features();
-        let required_features =
+        let adapter_limits = adapter.limits();
+        let mut required_features =
             if adapter_features.contains(wgpu::Features::SPIRV_SHADER_PASSTHROUGH) {
-                wgpu::Features::PUSH_CONSTANTS | wgpu::Features::SPIRV_SHADER_PASSTHROUGH
+                wgpu::Features::SPIRV_SHADER_PASSTHROUGH
             } else {
                 // wgpu will translate SPIRV to the native format (MSL for Metal, HLSL for DX12, etc.)
-                wgpu::Features::PUSH_CONSTANTS
+                wgpu::Features::empty()
             };
+        let params = params_source(adapter_features, &adapter_limits);
+        if params == ParamsSource::PushConstants {
+            required_features |= wgpu::Features::PUSH_CONSTANTS;
+        }
+
+        // Pipeline caches are only supported on Vulkan
+        let pipeline_cache_file = config
+            .pipeline_cache_dir
+            .as_ref()
+            .filter(|_| adapter_features.contains(wgpu::Features::PIPELINE_CACHE))
+            .and_then(|dir| {
+                let name = wgpu::util::pipeline_cache_key(&info)?;
+                Some(PipelineCacheFile::new(
+                    dir,
+                    &name,
+                    pipeline_cache_identity(&info),
+                ))
+            });
+        if pipeline_cache_file.is_some() {
+            required_features |= wgpu::Features::PIPELINE_CACHE;
+        }
+        if config.profiling && adapter_features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+            required_features |= wgpu::Features::TIMESTAMP_QUERY;
+        }
+
+        let variant = KernelVariant::select(
+            SPIRV_KERNELS,
+            params,
+            max_workgroup_size(&adapter_limits),
+            config.workgroup_size,
+        )?;
+        let default_limits = wgpu::Limits::default();
 
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: Some("GPU Device"),
                 required_features,
                 required_limits: wgpu::Limits {
-                    max_push_constant_size: 128,
-                    ..Default::default()
+                    max_push_constant_size: if params == ParamsSource::PushConstants {
+                        adapter_limits.max_push_constant_size.min(128)
+                    } else {
+                        0
+                    },
+                    max_compute_invocations_per_workgroup: default_limits
+                        .max_compute_invocations_per_workgroup
+                        .max(variant.workgroup_size),
+                    max_compute_workgroup_size_x: default_limits
+                        .max_compute_workgroup_size_x
+                        .max(variant.workgroup_size),
+                    ..default_limits
                 },
                 memory_hints: Default::default(),
                 trace: wgpu::Trace::default(),
             })
             .await?;
 
+        let capabilities = Self::query_capabilities(
+            adapter_features,
+            &device.limits(),
+            info.device_type == wgpu::DeviceType::Cpu,
+            variant.workgroup_size,
+        );
+
+        // Seed the cache from disk; wgpu also checks the driver's own cache header
+        // and falls back to an empty cache if it does not match
+        let pipeline_cache = pipeline_cache_file.as_ref().map(|file| unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("Bitonic Pipeline Cache"),
+                data: file.load().as_deref(),
+                fallback: true,
+            })
+        });
+
         // Try to create Bitonic pipeline
-        let (bitonic_pipeline, bitonic_bind_group_layout) = Self::create_bitonic_pipeline(&device);
+        let spirv = config.spirv_kernel.spirv(&variant)?;
+        let layout = BitonicLayout::reflect(&spirv, &variant)?;
+        let (bitonic_pipelines, bitonic_bind_group_layout) = Self::create_bitonic_pipelines(
+            &device,
+            pipeline_cache.as_ref(),
+            &spirv,
+            &variant,
+            &layout,
+        )?;
 
         Ok(Self {
             device,
             queue,
-            bitonic_pipeline,
-            bitonic_bind_group_layout,
+            bitonic_pipelines: Some(bitonic_pipelines),
+            bitonic_bind_group_layout: Some(bitonic_bind_group_layout),
+            pipeline_cache,
+            pipeline_cache_file,
             backend_name,
             adapter_name,
             driver_info,
+            capabilities,
+            variant,
+            key_binding: layout.key_binding,
+            params_binding: layout.params_binding,
         })
     }
 
-    fn create_bitonic_pipeline(
-        device: &wgpu::Device,
-    ) -> (Option<wgpu::ComputePipeline>, Option<wgpu::BindGroupLayout>) {
-        // Use the embedded kernel from the main crate
-        let kernel_bytes = crate::BITONIC_SPIRV;
-
-        let spirv_data = wgpu::util::make_spirv(kernel_bytes);
-        let shader_module = unsafe {
-            device.create_shader_module_trusted(
-                wgpu::ShaderModuleDescriptor {
-                    label: Some("Bitonic Kernel"),
-                    source: spirv_data,
-                },
-                wgpu::ShaderRuntimeChecks::unchecked(),
-            )
+    /// Create a runner on a device and queue owned by the host application
+    ///
+    /// [`BitonicParams`] are passed as push constants if the device was
+    /// created with `PUSH_CONSTANTS` and a large enough
+    /// `max_push_constant_size`, and through a uniform buffer otherwise;
+    /// `SPIRV_SHADER_PASSTHROUGH` is used when enabled. The bitonic pipeline is
+    /// built on `device`, and sorts are submitted to `queue`.
+    pub fn from_device(device: wgpu::Device, queue: wgpu::Queue) -> Result<Self> {
+        Self::from_device_with_kernel(device, queue, &KernelSource::Embedded)
+    }
+
+    /// Like [`WgpuRunner::from_device`], building the pipeline from `kernel`
+    pub fn from_device_with_kernel(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        kernel: &KernelSource,
+    ) -> Result<Self> {
+        let features = device.features();
+        let limits = device.limits();
+
+        // wgpu does not expose the adapter of a device, so it cannot be named
+        let variant = KernelVariant::select(
+            SPIRV_KERNELS,
+            params_source(features, &limits),
+            max_workgroup_size(&limits),
+            None,
+        )?;
+        let capabilities =
+            Self::query_capabilities(features, &limits, false, variant.workgroup_size);
+        let spirv = kernel.spirv(&variant)?;
+        let layout = BitonicLayout::reflect(&spirv, &variant)?;
+        let (bitonic_pipelines, bitonic_bind_group_layout) =
+            Self::create_bitonic_pipelines(&device, None, &spirv, &variant, &layout)?;
+
+        Ok(Self {
+            device,
+            queue,
+            bitonic_pipelines: Some(bitonic_pipelines),
+            bitonic_bind_group_layout: Some(bitonic_bind_group_layout),
+            pipeline_cache: None,
+            pipeline_cache_file: None,
+            backend_name: "Unknown",
+            adapter_name: "Host-provided device".to_string(),
+            driver_info: String::new(),
+            capabilities,
+            variant,
+            key_binding: layout.key_binding,
+            params_binding: layout.params_binding,
+        })
+    }
+
+    /// Record a sort of the `u32` keys in `range` of `buffer` into `encoder`
+    ///
+    /// `range` is in bytes and must hold a power-of-two number of keys, padded
+    /// by the caller with keys that sort last; its start must be a multiple of
+    /// `min_storage_buffer_offset_alignment`. Other key types must be stored in
+    /// their `SortableKey::to_sortable_u32` form. `buffer` needs `STORAGE`
+    /// usage. Nothing is submitted: the keys are sorted once the caller submits
+    /// `encoder` to the runner's queue.
+    pub fn sort_buffer(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        range: Range<wgpu::BufferAddress>,
+        order: SortOrder,
+    ) -> Result<()> {
+        let (Some(pipeline), Some(bind_group_layout)) =
+            (self.pipeline(order), &self.bitonic_bind_group_layout)
+        else {
+            return Err(ChimeraError::Other(
+                "Bitonic pipeline not available".to_string(),
+            ));
         };
 
-        // Create bind group layout for Bitonic (1 buffer: data)
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Bitonic Bind Group Layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
+        let key_size = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        let size = range.end.saturating_sub(range.start);
+        let len = size / key_size;
+        if size % key_size != 0 || (len > 1 && !len.is_power_of_two()) {
+            return Err(ChimeraError::Unsupported(format!(
+                "sort_buffer needs a power-of-two number of u32 keys, got {size} bytes"
+            )));
+        }
+        let alignment =
+            wgpu::BufferAddress::from(self.device.limits().min_storage_buffer_offset_alignment);
+        if range.start % alignment != 0 {
+            return Err(ChimeraError::Unsupported(format!(
+                "buffer offset {} is not a multiple of the storage buffer alignment {alignment}",
+                range.start
+            )));
+        }
+        if len <= 1 {
+            return Ok(());
+        }
+        self.capabilities
+            .check_sort(len as usize, key_size as usize)?;
+
+        let passes = crate::bitonic_passes(len as u32, order);
+        let bindings = self.pass_bindings(
+            bind_group_layout,
+            wgpu::BufferBinding {
+                buffer,
+                offset: range.start,
+                size: wgpu::BufferSize::new(size),
+            },
+            &passes,
+        );
+        Self::record_passes(
+            encoder,
+            pipeline,
+            &bindings,
+            &passes,
+            self.variant.workgroup_size,
+            None,
+        );
+
+        Ok(())
+    }
+
+    /// Sort `data` in one submission, awaiting the result instead of blocking
+    ///
+    /// This is how to sort on wasm32, where the browser's thread cannot block
+    /// and [`SortRunner::sort`] fails with [`ChimeraError::Unsupported`].
+    pub async fn sort_async<T: SortableKey>(&self, data: &mut [T], order: SortOrder) -> Result<()> {
+        let (Some(pipeline), Some(bind_group_layout)) =
+            (self.pipeline(order), &self.bitonic_bind_group_layout)
+        else {
+            return Err(ChimeraError::Other(
+                "Bitonic pipeline not available".to_string(),
+            ));
+        };
+        if data.len() <= 1 {
+            return Ok(());
+        }
+        self.capabilities
+            .check_sort(data.len(), std::mem::size_of::<T>())?;
+
+        let (mut keys, len) = self.prepare_data(data);
+        self.pad_data(&mut keys, len, order);
+        let passes = crate::bitonic_passes(keys.len() as u32, order);
+        let data_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Bitonic Data Buffer"),
+                contents: bytemuck::cast_slice(&keys),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            });
+        let bindings = self.pass_bindings(
+            bind_group_layout,
+            data_buffer.as_entire_buffer_binding(),
+            &passes,
+        );
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Bitonic Sort Encoder"),
+            });
+        Self::record_passes(
+            &mut encoder,
+            pipeline,
+            &bindings,
+            &passes,
+            self.variant.workgroup_size,
+            None,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        self.read_buffer(&data_buffer, &mut keys).await?;
+        keys.truncate(len);
+        self.finalize_data(&keys, data);
+        Ok(())
+    }
+
+    /// Bind group for the keys in `keys`, with the params of every pass in
+    /// a uniform buffer if the kernel reads them from one
+    fn pass_bindings(
+        &self,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        keys: wgpu::BufferBinding<'_>,
+        passes: &[BitonicParams],
+    ) -> PassBindings {
+        let keys = wgpu::BindGroupEntry {
+            binding: self.key_binding,
+            resource: wgpu::BindingResource::Buffer(keys),
+        };
+        let create = |entries: &[wgpu::BindGroupEntry<'_>]| {
+            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Bitonic Bind Group"),
+                layout: bind_group_layout,
+                entries,
+            })
+        };
+        let Some(params_binding) = self.params_binding else {
+            return PassBindings {
+                bind_group: create(&[keys]),
+                params_stride: None,
+            };
+        };
+
+        // Pass `i` reads the params at dynamic offset `i * stride`
+        let params_size = std::mem::size_of::<BitonicParams>();
+        let stride = (params_size as u32)
+            .next_multiple_of(self.device.limits().min_uniform_buffer_offset_alignment);
+        let mut contents = vec![0u8; stride as usize * passes.len()];
+        for (slot, params) in contents.chunks_exact_mut(stride as usize).zip(passes) {
+            slot[..params_size].copy_from_slice(bytemuck::bytes_of(params));
+        }
+        let params_buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Bitonic Params Buffer"),
+                contents: &contents,
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let bind_group = create(&[
+            keys,
+            wgpu::BindGroupEntry {
+                binding: params_binding,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &params_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(params_size as u64),
+                }),
+            },
+        ]);
+        PassBindings {
+            bind_group,
+            params_stride: Some(stride),
+        }
+    }
+
+    /// Record one dispatch per pass
+    ///
+    /// Without `timestamps` all dispatches share one compute pass; with them
+    /// each gets its own pass that writes queries `2 * i` and `2 * i + 1`.
+    fn record_passes(
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::ComputePipeline,
+        bindings: &PassBindings,
+        passes: &[BitonicParams],
+        workgroup_size: u32,
+        timestamps: Option<&wgpu::QuerySet>,
+    ) {
+        let begin_pass = |encoder: &mut wgpu::CommandEncoder, index: u32| {
+            let mut compute_pass = encoder
+                .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Bitonic Sort"),
+                    timestamp_writes: timestamps.map(|query_set| {
+                        wgpu::ComputePassTimestampWrites {
+                            query_set,
+                            beginning_of_pass_write_index: Some(2 * index),
+                            end_of_pass_write_index: Some(2 * index + 1),
+                        }
+                    }),
+                })
+                .forget_lifetime();
+            compute_pass.set_pipeline(pipeline);
+            if bindings.params_stride.is_none() {
+                compute_pass.set_bind_group(0, &bindings.bind_group, &[]);
+            }
+            compute_pass
+        };
+
+        // wgpu orders the storage accesses of consecutive dispatches in one pass
+        let mut compute_pass = begin_pass(encoder, 0);
+        for (i, params) in passes.iter().enumerate() {
+            if i > 0 && timestamps.is_some() {
+                drop(compute_pass);
+                compute_pass = begin_pass(encoder, i as u32);
+            }
+            match bindings.params_stride {
+                Some(stride) => {
+                    compute_pass.set_bind_group(0, &bindings.bind_group, &[i as u32 * stride])
+                }
+                None => compute_pass.set_push_constants(0, bytemuck::bytes_of(params)),
+            }
+            compute_pass.dispatch_workgroups(params.num_elements.div_ceil(workgroup_size), 1, 1);
+        }
+    }
+
+    /// Sort `data` in one submission with a timestamp query around every pass
+    ///
+    /// Upload and download are timed on the host: creating the initialized
+    /// storage buffer, and copying the result back once the passes finished.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn sort_with_timestamps<T: SortableKey>(
+        &self,
+        data: &mut [T],
+        order: SortOrder,
+    ) -> Result<SortProfile> {
+        let (Some(pipeline), Some(bind_group_layout)) =
+            (self.pipeline(order), &self.bitonic_bind_group_layout)
+        else {
+            return Err(ChimeraError::Other(
+                "Bitonic pipeline not available".to_string(),
+            ));
+        };
+
+        let mut spans = SpanRecorder::start();
+        let mut profile = SortProfile::new(TimingSource::GpuTimestamps);
+        if data.len() > 1 {
+            self.capabilities
+                .check_sort(data.len(), std::mem::size_of::<T>())?;
+            let (mut keys, len) = spans.time("prepare_data", || self.prepare_data(data));
+            spans.time("pad_data", || self.pad_data(&mut keys, len, order));
+            let passes = crate::bitonic_passes(keys.len() as u32, order);
+            let query_count = 2 * passes.len() as u32;
+            let query_bytes = u64::from(query_count) * u64::from(wgpu::QUERY_SIZE);
+
+            profile.device_start = spans.elapsed();
+            let data_buffer = spans.time("upload", || {
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Bitonic Data Buffer"),
+                        contents: bytemuck::cast_slice(&keys),
+                        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                    })
+            });
+            profile.upload = spans.last();
+
+            let query_set = self.device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Bitonic Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: query_count,
+            });
+            let resolve_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Bitonic Timestamp Resolve Buffer"),
+                size: query_bytes,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            });
+            let bindings = self.pass_bindings(
+                bind_group_layout,
+                data_buffer.as_entire_buffer_binding(),
+                &passes,
+            );
+
+            spans.time("submit", || {
+                let mut encoder =
+                    self.device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Bitonic Profiled Sort Encoder"),
+                        });
+                Self::record_passes(
+                    &mut encoder,
+                    pipeline,
+                    &bindings,
+                    &passes,
+                    self.variant.workgroup_size,
+                    Some(&query_set),
+                );
+                encoder.resolve_query_set(&query_set, 0..query_count, &resolve_buffer, 0);
+                self.queue.submit(Some(encoder.finish()));
+            });
+            spans.time("wait", || self.device.poll(wgpu::PollType::Wait).ok());
+
+            let download = spans.elapsed();
+            self.read_buffer(&data_buffer, &mut keys).await?;
+            spans.record("download", download);
+            profile.download = spans.last();
+
+            let mut ticks = vec![0u64; query_count as usize];
+            self.read_buffer(&resolve_buffer, &mut ticks).await?;
+            let period = f64::from(self.queue.get_timestamp_period());
+            for (params, pair) in passes.iter().zip(ticks.chunks_exact(2)) {
+                let ticks = pair[1].saturating_sub(pair[0]);
+                profile.passes.push(PassTiming::new(
+                    params,
+                    profile::ticks_to_duration(ticks, period),
+                ));
+            }
+
+            keys.truncate(len);
+            spans.time("finalize_data", || self.finalize_data(&keys, data));
+        }
+        spans.finish(&mut profile);
+        Ok(profile)
+    }
+
+    /// Copy `buffer` into `out` through a mappable staging buffer
+    async fn read_buffer<P: bytemuck::Pod>(
+        &self,
+        buffer: &wgpu::Buffer,
+        out: &mut [P],
+    ) -> Result<()> {
+        let size = std::mem::size_of_val(out) as u64;
+        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Bitonic Staging Buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Bitonic Copy Encoder"),
+            });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        let _ = self.device.poll(wgpu::PollType::Wait);
+        receiver
+            .await
+            .map_err(|e| ChimeraError::Other(format!("Channel error: {e:?}")))?
+            .map_err(|e| ChimeraError::Other(format!("Buffer async error: {e:?}")))?;
+
+        {
+            let view = buffer_slice.get_mapped_range();
+            out.copy_from_slice(bytemuck::cast_slice(&view));
+        }
+
+        staging_buffer.unmap();
+        Ok(())
+    }
+
+    /// List the adapters available for the backends in `config`
+    ///
+    /// The returned indices can be passed to [`RunnerConfig::device_index`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn enumerate_devices(config: &RunnerConfig) -> Vec<DeviceInfo> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: config.backends.into(),
+            ..Default::default()
+        });
+
+        instance
+            .enumerate_adapters(config.backends.into())
+            .iter()
+            .enumerate()
+            .map(|(index, adapter)| Self::device_info(index, &adapter.get_info()))
+            .collect()
+    }
+
+    /// Browsers do not list adapters, so there are none to choose from
+    #[cfg(target_arch = "wasm32")]
+    pub fn enumerate_devices(_config: &RunnerConfig) -> Vec<DeviceInfo> {
+        Vec::new()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn device_info(index: usize, info: &wgpu::AdapterInfo) -> DeviceInfo {
+        DeviceInfo {
+            host: "wgpu",
+            backend: backend_name(info.backend),
+            index,
+            name: info.name.clone(),
+            device_type: info.device_type.into(),
+            driver: Some(info.driver.clone()),
+        }
+    }
+
+    /// Derive runner capabilities from the adapter features and the limits of
+    /// the device we actually created
+    fn query_capabilities(
+        features: wgpu::Features,
+        limits: &wgpu::Limits,
+        is_software: bool,
+        workgroup_size: u32,
+    ) -> Capabilities {
+        let max_by_binding =
+            u64::from(limits.max_storage_buffer_binding_size) / std::mem::size_of::<u32>() as u64;
+        let max_by_dispatch =
+            u64::from(limits.max_compute_workgroups_per_dimension) * u64::from(workgroup_size);
+
+        Capabilities {
+            max_elements: Capabilities::clamp_max_elements(max_by_binding.min(max_by_dispatch)),
+            // Only `u32` kernels are built
+            supports_64bit_keys: false,
+            max_push_constant_size: limits.max_push_constant_size,
+            subgroup_operations: features.contains(wgpu::Features::SUBGROUP),
+            timestamp_queries: features.contains(wgpu::Features::TIMESTAMP_QUERY),
+            is_software,
+        }
+    }
+
+    /// Pipeline sorting in `order`
+    fn pipeline(&self, order: SortOrder) -> Option<&wgpu::ComputePipeline> {
+        self.bitonic_pipelines
+            .as_ref()
+            .map(|pipelines| &pipelines[order as usize])
+    }
+
+    /// Build the pipelines for `variant` from `spirv` with its reflected `layout`
+    ///
+    /// When the module declares the sort-order specialization constant, each
+    /// order gets a pipeline with it overridden; otherwise both share one that
+    /// reads the order from [`BitonicParams`]. Devices without SPIR-V
+    /// passthrough, WebGPU among them, get the module translated to WGSL by
+    /// [`spirv_to_wgsl`] instead.
+    ///
+    /// The workgroup size cannot be overridden the same way: naga's SPIR-V
+    /// frontend rejects `LocalSizeId`, so a width read from the
+    /// [`WORKGROUP_SIZE_SPEC_ID`] constant is frozen to
+    /// `variant.workgroup_size` before wgpu sees the module.
+    fn create_bitonic_pipelines(
+        device: &wgpu::Device,
+        cache: Option<&wgpu::PipelineCache>,
+        spirv: &[u32],
+        variant: &KernelVariant,
+        layout: &BitonicLayout,
+    ) -> Result<([wgpu::ComputePipeline; 2], wgpu::BindGroupLayout)> {
+        let entry_point = variant.entry_point;
+        let spirv: &[u32] = &crate::reflect::freeze_workgroup_size(
+            spirv,
+            &[(WORKGROUP_SIZE_SPEC_ID, variant.workgroup_size)],
+        )?;
+        let spirv_module = device
+            .features()
+            .contains(wgpu::Features::SPIRV_SHADER_PASSTHROUGH)
+            .then(|| unsafe {
+                device.create_shader_module_trusted(
+                    wgpu::ShaderModuleDescriptor {
+                        label: Some("Bitonic Kernel"),
+                        source: wgpu::ShaderSource::SpirV(spirv.into()),
+                    },
+                    wgpu::ShaderRuntimeChecks::unchecked(),
+                )
+            });
+
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = layout
+            .bindings
+            .iter()
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding: binding.binding,
                 visibility: wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: false,
+                    ty: match binding.ty {
+                        BindingType::StorageBuffer { read_only } => {
+                            wgpu::BufferBindingType::Storage { read_only }
+                        }
+                        BindingType::UniformBuffer => wgpu::BufferBindingType::Uniform,
+                    },
+                    // The params of all passes share a buffer, one slot per pass
+                    has_dynamic_offset: Some(binding.binding) == layout.params_binding,
                     min_binding_size: None,
                 },
                 count: None,
-            }],
+            })
+            .collect();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bitonic Bind Group Layout"),
+            entries: &entries,
         });
 
-        // Create pipeline layout
+        let push_constant_ranges = [wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::COMPUTE,
+            range: 0..layout.push_constant_size,
+        }];
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Bitonic Pipeline Layout"),
             bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[wgpu::PushConstantRange {
-                stages: wgpu::ShaderStages::COMPUTE,
-                range: 0..std::mem::size_of::<BitonicParams>() as u32,
-            }],
+            push_constant_ranges: if layout.push_constant_size > 0 {
+                &push_constant_ranges
+            } else {
+                &[]
+            },
         });
 
-        // Get the entry point name from the build script
-        let entry_point = option_env!("BITONIC_KERNEL_SPV_ENTRY").unwrap_or("bitonic_kernel");
-
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Bitonic Pipeline"),
-            layout: Some(&pipeline_layout),
-            module: &shader_module,
-            entry_point: Some(entry_point),
-            compilation_options: Default::default(),
-            cache: None,
-        });
+        let spec_id = SORT_ORDER_SPEC_ID.to_string();
+        let create = |label, order: Option<SortOrder>| -> Result<wgpu::ComputePipeline> {
+            // The WGSL module has the order compiled in, as WGSL output cannot
+            // keep specialization constants
+            let wgsl_module;
+            let (module, entry_point, constants): (_, _, Vec<(&str, f64)>) = match &spirv_module {
+                Some(module) => (
+                    module,
+                    entry_point.to_string(),
+                    order
+                        .map(|order| (spec_id.as_str(), f64::from(u32::from(order))))
+                        .into_iter()
+                        .collect(),
+                ),
+                None => {
+                    let (wgsl, name) = spirv_to_wgsl(spirv, entry_point, order)?;
+                    wgsl_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                        label: Some("Bitonic Kernel (WGSL)"),
+                        source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+                    });
+                    (&wgsl_module, name, Vec::new())
+                }
+            };
+            Ok(
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&pipeline_layout),
+                    module,
+                    entry_point: Some(&entry_point),
+                    compilation_options: wgpu::PipelineCompilationOptions {
+                        constants: &constants,
+                        ..Default::default()
+                    },
+                    cache,
+                }),
+            )
+        };
+        let pipelines = if layout.sort_order_constant {
+            [
+                create("Bitonic Pipeline (ascending)", Some(SortOrder::Ascending))?,
+                create("Bitonic Pipeline (descending)", Some(SortOrder::Descending))?,
+            ]
+        } else {
+            let pipeline = create("Bitonic Pipeline", None)?;
+            [pipeline.clone(), pipeline]
+        };
 
-        (Some(pipeline), Some(bind_group_layout))
+        Ok((pipelines, bind_group_layout))
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
     async fn execute_kernel_pass_async(
         &self,
         data: &mut [u32],
@@ -162,10 +854,6 @@ impl WgpuRunner {
     ) -> Result<()> {
         let size = std::mem::size_of_val(data) as u64;
 
-        // Calculate workgroups to cover all elements
-        let workgroup_size = WORKGROUP_SIZE;
-        let num_workgroups = params.num_elements.div_ceil(workgroup_size);
-
         // Create GPU buffer for in-place sorting
         let data_buffer = self
             .device
@@ -183,15 +871,11 @@ impl WgpuRunner {
             mapped_at_creation: false,
         });
 
-        // Create bind group
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Bitonic Bind Group"),
-            layout: bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: data_buffer.as_entire_binding(),
-            }],
-        });
+        let bindings = self.pass_bindings(
+            bind_group_layout,
+            data_buffer.as_entire_buffer_binding(),
+            std::slice::from_ref(&params),
+        );
 
         // Encode commands for this single pass
         let mut encoder = self
@@ -203,24 +887,14 @@ impl WgpuRunner {
                     params.pass_of_stage.as_u32()
                 )),
             });
-
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some(&format!(
-                    "Bitonic Pass Stage {} Pass {}",
-                    params.stage.as_u32(),
-                    params.pass_of_stage.as_u32()
-                )),
-                timestamp_writes: None,
-            });
-
-            compute_pass.set_pipeline(pipeline);
-            compute_pass.set_bind_group(0, &bind_group, &[]);
-            compute_pass.set_push_constants(0, bytemuck::bytes_of(&params));
-
-            // Dispatch with WORKGROUP_SIZE threads per workgroup (matching kernel)
-            compute_pass.dispatch_workgroups(num_workgroups, 1, 1);
-        }
+        Self::record_passes(
+            &mut encoder,
+            pipeline,
+            &bindings,
+            &[params],
+            self.variant.workgroup_size,
+            None,
+        );
 
         // Submit this pass and wait for completion
         self.queue.submit(Some(encoder.finish()));
@@ -263,6 +937,89 @@ impl WgpuRunner {
     }
 }
 
+/// Translate `entry_point` of `spirv` to WGSL for devices that cannot take
+/// SPIR-V as is, returning the source and the entry point's WGSL name
+///
+/// naga's WGSL backend cannot write specialization constants, so they are
+/// frozen first: the sort order to `order` if given, the rest to their defaults.
+/// It also renames identifiers ending in a digit, hence the returned name.
+fn spirv_to_wgsl(
+    spirv: &[u32],
+    entry_point: &str,
+    order: Option<SortOrder>,
+) -> Result<(String, String)> {
+    let values: Vec<(u32, u32)> = order
+        .map(|order| (SORT_ORDER_SPEC_ID, order.into()))
+        .into_iter()
+        .collect();
+    let spirv = crate::reflect::specialize(spirv, &values)?;
+
+    let error = |err: &dyn std::fmt::Display| {
+        ChimeraError::InvalidKernel(format!("failed to translate the kernel to WGSL: {err}"))
+    };
+    let options = naga::front::spv::Options {
+        adjust_coordinate_space: false,
+        strict_capabilities: true,
+        block_ctx_dump_prefix: None,
+    };
+    let mut module = naga::front::spv::Frontend::new(spirv.into_iter(), &options)
+        .parse()
+        .map_err(|err| error(&err))?;
+    module
+        .entry_points
+        .retain(|entry| entry.name == entry_point);
+    if module.entry_points.is_empty() {
+        return Err(error(&format_args!("no entry point `{entry_point}`")));
+    }
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|err| error(&err.into_inner()))?;
+    let wgsl =
+        naga::back::wgsl::write_string(&module, &info, naga::back::wgsl::WriterFlags::empty())
+            .map_err(|err| error(&err))?;
+
+    // The only `@compute` function left is the entry point
+    let name = wgsl
+        .split_once("@compute")
+        .and_then(|(_, rest)| rest.split_once("fn "))
+        .and_then(|(_, rest)| rest.split_once('('))
+        .map(|(name, _)| name.trim().to_string())
+        .ok_or_else(|| error(&"no compute entry point in the WGSL output"))?;
+    Ok((wgsl, name))
+}
+
+/// Bytes identifying the adapter and driver a pipeline cache was built for
+fn pipeline_cache_identity(info: &wgpu::AdapterInfo) -> Vec<u8> {
+    format!(
+        "{:?}\0{}\0{}\0{}\0{}\0{}",
+        info.backend, info.vendor, info.device, info.name, info.driver, info.driver_info
+    )
+    .into_bytes()
+}
+
+/// The synchronous [`SortRunner`] API would block the browser's thread
+#[cfg(target_arch = "wasm32")]
+fn wasm_blocking_error() -> ChimeraError {
+    ChimeraError::Unsupported(
+        "sorting blocks until the GPU finishes, which wasm32 cannot; use WgpuRunner::sort_async"
+            .to_string(),
+    )
+}
+
+fn backend_name(backend: wgpu::Backend) -> &'static str {
+    match backend {
+        wgpu::Backend::Vulkan => "Vulkan",
+        wgpu::Backend::Metal => "Metal",
+        wgpu::Backend::Dx12 => "DirectX 12",
+        wgpu::Backend::Gl => "OpenGL",
+        wgpu::Backend::BrowserWebGpu => "WebGPU",
+        _ => "Unknown",
+    }
+}
+
 impl SortRunner for WgpuRunner {
     fn backend_info(
         &self,
@@ -274,28 +1031,32 @@ impl SortRunner for WgpuRunner {
     ) {
         (
             "wgpu",
-            Some(self.backend_name.as_str()).map(|s| match s {
-                "Vulkan" => "Vulkan",
-                "Metal" => "Metal",
-                "DirectX 12" => "DirectX 12",
-                "OpenGL" => "OpenGL",
-                "WebGPU" => "WebGPU",
-                _ => "Unknown",
-            }),
+            Some(self.backend_name),
             Some(self.adapter_name.clone()),
             Some(self.driver_info.clone()),
         )
     }
 
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn execute_kernel_pass(&self, _data: &mut [u32], _params: BitonicParams) -> Result<()> {
+        Err(wasm_blocking_error())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
     fn execute_kernel_pass(&self, data: &mut [u32], params: BitonicParams) -> Result<()> {
-        if self.bitonic_pipeline.is_none() || self.bitonic_bind_group_layout.is_none() {
+        let order = SortOrder::try_from(params.sort_order)
+            .map_err(|err| ChimeraError::Other(err.to_string()))?;
+        let (Some(pipeline), Some(bind_group_layout)) =
+            (self.pipeline(order), &self.bitonic_bind_group_layout)
+        else {
             return Err(ChimeraError::Other(
                 "Bitonic pipeline not available".to_string(),
             ));
-        }
-
-        let pipeline = self.bitonic_pipeline.as_ref().unwrap();
-        let bind_group_layout = self.bitonic_bind_group_layout.as_ref().unwrap();
+        };
 
         futures::executor::block_on(self.execute_kernel_pass_async(
             data,
@@ -304,17 +1065,59 @@ impl SortRunner for WgpuRunner {
             bind_group_layout,
         ))
     }
+
+    #[cfg(target_arch = "wasm32")]
+    fn sort_profiled<T: SortableKey + bytemuck::Pod + Send + Sync>(
+        &self,
+        _data: &mut [T],
+        _order: SortOrder,
+    ) -> Result<SortProfile> {
+        Err(wasm_blocking_error())
+    }
+
+    /// Uses timestamp queries when the runner was created with [`RunnerConfig::profiling`]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn sort_profiled<T: SortableKey + bytemuck::Pod + Send + Sync>(
+        &self,
+        data: &mut [T],
+        order: SortOrder,
+    ) -> Result<SortProfile> {
+        if !self
+            .device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+        {
+            return profile::wall_clock_profile(self, data, order);
+        }
+        futures::executor::block_on(self.sort_with_timestamps(data, order))
+    }
+}
+
+impl Drop for WgpuRunner {
+    fn drop(&mut self) {
+        // Failing to persist the cache only costs a recompile next time
+        if let (Some(file), Some(data)) = (
+            &self.pipeline_cache_file,
+            self.pipeline_cache
+                .as_ref()
+                .and_then(|cache| cache.get_data()),
+        ) {
+            let _ = file.store(&data);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::WgpuRunner;
+    use crate::runners::testing;
     use crate::{verify_sorted, SortRunner};
     use shared::SortOrder;
 
     #[test]
     fn test_bitonic_u32() {
-        let runner = futures::executor::block_on(WgpuRunner::new()).unwrap();
+        let Some(runner) = testing::wgpu_runner() else {
+            return;
+        };
         let mut data = vec![42u32, 7, 999, 0, 13, 256, 128, 511];
 
         runner.sort(&mut data, SortOrder::Ascending).unwrap();
@@ -324,7 +1127,9 @@ mod tests {
 
     #[test]
     fn test_bitonic_i32() {
-        let runner = futures::executor::block_on(WgpuRunner::new()).unwrap();
+        let Some(runner) = testing::wgpu_runner() else {
+            return;
+        };
         let mut data = vec![-42i32, 7, -999, 0, 13, -256, 128, -1];
 
         runner.sort(&mut data, SortOrder::Ascending).unwrap();
@@ -334,7 +1139,9 @@ mod tests {
 
     #[test]
     fn test_bitonic_f32() {
-        let runner = futures::executor::block_on(WgpuRunner::new()).unwrap();
+        let Some(runner) = testing::wgpu_runner() else {
+            return;
+        };
         let mut data = vec![3.14f32, -2.71, 0.0, -0.0, 1.41, -99.9, 42.0];
 
         runner.sort(&mut data, SortOrder::Ascending).unwrap();
@@ -343,7 +1150,9 @@ mod tests {
 
     #[test]
     fn test_bitonic_u32_descending() {
-        let runner = futures::executor::block_on(WgpuRunner::new()).unwrap();
+        let Some(runner) = testing::wgpu_runner() else {
+            return;
+        };
         let mut data = vec![42u32, 7, 999, 0, 13, 256, 128, 511];
 
         runner.sort(&mut data, SortOrder::Descending).unwrap();
@@ -353,7 +1162,9 @@ mod tests {
 
     #[test]
     fn test_bitonic_i32_descending() {
-        let runner = futures::executor::block_on(WgpuRunner::new()).unwrap();
+        let Some(runner) = testing::wgpu_runner() else {
+            return;
+        };
         let mut data = vec![-42i32, 7, -999, 0, 13, -256, 128, -1];
 
         runner.sort(&mut data, SortOrder::Descending).unwrap();
@@ -363,10 +1174,203 @@ mod tests {
 
     #[test]
     fn test_bitonic_f32_descending() {
-        let runner = futures::executor::block_on(WgpuRunner::new()).unwrap();
+        let Some(runner) = testing::wgpu_runner() else {
+            return;
+        };
         let mut data = vec![3.14f32, -2.71, 0.0, -0.0, 1.41, -99.9, 42.0];
 
         runner.sort(&mut data, SortOrder::Descending).unwrap();
         assert!(verify_sorted(&data, SortOrder::Descending));
     }
+
+    #[test]
+    fn test_software_adapter() {
+        let Some(runner) = testing::software_wgpu_runner() else {
+            return;
+        };
+        assert!(runner.capabilities().is_software);
+
+        let mut data = vec![42u32, 7, 999, 0, 13, 256, 128, 511, 3, 1000];
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, vec![0, 3, 7, 13, 42, 128, 256, 511, 999, 1000]);
+    }
+
+    #[test]
+    fn test_specialized_workgroup_size() {
+        // naga cannot take LocalSizeId, so the size is frozen into the module
+        let config = crate::RunnerConfig::new().workgroup_size(96);
+        let Some(runner) = testing::wgpu_runner_with(&config) else {
+            return;
+        };
+        assert_eq!(runner.variant.workgroup_size, 96);
+
+        let mut data: Vec<u32> = (0..5000).map(|i| (i * 7919) % 4099).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        runner.sort(&mut data, SortOrder::Descending).unwrap();
+        expected.reverse();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_pipeline_cache_persisted() {
+        let dir = testing::TempDir::new("wgpu-pipeline-cache");
+        let config = crate::RunnerConfig::new().pipeline_cache_dir(dir.path());
+        let Some(runner) = testing::wgpu_runner_with(&config) else {
+            return;
+        };
+        // Only Vulkan adapters support pipeline caches
+        let Some(path) = runner
+            .pipeline_cache_file
+            .as_ref()
+            .map(|file| file.path().to_path_buf())
+        else {
+            return;
+        };
+
+        let mut data = vec![5u32, 3, 9, 1];
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        drop(runner);
+        assert!(path.exists());
+
+        let runner = futures::executor::block_on(super::WgpuRunner::with_config(&config)).unwrap();
+        let mut data = vec![5u32, 3, 9, 1];
+        runner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, vec![1, 3, 5, 9]);
+    }
+
+    #[test]
+    fn test_from_device_sorts_host_buffer() {
+        use wgpu::util::DeviceExt;
+
+        let Some((device, queue)) = testing::wgpu_device(wgpu::Features::PUSH_CONSTANTS) else {
+            return;
+        };
+        let runner = super::WgpuRunner::from_device(device.clone(), queue.clone()).unwrap();
+
+        // Keys start at the first aligned offset past the buffer start
+        let skipped = (device.limits().min_storage_buffer_offset_alignment as usize / 4).max(1);
+        let keys: Vec<u32> = (0..1024u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+        let contents: Vec<u32> = std::iter::repeat_n(7, skipped)
+            .chain(keys.iter().copied())
+            .collect();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&contents),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let start = (skipped * 4) as u64;
+        let mut encoder = device.create_command_encoder(&Default::default());
+        runner
+            .sort_buffer(
+                &mut encoder,
+                &buffer,
+                start..buffer.size(),
+                SortOrder::Ascending,
+            )
+            .unwrap();
+        assert!(runner
+            .sort_buffer(
+                &mut encoder,
+                &buffer,
+                start..start + 4000,
+                SortOrder::Ascending
+            )
+            .is_err());
+        encoder.copy_buffer_to_buffer(&buffer, 0, &readback, 0, buffer.size());
+        queue.submit(Some(encoder.finish()));
+
+        // The host's device outlives the runner
+        drop(runner);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::PollType::Wait).unwrap();
+        let sorted: Vec<u32> =
+            bytemuck::cast_slice(&readback.slice(..).get_mapped_range()).to_vec();
+
+        let mut expected = keys;
+        expected.sort_unstable();
+        assert_eq!(&sorted[..skipped], &vec![7; skipped][..]);
+        assert_eq!(&sorted[skipped..], &expected[..]);
+    }
+
+    #[test]
+    fn test_from_device_without_push_constants() {
+        let Some((device, queue)) = testing::wgpu_device(wgpu::Features::empty()) else {
+            return;
+        };
+        let runner = super::WgpuRunner::from_device(device, queue).unwrap();
+        assert_eq!(
+            runner.variant.params,
+            crate::kernel::ParamsSource::UniformBuffer
+        );
+        assert!(runner.params_binding.is_some());
+
+        // Every pass reads its own slot of the params buffer
+        let mut data: Vec<u32> = (0..300u32).map(|i| i.wrapping_mul(2_654_435_761)).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable_by(|a, b| b.cmp(a));
+        runner.sort(&mut data, SortOrder::Descending).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_spirv_to_wgsl() {
+        let variant = crate::kernel::SPIRV_KERNELS
+            .iter()
+            .find(|variant| variant.params == crate::kernel::ParamsSource::UniformBuffer)
+            .expect("uniform-params kernel variant");
+        let spirv = crate::kernel::parse_spirv(variant.artifact).unwrap();
+
+        for order in [
+            None,
+            Some(SortOrder::Ascending),
+            Some(SortOrder::Descending),
+        ] {
+            let (wgsl, name) = super::spirv_to_wgsl(&spirv, variant.entry_point, order).unwrap();
+            // naga suffixes names ending in a digit
+            assert_eq!(name, format!("{}_", variant.entry_point));
+            assert_eq!(wgsl.matches("@compute").count(), 1, "{wgsl}");
+            assert!(!wgsl.contains("override"), "{wgsl}");
+        }
+        assert!(super::spirv_to_wgsl(&spirv, "missing", None).is_err());
+    }
+
+    #[test]
+    fn test_sort_async() {
+        let Some(runner) = testing::wgpu_runner() else {
+            return;
+        };
+        let mut data: Vec<f32> = (0..500).map(|i| ((i * 37) % 101) as f32 - 50.0).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable_by(|a, b| b.total_cmp(a));
+
+        futures::executor::block_on(runner.sort_async(&mut data, SortOrder::Descending)).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_sort_profiled() {
+        let config = crate::RunnerConfig::new().profiling(true);
+        let Some(runner) = testing::wgpu_runner_with(&config) else {
+            return;
+        };
+        let mut data: Vec<u32> = (0..1000u32).rev().collect();
+
+        let profile = runner
+            .sort_profiled(&mut data, SortOrder::Ascending)
+            .unwrap();
+        assert_eq!(data, (0..1000).collect::<Vec<_>>());
+        // 1024 padded keys take 10 stages of 1..=10 passes
+        assert_eq!(profile.passes.len(), 55);
+        assert_eq!(profile.passes.last().map(|pass| pass.stage), Some(9));
+    }
 }
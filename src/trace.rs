@@ -0,0 +1,428 @@
+//! Chrome Trace Event export of sort profiles
+//!
+//! [`ChromeTrace`] turns [`SortProfile`]s into the JSON trace format read by
+//! `chrome://tracing` and the Perfetto UI. Each sort becomes a span on the
+//! host track containing its host steps; device timings go on a separate
+//! track, starting where the host handed work to the device.
+
+use crate::{error::Result, profile::SortProfile, profile::TimingSource};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+/// Process id used for every event
+const PID: u32 = 1;
+
+/// Track of a trace event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Track {
+    Host,
+    Device,
+}
+
+impl Track {
+    fn tid(self) -> u32 {
+        match self {
+            Track::Host => 1,
+            Track::Device => 2,
+        }
+    }
+}
+
+/// A complete (`"ph": "X"`) trace event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub name: String,
+    pub track: Track,
+    pub start: Duration,
+    pub duration: Duration,
+    /// `(stage, pass_of_stage)` for events of a bitonic pass
+    pub pass: Option<(u32, u32)>,
+    /// How a device event was timed; `None` on the host track
+    pub source: Option<TimingSource>,
+}
+
+/// Sort profiles laid out one after another on a common timeline
+#[derive(Clone, Debug, Default)]
+pub struct ChromeTrace {
+    events: Vec<TraceEvent>,
+    end: Duration,
+}
+
+impl ChromeTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a sort called `label`, starting where the previous one ended
+    pub fn add_sort(&mut self, label: &str, profile: &SortProfile) -> &mut Self {
+        let origin = self.end;
+        let source = profile.source;
+        let mut push = |name: String, track, start, duration, pass| {
+            self.events.push(TraceEvent {
+                name,
+                track,
+                start: origin + start,
+                duration,
+                pass,
+                source: (track == Track::Device).then_some(source),
+            })
+        };
+
+        push(
+            label.to_string(),
+            Track::Host,
+            Duration::ZERO,
+            profile.wall_time,
+            None,
+        );
+        for span in &profile.host_spans {
+            push(
+                span.name.to_string(),
+                Track::Host,
+                span.start,
+                span.duration,
+                span.pass,
+            );
+        }
+
+        // Wall-clock profiles time the passes on the host, already covered by the host spans
+        if profile.source != TimingSource::WallClock {
+            let mut at = profile.device_start;
+            push(
+                "upload".to_string(),
+                Track::Device,
+                at,
+                profile.upload,
+                None,
+            );
+            at += profile.upload;
+            for pass in &profile.passes {
+                push(
+                    format!("stage {} pass {}", pass.stage, pass.pass_of_stage),
+                    Track::Device,
+                    at,
+                    pass.duration,
+                    Some((pass.stage, pass.pass_of_stage)),
+                );
+                at += pass.duration;
+            }
+            push(
+                "download".to_string(),
+                Track::Device,
+                at,
+                profile.download,
+                None,
+            );
+        }
+
+        self.end = origin + profile.wall_time;
+        self
+    }
+
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// Serialize as a Chrome Trace Event JSON object
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+
+        // Each device event names its own source; the track does too if they all agree
+        let mut tracks = vec![(Track::Host, "host".to_string())];
+        let mut sources = self.events.iter().filter_map(|event| event.source);
+        if let Some(first) = sources.next() {
+            let name = if sources.all(|source| source == first) {
+                format!("device ({first})")
+            } else {
+                "device".to_string()
+            };
+            tracks.push((Track::Device, name));
+        }
+        for (i, (track, name)) in tracks.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{PID},\"tid\":{},\"args\":{{\"name\":{}}}}}",
+                track.tid(),
+                json_string(name)
+            );
+        }
+
+        for event in &self.events {
+            let _ = write!(
+                json,
+                ",{{\"name\":{},\"cat\":\"{}\",\"ph\":\"X\",\"pid\":{PID},\"tid\":{},\"ts\":{},\"dur\":{}",
+                json_string(&event.name),
+                match event.track {
+                    Track::Host => "host",
+                    Track::Device => "device",
+                },
+                event.track.tid(),
+                micros(event.start),
+                micros(event.duration)
+            );
+            let mut args = Vec::new();
+            if let Some((stage, pass)) = event.pass {
+                args.push(format!("\"stage\":{stage},\"pass_of_stage\":{pass}"));
+            }
+            if let Some(source) = event.source {
+                args.push(format!("\"source\":{}", json_string(&source.to_string())));
+            }
+            if !args.is_empty() {
+                let _ = write!(json, ",\"args\":{{{}}}", args.join(","));
+            }
+            json.push('}');
+        }
+
+        json.push_str("],\"displayTimeUnit\":\"ms\"}");
+        json
+    }
+
+    /// Write the trace to `path`, ready to load in `chrome://tracing` or Perfetto
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_json())?;
+        Ok(())
+    }
+}
+
+/// Trace timestamps are in microseconds
+fn micros(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1e6)
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_string, ChromeTrace, Track};
+    use crate::{CpuRunner, SortRunner};
+    use shared::SortOrder;
+    use std::collections::BTreeMap;
+
+    /// Just enough JSON to check the structure of a trace
+    #[derive(Debug, PartialEq)]
+    enum Json {
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(BTreeMap<String, Json>),
+    }
+
+    impl Json {
+        fn parse(text: &str) -> Json {
+            let mut chars = text.chars().peekable();
+            let value = Self::value(&mut chars);
+            assert_eq!(chars.next(), None, "trailing characters");
+            value
+        }
+
+        fn value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Json {
+            match chars.peek().copied() {
+                Some('{') => {
+                    chars.next();
+                    let mut object = BTreeMap::new();
+                    while chars.peek() != Some(&'}') {
+                        let Json::String(key) = Self::value(chars) else {
+                            panic!("object key is not a string");
+                        };
+                        assert_eq!(chars.next(), Some(':'));
+                        object.insert(key, Self::value(chars));
+                        if chars.peek() == Some(&',') {
+                            chars.next();
+                        }
+                    }
+                    chars.next();
+                    Json::Object(object)
+                }
+                Some('[') => {
+                    chars.next();
+                    let mut array = Vec::new();
+                    while chars.peek() != Some(&']') {
+                        array.push(Self::value(chars));
+                        if chars.peek() == Some(&',') {
+                            chars.next();
+                        }
+                    }
+                    chars.next();
+                    Json::Array(array)
+                }
+                Some('"') => {
+                    chars.next();
+                    let mut string = String::new();
+                    loop {
+                        match chars.next().expect("unterminated string") {
+                            '"' => break,
+                            '\\' => match chars.next().unwrap() {
+                                'n' => string.push('\n'),
+                                'u' => {
+                                    let hex: String = chars.by_ref().take(4).collect();
+                                    let code = u32::from_str_radix(&hex, 16).unwrap();
+                                    string.push(char::from_u32(code).unwrap());
+                                }
+                                c => string.push(c),
+                            },
+                            c => string.push(c),
+                        }
+                    }
+                    Json::String(string)
+                }
+                _ => {
+                    let mut number = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_ascii_digit() || matches!(c, '-' | '.' | 'e' | 'E' | '+')) {
+                            break;
+                        }
+                        number.push(c);
+                        chars.next();
+                    }
+                    Json::Number(number.parse().expect("invalid number"))
+                }
+            }
+        }
+
+        fn get(&self, key: &str) -> &Json {
+            match self {
+                Json::Object(object) => &object[key],
+                _ => panic!("not an object"),
+            }
+        }
+
+        fn str(&self) -> &str {
+            match self {
+                Json::String(string) => string,
+                _ => panic!("not a string"),
+            }
+        }
+
+        fn num(&self) -> f64 {
+            match self {
+                Json::Number(number) => *number,
+                _ => panic!("not a number"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_cpu_runner_trace() {
+        let mut data = vec![42u32, 7, 999, 0, 13, 256, 128, 511, 3];
+        let profile = CpuRunner
+            .sort_profiled(&mut data, SortOrder::Ascending)
+            .unwrap();
+
+        let mut trace = ChromeTrace::new();
+        trace
+            .add_sort("sort 1", &profile)
+            .add_sort("sort \"2\"", &profile);
+        let json = Json::parse(&trace.to_json());
+
+        let Json::Array(events) = json.get("traceEvents") else {
+            panic!("traceEvents is not an array");
+        };
+        let (metadata, spans): (Vec<&Json>, Vec<&Json>) = events
+            .iter()
+            .partition(|event| event.get("ph").str() == "M");
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata[0].get("args").get("name").str(), "host");
+
+        // 16 padded keys: 4 stages, 10 passes, plus the sort and 3 other steps
+        assert_eq!(spans.len(), 2 * 14);
+        for span in &spans {
+            assert_eq!(span.get("ph").str(), "X");
+            assert_eq!(span.get("tid").num(), 1.0);
+            assert!(span.get("ts").num() >= 0.0 && span.get("dur").num() >= 0.0);
+        }
+        let names: Vec<&str> = spans.iter().map(|span| span.get("name").str()).collect();
+        assert_eq!(names[..3], ["sort 1", "prepare_data", "pad_data"]);
+        assert_eq!(names[13], "finalize_data");
+        assert_eq!(names[14], "sort \"2\"");
+
+        let passes: Vec<(f64, f64)> = spans
+            .iter()
+            .filter(|span| span.get("name").str() == "execute_kernel_pass")
+            .map(|span| {
+                let args = span.get("args");
+                (args.get("stage").num(), args.get("pass_of_stage").num())
+            })
+            .take(10)
+            .collect();
+        assert_eq!(passes[..3], [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]);
+        assert_eq!(passes[9], (3.0, 3.0));
+
+        // The second sort starts once the first has ended, and contains its steps
+        let start = |i: usize| spans[i].get("ts").num();
+        let end = |i: usize| start(i) + spans[i].get("dur").num();
+        assert!(start(14) >= end(0) - 0.001);
+        assert!((15..28).all(|i| start(i) >= start(14) && end(i) <= end(14) + 0.001));
+    }
+
+    #[test]
+    fn test_device_track() {
+        let mut profile = crate::SortProfile::new(crate::profile::TimingSource::GpuTimestamps);
+        profile.upload = std::time::Duration::from_micros(5);
+        profile.download = std::time::Duration::from_micros(7);
+        profile.wall_time = std::time::Duration::from_micros(100);
+        profile.device_start = std::time::Duration::from_micros(10);
+
+        let mut trace = ChromeTrace::new();
+        trace.add_sort("sort", &profile);
+        let device: Vec<_> = trace
+            .events()
+            .iter()
+            .filter(|event| event.track == Track::Device)
+            .map(|event| (event.name.as_str(), event.start.as_micros()))
+            .collect();
+        assert_eq!(device, [("upload", 10), ("download", 15)]);
+        assert!(trace.to_json().contains("\"device (GPU timestamps)\""));
+
+        // Mixed sources leave the track unlabelled and label each event instead
+        profile.source = crate::profile::TimingSource::CudaEvents;
+        trace.add_sort("sort", &profile);
+        let json = Json::parse(&trace.to_json());
+        let Json::Array(events) = json.get("traceEvents") else {
+            panic!("traceEvents is not an array");
+        };
+        assert_eq!(events[1].get("args").get("name").str(), "device");
+        let sources: Vec<&str> = events
+            .iter()
+            .filter(|event| event.get("ph").str() == "X" && event.get("tid").num() == 2.0)
+            .map(|event| event.get("args").get("source").str())
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                "GPU timestamps",
+                "GPU timestamps",
+                "CUDA events",
+                "CUDA events"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+        assert_eq!(
+            Json::parse(&json_string("a\"b\\c\n\u{1}")),
+            Json::String("a\"b\\c\n\u{1}".to_string())
+        );
+    }
+}
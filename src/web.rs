@@ -0,0 +1,106 @@
+//! Browser entry points exported through wasm-bindgen
+//!
+//! Built with `wasm-pack build --target web -- --features web`; `web/index.html`
+//! loads the package and calls [`sort_keys`] as `sortKeys`.
+
+use crate::{runners::CpuRunner, SortRunner, WgpuRunner};
+use shared::SortOrder;
+use wasm_bindgen::prelude::*;
+
+/// Keys sorted by [`sort_keys`] and the backend that sorted them
+#[wasm_bindgen]
+pub struct SortedKeys {
+    keys: Vec<u32>,
+    backend: String,
+    fallback_reason: Option<String>,
+}
+
+#[wasm_bindgen]
+impl SortedKeys {
+    /// The sorted keys
+    #[wasm_bindgen(getter)]
+    pub fn keys(&self) -> Vec<u32> {
+        self.keys.clone()
+    }
+
+    /// Backend that sorted the keys, `"cpu"` if WebGPU was unavailable
+    #[wasm_bindgen(getter)]
+    pub fn backend(&self) -> String {
+        self.backend.clone()
+    }
+
+    /// Why the sort fell back to the CPU, if it did
+    #[wasm_bindgen(getter, js_name = fallbackReason)]
+    pub fn fallback_reason(&self) -> Option<String> {
+        self.fallback_reason.clone()
+    }
+}
+
+/// Sort `keys` with WebGPU, or on the CPU where the browser has no WebGPU
+#[wasm_bindgen(js_name = sortKeys)]
+pub async fn sort_keys(mut keys: Vec<u32>, descending: bool) -> Result<SortedKeys, JsError> {
+    let order = if descending {
+        SortOrder::Descending
+    } else {
+        SortOrder::Ascending
+    };
+
+    let gpu = match WgpuRunner::new().await {
+        Ok(runner) => match runner.sort_async(&mut keys, order).await {
+            Ok(()) => Ok(runner.backend_info().1.unwrap_or("wgpu").to_string()),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    let (backend, fallback_reason) = match gpu {
+        Ok(backend) => (backend, None),
+        Err(e) => {
+            CpuRunner
+                .sort(&mut keys, order)
+                .map_err(|e| JsError::new(&e.to_string()))?;
+            ("cpu".to_string(), Some(e.to_string()))
+        }
+    };
+
+    Ok(SortedKeys {
+        keys,
+        backend,
+        fallback_reason,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sort_keys;
+    use crate::{runners::CpuRunner, verify_sorted, SortRunner};
+    use shared::SortOrder;
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_cpu_runner() {
+        let mut data = vec![3.5f32, -1.0, 0.0, 42.0, -7.25];
+
+        CpuRunner.sort(&mut data, SortOrder::Ascending).unwrap();
+        assert_eq!(data, vec![-7.25, -1.0, 0.0, 3.5, 42.0]);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_sort_keys() {
+        let keys: Vec<u32> = (0..1000u32)
+            .map(|i| i.wrapping_mul(2_654_435_761))
+            .collect();
+
+        let Ok(sorted) = sort_keys(keys, true).await else {
+            panic!("sortKeys failed");
+        };
+        // Headless browsers usually lack WebGPU, which exercises the CPU fallback
+        assert_eq!(
+            sorted.backend() == "cpu",
+            sorted.fallback_reason().is_some()
+        );
+        assert_eq!(sorted.keys().len(), 1000);
+        assert!(verify_sorted(&sorted.keys(), SortOrder::Descending));
+    }
+}